@@ -12,18 +12,20 @@ const SearchResult = ({document}) => {
         <div className={styles.SearchResultContainer}>
             <h1 style={{fontSize:'0.7rem', fontWeight: 'bold', color:'whitesmoke'}}> {document.type === 'meta' ? document.engine: 'Local'} </h1>
             <h1 style={{fontSize:'0.7rem', fontWeight:'200', color:'gray'}}>
-               {document.url}</h1>
+               {document.link || document.url}</h1>
             <h1 style={{fontSize:'1.2rem', fontWeight:'500', color: 'aqua'}}>
                 {document.title}</h1>
             <div style={{display:'flex', flexDirection:'row'}}>
                              
-        {document.images && (
+        {document.images && document.images.length > 0 && (
             <>
                 <p>Images:</p>
                 {document.images.map((image, index) => (
                     <div key={index} style={{ width: '200px', height: '200px', position: 'relative', overflow: 'hidden' }}>
                         <img 
-                            src={image} 
+                            src={image.url} 
+                            alt={image.alt} 
+                            title={image.caption || image.title} 
                             style={{ 
                                 display: 'block', 
                                 maxWidth: '100%', 
@@ -198,9 +198,11 @@ const Home = () => {
                         } else if ('Search' in item) {
                             indexed = item.Search.indexed;
                             ranked = item.Search.results.length;
-                            for (const result of item.Search.results) {
+                            item.Search.results.forEach((result, index) => {
                                 result.type = 'local';
-                            }
+                                // Deep link into the matching page of PDFs, the URL otherwise.
+                                result.link = item.Search.links[index] || result.url;
+                            });
                             searchResults.push(...item.Search.results);
                         }
                     }
@@ -343,7 +345,7 @@ const Home = () => {
             {!(loadingResults) ? (
                 searchResults.map((document, index) => (
                     <div key={index} style={{display: 'flex', flexDirection: 'column', alignItems: 'center', justifyContent: 'center'}}>
-                        <div onClick={() => window.open(document.url, '_blank')} key={index}>
+                        <div onClick={() => window.open(document.link || document.url, '_blank')} key={index}>
                             <SearchResult document={document} />
                         </div>
                         <div>
@@ -0,0 +1,359 @@
+// Module analyses text into index terms - a tokeniser splits it into words, and a chain
+// of filters then case folds them, marks stop words, stems and folds accents. The same
+// analyser is applied to documents as they are indexed and to queries, so their terms
+// line up. The default settings are read from the analysis table of the Rocket config
+// (Rocket.toml or ROCKET_ANALYSIS) when the server starts, and each collection (see
+// collection.rs) may override them for its own indices.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use rust_stemmers::{Algorithm, Stemmer};
+use serde::{Deserialize, Serialize};
+use unicode_normalization::char::{decompose_canonical, is_combining_mark};
+use unicode_segmentation::UnicodeSegmentation;
+use crate::language::{is_unsegmented, stop_words};
+
+
+// Languages with built-in stop words, overridden by those of the config.
+const STOP_WORD_LANGUAGES: [&str; 7] = ["en", "fr", "de", "es", "it", "pt", "nl"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct AnalysisConfig {
+    // Reduce words to their stems (Snowball, in the language of the text).
+    pub stemming: bool,
+    // Strip accents from Latin, Greek and Cyrillic letters, i.e. café to cafe.
+    pub fold_accents: bool,
+    // ISO 639-1 code of the language queries, and documents of unknown language, are
+    // analysed in.
+    pub default_language: String,
+    // Stop words by language, replacing the built-in list of each language given.
+    pub stop_words: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for AnalysisConfig {
+    fn default() -> Self {
+        AnalysisConfig {
+            stemming: true,
+            fold_accents: true,
+            default_language: String::from("en"),
+            stop_words: BTreeMap::new(),
+        }
+    }
+}
+
+// A word of the text and its position, counted over every word (stop words included)
+// so that phrases line up with the original text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub text: String,
+    pub position: u32,
+    // Stop words are kept in the chain to count positions, and left out of the index.
+    pub stop: bool,
+}
+
+pub trait Tokeniser: Send + Sync {
+    fn tokenise(&self, text: &str) -> Vec<Token>;
+}
+
+// Step of the chain, given the tokens and the language (ISO 639-1 code, None where
+// unknown) of the text they come from.
+pub trait TokenFilter: Send + Sync {
+    fn apply(&self, tokens: Vec<Token>, language: Option<&str>) -> Vec<Token>;
+}
+
+// Splits text at Unicode word boundaries (UAX #29), keeping words with digits whole
+// (CS101, 3.14). Words joined by hyphens where one of them holds a digit are kept as
+// one (COVID-19, x86-64, 2nd-order), as are the trailing + and # of names like C++
+// and C#. Runs of scripts written without spaces are split into overlapping bigrams.
+pub struct UnicodeTokeniser;
+
+fn is_word(segment: &str) -> bool {
+    segment.chars().any(char::is_alphanumeric)
+}
+
+fn has_digit(segment: &str) -> bool {
+    segment.chars().any(char::is_numeric)
+}
+
+impl UnicodeTokeniser {
+    // Bigrams of a run of unsegmented script (a lone character stays a unigram), so
+    // queries match without a dictionary-based word segmenter.
+    fn flush_run(run: &mut Vec<char>, tokens: &mut Vec<Token>, position: &mut u32) {
+        let words: Vec<String> = match run.len() {
+            0 => Vec::new(),
+            1 => vec![run[0].to_string()],
+            _ => run.windows(2).map(|pair| pair.iter().collect()).collect(),
+        };
+        for text in words {
+            tokens.push(Token { text, position: *position, stop: false });
+            *position += 1;
+        }
+        run.clear();
+    }
+}
+
+impl Tokeniser for UnicodeTokeniser {
+    fn tokenise(&self, text: &str) -> Vec<Token> {
+        let segments: Vec<&str> = text.split_word_bounds().collect();
+        let mut tokens: Vec<Token> = Vec::new();
+        let mut run: Vec<char> = Vec::new();
+        let mut position: u32 = 0;
+
+        let mut i = 0;
+        while i < segments.len() {
+            let segment = segments[i];
+            i += 1;
+            if segment.chars().all(is_unsegmented) {
+                run.extend(segment.chars());
+                continue;
+            }
+            Self::flush_run(&mut run, &mut tokens, &mut position);
+            if !is_word(segment) {
+                continue;
+            }
+
+            let mut word = segment.to_string();
+            let mut numeric = has_digit(segment);
+            while i + 1 < segments.len() && segments[i] == "-" && is_word(segments[i + 1]) && (numeric || has_digit(segments[i + 1])) {
+                word.push('-');
+                word.push_str(segments[i + 1]);
+                numeric = true;
+                i += 2;
+            }
+            while i < segments.len() && matches!(segments[i], "+" | "#") && !segments.get(i + 1).is_some_and(|next| is_word(next)) {
+                word.push_str(segments[i]);
+                i += 1;
+            }
+
+            tokens.push(Token { text: word, position, stop: false });
+            position += 1;
+        }
+        Self::flush_run(&mut run, &mut tokens, &mut position);
+
+        tokens
+    }
+}
+
+pub struct LowercaseFilter;
+
+impl TokenFilter for LowercaseFilter {
+    fn apply(&self, mut tokens: Vec<Token>, _language: Option<&str>) -> Vec<Token> {
+        for token in tokens.iter_mut() {
+            token.text = token.text.to_lowercase();
+        }
+        tokens
+    }
+}
+
+// Stop words of each language. Lists hold both the words and their accent folded
+// forms, so they are recognised before and after folding.
+pub struct StopWords {
+    lists: HashMap<String, HashSet<String>>,
+    default_language: String,
+}
+
+impl StopWords {
+    fn new(config: &AnalysisConfig) -> Self {
+        let mut lists: HashMap<String, Vec<String>> = STOP_WORD_LANGUAGES.iter()
+            .map(|language| (language.to_string(), stop_words(Some(language)).iter().map(|word| word.to_string()).collect()))
+            .collect();
+        lists.extend(config.stop_words.clone());
+
+        let lists = lists.into_iter()
+            .map(|(language, words)| {
+                let words = words.iter()
+                    .map(|word| word.to_lowercase())
+                    .flat_map(|word| [fold_accents(&word), word])
+                    .collect();
+                (language.to_lowercase(), words)
+            })
+            .collect();
+        StopWords { lists, default_language: config.default_language.clone() }
+    }
+
+    // Whether the term is a stop word of the language, the default language where it
+    // is unknown or has no list.
+    pub fn contains(&self, term: &str, language: Option<&str>) -> bool {
+        language.and_then(|language| self.lists.get(language))
+            .or_else(|| self.lists.get(&self.default_language))
+            .is_some_and(|words| words.contains(term))
+    }
+}
+
+pub struct StopWordFilter(Arc<StopWords>);
+
+impl TokenFilter for StopWordFilter {
+    fn apply(&self, mut tokens: Vec<Token>, language: Option<&str>) -> Vec<Token> {
+        for token in tokens.iter_mut() {
+            token.stop = self.0.contains(&token.text, language);
+        }
+        tokens
+    }
+}
+
+// Snowball stemming in the language of the text. Stop words, identifiers (words with
+// digits or symbols) and languages without a stemmer are left as they are.
+pub struct StemFilter {
+    default_language: String,
+}
+
+fn stemmer_algorithm(language: &str) -> Option<Algorithm> {
+    Some(match language {
+        "ar" => Algorithm::Arabic,
+        "da" => Algorithm::Danish,
+        "nl" => Algorithm::Dutch,
+        "en" => Algorithm::English,
+        "fi" => Algorithm::Finnish,
+        "fr" => Algorithm::French,
+        "de" => Algorithm::German,
+        "el" => Algorithm::Greek,
+        "hu" => Algorithm::Hungarian,
+        "it" => Algorithm::Italian,
+        "no" | "nb" => Algorithm::Norwegian,
+        "pt" => Algorithm::Portuguese,
+        "ro" => Algorithm::Romanian,
+        "ru" => Algorithm::Russian,
+        "es" => Algorithm::Spanish,
+        "sv" => Algorithm::Swedish,
+        "ta" => Algorithm::Tamil,
+        "tr" => Algorithm::Turkish,
+        _ => return None,
+    })
+}
+
+impl TokenFilter for StemFilter {
+    fn apply(&self, mut tokens: Vec<Token>, language: Option<&str>) -> Vec<Token> {
+        let Some(algorithm) = stemmer_algorithm(language.unwrap_or(&self.default_language)) else { return tokens };
+        let stemmer = Stemmer::create(algorithm);
+        for token in tokens.iter_mut().filter(|token| !token.stop && token.text.chars().all(char::is_alphabetic)) {
+            token.text = stemmer.stem(&token.text).into_owned();
+        }
+        tokens
+    }
+}
+
+// Strips the accents of Latin, Greek and Cyrillic letters. Other scripts are left
+// alone, as their combining marks (i.e. Thai vowels, kana voicing) change the word.
+fn fold_accents(word: &str) -> String {
+    let mut folded = String::with_capacity(word.len());
+    for c in word.chars() {
+        if matches!(c, '\u{0000}'..='\u{024F}' | '\u{0370}'..='\u{04FF}' | '\u{1E00}'..='\u{1FFF}') {
+            decompose_canonical(c, |part| if !is_combining_mark(part) { folded.push(part) });
+        } else {
+            folded.push(c);
+        }
+    }
+    folded
+}
+
+pub struct AccentFoldFilter;
+
+impl TokenFilter for AccentFoldFilter {
+    fn apply(&self, mut tokens: Vec<Token>, _language: Option<&str>) -> Vec<Token> {
+        for token in tokens.iter_mut() {
+            token.text = fold_accents(&token.text);
+        }
+        tokens
+    }
+}
+
+pub struct Analyser {
+    // Settings the chain was built from, recorded in each index file.
+    config: AnalysisConfig,
+    tokeniser: Box<dyn Tokeniser>,
+    filters: Vec<Box<dyn TokenFilter>>,
+    stop_words: Arc<StopWords>,
+}
+
+impl Analyser {
+    // The default chain - Unicode words, lower case, stop words, then stemming and
+    // accent folding where the config enables them. Stemming comes before folding, as
+    // the stemmers expect accented text.
+    pub fn from_config(config: &AnalysisConfig) -> Self {
+        let stop_words = Arc::new(StopWords::new(config));
+        let mut filters: Vec<Box<dyn TokenFilter>> = vec![Box::new(LowercaseFilter), Box::new(StopWordFilter(stop_words.clone()))];
+        if config.stemming {
+            filters.push(Box::new(StemFilter { default_language: config.default_language.clone() }));
+        }
+        if config.fold_accents {
+            filters.push(Box::new(AccentFoldFilter));
+        }
+        Analyser { config: config.clone(), tokeniser: Box::new(UnicodeTokeniser), filters, stop_words }
+    }
+
+    pub fn config(&self) -> &AnalysisConfig {
+        &self.config
+    }
+
+    fn tokens(&self, text: &str, language: Option<&str>) -> Vec<Token> {
+        self.filters.iter().fold(self.tokeniser.tokenise(text), |tokens, filter| filter.apply(tokens, language))
+    }
+
+    // Index terms of text in the language, with their positions. Stop words are left
+    // out, but still counted in the positions of the words after them.
+    pub fn analyse(&self, text: &str, language: Option<&str>) -> Vec<Token> {
+        self.tokens(text, language).into_iter().filter(|token| !token.stop).collect()
+    }
+
+    // Query terms in order, analysed in the language of the query (the default language
+    // where not given) and keeping stop words - terms missing from the index score
+    // nothing anyway.
+    pub fn analyse_query(&self, query: &str, language: Option<&str>) -> Vec<String> {
+        self.tokens(query, language).into_iter().map(|token| token.text).collect()
+    }
+
+    // Whether the (analysed) term is a stop word of the language, and so not indexed.
+    pub fn is_stop_word(&self, term: &str, language: Option<&str>) -> bool {
+        self.stop_words.contains(term, language)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(analyser: &Analyser, text: &str) -> Vec<(u32, String)> {
+        analyser.analyse(text, Some("en")).into_iter().map(|token| (token.position, token.text)).collect()
+    }
+
+    #[test]
+    fn keeps_identifiers_whole() {
+        let analyser = Analyser::from_config(&AnalysisConfig::default());
+        assert_eq!(analyser.analyse_query("C++ C# CS101 COVID-19 3.14", None), vec!["c++", "c#", "cs101", "covid-19", "3.14"]);
+        assert_eq!(analyser.analyse_query("O(n log n)", None), vec!["o", "n", "log", "n"]);
+    }
+
+    #[test]
+    fn drops_stop_words_but_counts_their_positions() {
+        let analyser = Analyser::from_config(&AnalysisConfig::default());
+        assert_eq!(terms(&analyser, "the theory of relativity"), vec![(1, String::from("theori")), (3, String::from("relat"))]);
+        assert!(analyser.is_stop_word("the", None));
+        assert!(!analyser.is_stop_word("theori", None));
+    }
+
+    #[test]
+    fn stems_in_the_language_of_the_text() {
+        let analyser = Analyser::from_config(&AnalysisConfig::default());
+        assert_eq!(analyser.analyse("running computers", Some("en")).into_iter().map(|token| token.text).collect::<Vec<String>>(), vec!["run", "comput"]);
+        assert_eq!(analyser.analyse("ordinateurs", Some("fr"))[0].text, "ordin");
+
+        let unstemmed = Analyser::from_config(&AnalysisConfig { stemming: false, ..Default::default() });
+        assert_eq!(terms(&unstemmed, "running computers"), vec![(0, String::from("running")), (1, String::from("computers"))]);
+    }
+
+    #[test]
+    fn folds_accents_where_enabled() {
+        let config = AnalysisConfig { stemming: false, ..Default::default() };
+        assert_eq!(Analyser::from_config(&config).analyse_query("Café Über", None), vec!["cafe", "uber"]);
+
+        let config = AnalysisConfig { fold_accents: false, ..config };
+        assert_eq!(Analyser::from_config(&config).analyse_query("Café Über", None), vec!["café", "über"]);
+    }
+
+    #[test]
+    fn splits_unsegmented_scripts_into_bigrams() {
+        let analyser = Analyser::from_config(&AnalysisConfig::default());
+        assert_eq!(terms(&analyser, "北京大学"), vec![(0, String::from("北京")), (1, String::from("京大")), (2, String::from("大学"))]);
+    }
+}
@@ -0,0 +1,114 @@
+// Module keeps the link graph of the crawl - every link with its anchor text - and
+// gathers the text other pages use to link to each document. Inbound anchor text
+// often names a page better than the page does itself (i.e. "MIT OCW Linear Algebra
+// notes"), so it is stored on the document as a field of its own and scored by BM25.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use url::Url;
+use crate::links::{registered_domain, LinkEdge};
+use crate::parser::Document;
+use crate::segment::write_file;
+
+
+// File the link graph is kept in, under the directory of the indices it was crawled for.
+const LINK_GRAPH_FILE: &str = "links.json";
+
+// Anchor texts kept per document.
+const MAX_ANCHORS: usize = 100;
+// Anchors that say nothing about the page they lead to.
+const GENERIC_ANCHORS: [&str; 20] = ["click here", "here", "this", "link", "this link", "more", "read more", "more...", "learn more",
+    "continue", "continue reading", "next", "previous", "prev", "back", "home", "top", "back to top", "download", "pdf"];
+
+// Outgoing links of each crawled page, keyed by the page's URL.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct LinkGraph {
+    edges: HashMap<String, Vec<LinkEdge>>,
+}
+
+impl LinkGraph {
+    pub fn read(dir: &str) -> Result<Self, String> {
+        let file = File::open(Path::new(dir).join(LINK_GRAPH_FILE)).map_err(|e| e.to_string())?;
+        bincode::deserialize_from(file).map_err(|e| e.to_string())
+    }
+
+    pub fn write(&self, dir: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if !Path::new(dir).exists() {
+            fs::create_dir_all(dir)?;
+        }
+
+        write_file(&format!("{}/{}", dir, LINK_GRAPH_FILE), &[&bincode::serialize(self)?])?;
+        Ok(())
+    }
+
+    // Replaces the outgoing links of a page with those of its latest fetch.
+    pub fn set_links(&mut self, source: &str, edges: Vec<LinkEdge>) {
+        self.edges.insert(source.to_string(), edges);
+    }
+
+    pub fn remove(&mut self, source: &str) {
+        self.edges.remove(source);
+    }
+
+    // Anchor texts of the links to each target, most common first. Links a page
+    // does not vouch for (nofollow etc.), links to itself and generic anchors
+    // ("click here") are left out, and each site counts a given anchor once -
+    // otherwise site-wide navigation repeated on every page would drown the rest.
+    pub fn inbound_anchors(&self) -> HashMap<String, Vec<String>> {
+        let mut seen: HashSet<(String, String, String)> = HashSet::new();
+        let mut counts: HashMap<String, HashMap<String, usize>> = HashMap::new();
+
+        for edge in self.edges.values().flatten() {
+            let anchor = edge.anchor.trim();
+            if anchor.is_empty() || edge.source == edge.target || !edge.is_endorsed() || GENERIC_ANCHORS.contains(&anchor.to_lowercase().as_str()) {
+                continue;
+            }
+
+            let site = Url::parse(&edge.source).ok()
+                .and_then(|source| registered_domain(&source))
+                .unwrap_or_else(|| edge.source.clone());
+            if !seen.insert((site, edge.target.clone(), anchor.to_lowercase())) {
+                continue;
+            }
+
+            *counts.entry(edge.target.clone()).or_default().entry(anchor.to_string()).or_insert(0) += 1;
+        }
+
+        counts.into_iter()
+            .map(|(target, anchors)| {
+                let mut anchors: Vec<(String, usize)> = anchors.into_iter().collect();
+                anchors.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+                // Repeated anchors are kept once per site using them, so they weigh more.
+                let anchors = anchors.into_iter()
+                    .flat_map(|(anchor, count)| std::iter::repeat_n(anchor, count))
+                    .take(MAX_ANCHORS)
+                    .collect();
+                (target, anchors)
+            })
+            .collect()
+    }
+}
+
+// Sets each document's inbound anchor text from the graph - links to its near-duplicates
+// included - returning the URLs of documents whose anchor text changed.
+pub fn attach_anchor_text(documents: &mut [Document], inbound: &HashMap<String, Vec<String>>) -> HashSet<String> {
+    let mut changed: HashSet<String> = HashSet::new();
+
+    for document in documents.iter_mut() {
+        let anchors: Vec<String> = std::iter::once(&document.url)
+            .chain(document.alternates.iter())
+            .filter_map(|url| inbound.get(url))
+            .flatten()
+            .take(MAX_ANCHORS)
+            .cloned()
+            .collect();
+        if anchors != document.anchor_text {
+            document.anchor_text = anchors;
+            changed.insert(document.url.clone());
+        }
+    }
+    changed
+}
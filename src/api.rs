@@ -2,10 +2,16 @@ extern crate redis;
 use rocket::http::{Header, Status};
 use rocket::{Request, Response, routes};
 use rocket::response::{Responder, Result};
-use rocket::fairing::{Fairing, Info, Kind};
+use rocket::fairing::{AdHoc, Fairing, Info, Kind};
 use rocket::serde::json::Json;
-use rocket::{post, options, launch};
-use crate::services::{fill_indices, get_search_results};
+use rocket::{get, post, options, launch, State};
+use crate::services::{fill_indices, get_search_results, recrawl_indices, schedule_recrawls, RecrawlReport};
+use crate::crawl::CrawlConfig;
+use crate::translate::TranslationConfig;
+use std::sync::Arc;
+use std::time::Duration;
+use crate::collection::{CollectionStats, Collections, CreateCollection};
+use crate::analysis::AnalysisConfig;
 use crate::auth::{authenticate, Credentials, SearchHistoryResponse, make_registration, update_history};
 use crate::config::Config;
 use crate::meta::{aggregate, MetaSearchRequest, SearchResult, SearchResponse, MetaSearchResult};
@@ -20,19 +26,43 @@ fn options() -> &'static str {
 // Currently we fill all index types by default to prevent
 // another expensive call to this endpoint
 #[post("/fill", data = "<config>")]
-async fn fill(config: Json<Config>) {
+async fn fill(config: Json<Config>, collections: &State<Arc<Collections>>) {
     let config: Config = config.into_inner();
     let crawl_depth: u8 = config.search_params.crawl_depth;
     let seed_count: u8 = config.search_params.number_of_seeds;
-    match fill_indices(crawl_depth, seed_count).await {
-        Ok(_) => return,
+    let collections = match collections.resolve(&config.search_params.collections) {
+        Ok(collections) => collections,
         Err(e) => {
-            eprintln!("Error filling indices: {:?}", e);
+            eprintln!("Error filling indices: {}", e);
             return
         }
+    };
+    for collection in collections {
+        if let Err(e) = fill_indices(&collection, crawl_depth, seed_count, &config.search_params.crawl, &config.search_params.translation).await {
+            eprintln!("Error filling indices of collection {}: {:?}", collection.name, e);
+        }
     }
 }
 
+// Revisits already indexed pages of a collection and applies only what changed to its indices.
+#[post("/recrawl", data = "<config>")]
+async fn recrawl(config: Json<Config>, collections: &State<Arc<Collections>>) -> std::result::Result<Json<RecrawlReport>, Json<String>> {
+    let config: Config = config.into_inner();
+    let collection = match collections.resolve(&config.search_params.collections) {
+        Ok(resolved) if resolved.len() == 1 => resolved[0].clone(),
+        Ok(_) => return Err(Json(String::from("Recrawl one collection at a time"))),
+        Err(e) => return Err(Json(e)),
+    };
+    let crawl_config = collection.config.crawl.as_ref().unwrap_or(&config.search_params.crawl);
+    let translation = collection.config.translation.as_ref().unwrap_or(&config.search_params.translation);
+    match recrawl_indices(crawl_config, translation, &collection.indices).await {
+        Ok(report) => Ok(Json(report)),
+        Err(e) => {
+            eprintln!("Error recrawling indices: {:?}", e);
+            Err(Json(e.to_string()))
+        }
+    }
+}
 
 impl<'r> Responder<'r, 'static> for SearchResult {
     fn respond_to(self, request: &'r Request<'_>) -> Result<'static> {
@@ -48,7 +78,7 @@ impl<'r> Responder<'r, 'static> for SearchResult {
 }
 
 #[post("/get-results", data = "<config>")]
-pub async fn get_results(config: Json<Config>) -> SearchResult {
+pub async fn get_results(config: Json<Config>, collections: &State<Arc<Collections>>) -> SearchResult {
     let config: Config = config.into_inner();
 
     let search_params = config.search_params;
@@ -56,6 +86,12 @@ pub async fn get_results(config: Json<Config>) -> SearchResult {
     let browsers = search_params.browsers;
     let method = search_params.search_method;
     let index_type = search_params.index_type;
+    let scoring = search_params.scoring;
+    let language = search_params.language;
+    let collections = match collections.resolve(&search_params.collections) {
+        Ok(collections) => collections,
+        Err(e) => return SearchResult::Error(Json(e))
+    };
     
     println!("Using the following browsers: {:?}", browsers);
     println!("Using the following method: {}", method);
@@ -117,11 +153,11 @@ pub async fn get_results(config: Json<Config>) -> SearchResult {
     // TF-IDF w/ Inverted - then pass empty script to below.
    
     // embedding.py & sentence_transform.py
-    // if index_type == 1 then pass empty string as script.
+    // if index_type == 1 or 2 (B-Tree) then pass empty string as script.
     // if search method == 2 then pass sentence_transform.py as script.
     // if search method == 1 then pass embedding.py as script.
     let mut script = "";
-    if index_type == 1 {
+    if index_type == 1 || index_type == 2 {
         script = "";
     }
     else if method == 1 {
@@ -131,7 +167,7 @@ pub async fn get_results(config: Json<Config>) -> SearchResult {
         script = "scripts/sentence_transform.py"
     }
 
-    match get_search_results(q, script) {
+    match get_search_results(q, language.as_deref(), index_type, script, scoring.as_ref(), &collections) {
         Ok(results) => {
             responses.push(results);
             return SearchResult::Documents(Json(responses))
@@ -153,6 +189,33 @@ pub async fn get_results(config: Json<Config>) -> SearchResult {
 }
 
 
+// Adds a collection, or replaces the config of an existing one.
+#[post("/create", data = "<request>")]
+fn create_collection(request: Json<CreateCollection>, collections: &State<Arc<Collections>>) -> std::result::Result<Json<CollectionStats>, Json<String>> {
+    let request = request.into_inner();
+    match collections.create(&request.name, request.config) {
+        Ok(collection) => Ok(Json(collection.stats())),
+        Err(e) => {
+            eprintln!("Error creating collection {}: {}", request.name, e);
+            Err(Json(e))
+        }
+    }
+}
+
+#[get("/list")]
+fn list_collections(collections: &State<Arc<Collections>>) -> Json<Vec<CollectionStats>> {
+    Json(collections.all().iter().map(|collection| collection.stats()).collect())
+}
+
+#[get("/<name>/stats")]
+fn collection_stats(name: &str, collections: &State<Arc<Collections>>) -> std::result::Result<Json<CollectionStats>, Json<String>> {
+    match collections.get(name) {
+        Some(collection) => Ok(Json(collection.stats())),
+        None => Err(Json(format!("No collection named {}", name))),
+    }
+}
+
+
 // TO DO: specify to return (i) unspecified error (for now) (ii) user does not exist (iii)
 // successful login with included user session object (also will include search history)
 pub enum AuthResult {
@@ -277,15 +340,88 @@ impl Fairing for CORS {
     }
 }
 
+// Starts scheduled recrawls where recrawl_interval_secs is set (Rocket.toml or
+// ROCKET_RECRAWL_INTERVAL_SECS), 0 disables them. They crawl and translate with the
+// crawl and translation tables of the config, the defaults where missing.
+fn recrawl_schedule() -> AdHoc {
+    AdHoc::on_liftoff("Recrawl schedule", |rocket| Box::pin(async move {
+        let interval: u64 = rocket.figment().extract_inner("recrawl_interval_secs").unwrap_or(0);
+        if interval > 0 {
+            println!("Recrawling indices every {} seconds", interval);
+            let crawl: CrawlConfig = extract_or_default(rocket.figment(), "crawl");
+            let translation: TranslationConfig = extract_or_default(rocket.figment(), "translation");
+            if let Some(collections) = rocket.state::<Arc<Collections>>().cloned() {
+                tokio::spawn(schedule_recrawls(Duration::from_secs(interval), crawl, translation, collections));
+            }
+        }
+    }))
+}
+
+// Reads the table of the config under the key, reporting it where invalid.
+fn extract_or_default<T: Default + serde::de::DeserializeOwned>(figment: &rocket::figment::Figment, key: &str) -> T {
+    figment.extract_inner(key).unwrap_or_else(|e| {
+        if !e.missing() {
+            eprintln!("Invalid {} config, using the defaults: {}", key, e);
+        }
+        T::default()
+    })
+}
+
 #[launch]
 pub fn rocket() -> _ {
+    let figment = rocket::Config::figment().merge(("port", 9797));
+    // Text analysis of collections that do not set their own. It stays fixed while the
+    // server runs - indices built with other settings would not match its queries.
+    let analysis: AnalysisConfig = extract_or_default(&figment, "analysis");
+
     rocket::build()
-        .configure(rocket::Config::figment().merge(("port", 9797))) 
+        .configure(figment) 
+        // Indices of each collection are read from disk once, and swapped for new ones
+        // after each fill or recrawl.
+        .manage(Collections::load(analysis))
         .attach(CORS)
-        .mount("/search", routes![fill, get_results, options])
+        .attach(recrawl_schedule())
+        .mount("/search", routes![fill, get_results, recrawl, options])
         .mount("/auth", routes![login, register, add_history, options])
         .mount("/config", routes![write, read, options])
+        .mount("/collections", routes![create_collection, list_collections, collection_stats, options])
 }
 
 
 
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::ContentType;
+    use rocket::local::blocking::Client;
+    use super::*;
+
+    #[test]
+    fn lists_created_collections() {
+        let dir = std::env::temp_dir().join(format!("collections-test-{}", std::process::id())).to_string_lossy().into_owned();
+        let _ = std::fs::remove_dir_all(&dir);
+        let client = |collections: Arc<Collections>| {
+            Client::tracked(rocket::build().manage(collections).mount("/collections", routes![create_collection, list_collections, collection_stats])).unwrap()
+        };
+
+        let created = client(Collections::load_from(&dir, AnalysisConfig::default()));
+        let status = created.post("/collections/create")
+            .header(ContentType::JSON)
+            .body(r#"{"name": "physics", "seeds": ["https://arxiv.org"], "crawl_depth": 2, "crawl": {"max_in_flight": 4}}"#)
+            .dispatch()
+            .status();
+        assert_eq!(status, Status::Ok);
+        let listed: Vec<CollectionStats> = created.get("/collections/list").dispatch().into_json().unwrap();
+        assert_eq!(listed.iter().map(|stats| stats.name.as_str()).collect::<Vec<&str>>(), vec!["default", "physics"]);
+        assert_eq!(listed[1].config.seeds, vec!["https://arxiv.org"]);
+        drop(created);
+
+        // The registry keeps the collection, and its config, for the next start.
+        let reloaded = client(Collections::load_from(&dir, AnalysisConfig::default()));
+        let stats: CollectionStats = reloaded.get("/collections/physics/stats").dispatch().into_json().unwrap();
+        assert_eq!(stats.config.crawl_depth, Some(2));
+        assert_eq!(stats.config.crawl.map(|crawl| crawl.max_in_flight), Some(4));
+        assert_eq!(stats.documents, 0);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -120,6 +120,6 @@ pub fn update_history(cred: &Credentials) -> RedisResult<()> {
         arr.extend(cred.history.iter().map(|h| json!(h)));
     }
 
-    con.hset(cred.redis_key(), "history", curr_history.to_string())?;
+    con.hset::<_, _, _, ()>(cred.redis_key(), "history", curr_history.to_string())?;
     Ok(())
 }
@@ -0,0 +1,327 @@
+// Module separates a page's main content from its boilerplate (navigation, cookie
+// banners, footers, "related links" sidebars). Containers are scored readability
+// style - by the length, comma count and link density of the paragraphs they hold,
+// nudged by their class and id - and text blocks are taken from the best container
+// and any siblings that score close to it.
+
+use std::collections::HashMap;
+use ego_tree::NodeId;
+use scraper::{ElementRef, Html, Selector};
+
+
+// Elements never part of the main content, nor scored.
+const EXCLUDED_TAGS: [&str; 13] = ["nav", "header", "footer", "aside", "form", "script", "style", "noscript", "template", "iframe", "button", "select", "svg"];
+// Elements whose text is scored and credited to their ancestors.
+const SCORED_TAGS: &str = "p, pre, td, li, dd, blockquote";
+// Elements emitted whole as a content block, rather than descended into.
+const BLOCK_TAGS: [&str; 13] = ["p", "h1", "h2", "h3", "h4", "h5", "h6", "pre", "li", "dt", "dd", "blockquote", "figcaption"];
+// Elements whose text runs on with the text around them.
+const INLINE_TAGS: [&str; 16] = ["a", "abbr", "b", "br", "cite", "code", "em", "i", "kbd", "mark", "q", "small", "span", "strong", "sub", "sup"];
+
+const POSITIVE_NAMES: [&str; 10] = ["article", "body", "content", "entry", "main", "page", "post", "text", "lecture", "course"];
+const NEGATIVE_NAMES: [&str; 17] = ["banner", "breadcrumb", "comment", "consent", "cookie", "footer", "masthead", "menu", "nav", "promo", "related", "share", "sidebar", "social", "sponsor", "subscribe", "widget"];
+
+// Paragraphs shorter than this are too short to say where the content is.
+const MIN_SCORED_CHARS: usize = 25;
+// Siblings of the best container are kept where they score this fraction of it.
+const SIBLING_FRACTION: f64 = 0.2;
+
+#[derive(Debug, Default)]
+pub struct MainContent {
+    // Text blocks of the main content in document order.
+    pub blocks: Vec<String>,
+    // Percentage (0-100) of the page's text left out as boilerplate.
+    pub boilerplate_ratio: u8,
+}
+
+fn normalise_text(element: ElementRef) -> String {
+    element.text().collect::<Vec<&str>>().join(" ").split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+fn text_length(element: ElementRef) -> usize {
+    element.text().map(|text| text.trim().chars().count()).sum()
+}
+
+// Share of the element's text inside links - high for menus and link lists.
+fn link_density(element: ElementRef) -> f64 {
+    let link_selector = Selector::parse("a").unwrap();
+    let length = text_length(element);
+    if length == 0 {
+        return 0.0;
+    }
+
+    let link_length: usize = element.select(&link_selector).map(text_length).sum();
+    link_length as f64 / length as f64
+}
+
+fn names(element: ElementRef) -> String {
+    let value = element.value();
+    format!("{} {}", value.attr("class").unwrap_or_default(), value.attr("id").unwrap_or_default()).to_lowercase()
+}
+
+// Weight from the class and id, i.e. "article-body" up, "cookie-banner" down.
+fn name_weight(element: ElementRef) -> f64 {
+    let names = names(element);
+    let mut weight = 0.0;
+
+    if POSITIVE_NAMES.iter().any(|name| names.contains(name)) {
+        weight += 25.0;
+    }
+    if NEGATIVE_NAMES.iter().any(|name| names.contains(name)) {
+        weight -= 25.0;
+    }
+    weight
+}
+
+fn is_excluded(element: ElementRef) -> bool {
+    EXCLUDED_TAGS.contains(&element.value().name())
+        || element.value().attr("role").is_some_and(|role| matches!(role, "navigation" | "banner" | "contentinfo" | "complementary"))
+        || element.value().attr("aria-hidden") == Some("true")
+        // Clearly boilerplate by name, unless it also claims to be content. Page level
+        // elements are never dropped by name (i.e. <body class="has-cookie-banner">).
+        || (!matches!(element.value().name(), "html" | "body" | "main" | "article") && name_weight(element) < 0.0)
+}
+
+pub fn has_excluded_ancestor(element: ElementRef) -> bool {
+    element.ancestors().filter_map(ElementRef::wrap).any(is_excluded)
+}
+
+// Starting score of a container from its tag and names.
+fn initial_score(element: ElementRef) -> f64 {
+    let tag_score = match element.value().name() {
+        "article" | "main" => 10.0,
+        "div" | "section" => 5.0,
+        "pre" | "td" | "blockquote" => 3.0,
+        "ol" | "ul" | "dl" | "dd" | "dt" | "li" => -3.0,
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th" => -5.0,
+        _ => 0.0,
+    };
+    tag_score + name_weight(element)
+}
+
+// Scores every container holding scored text, crediting each paragraph's score to
+// its parent in full and its grandparent by half.
+fn score_containers(document: &Html) -> HashMap<NodeId, f64> {
+    let scored_selector = Selector::parse(SCORED_TAGS).unwrap();
+    let mut scores: HashMap<NodeId, f64> = HashMap::new();
+
+    for element in document.select(&scored_selector) {
+        let length = text_length(element);
+        if length < MIN_SCORED_CHARS || has_excluded_ancestor(element) {
+            continue;
+        }
+
+        let commas = element.text().map(|text| text.matches(',').count()).sum::<usize>() as f64;
+        let score = 1.0 + commas + (length as f64 / 100.0).min(3.0);
+
+        let ancestors = element.ancestors().filter_map(ElementRef::wrap).take(2);
+        for (level, ancestor) in ancestors.enumerate() {
+            let divider = if level == 0 { 1.0 } else { 2.0 };
+            *scores.entry(ancestor.id()).or_insert_with(|| initial_score(ancestor)) += score / divider;
+        }
+    }
+
+    // Containers mostly made of links are menus, whatever their text.
+    for (id, score) in scores.iter_mut() {
+        if let Some(element) = document.tree.get(*id).and_then(ElementRef::wrap) {
+            *score *= 1.0 - link_density(element);
+        }
+    }
+
+    scores
+}
+
+// Appends the element's text blocks in document order - whole blocks for paragraphs,
+// headings, list items, definitions and <pre>, a row per block for tables.
+fn collect_blocks(element: ElementRef, blocks: &mut Vec<String>) {
+    if is_excluded(element) {
+        return;
+    }
+
+    let name = element.value().name();
+
+    if name == "tr" {
+        let cell_selector = Selector::parse("td, th").unwrap();
+        let cells: Vec<String> = element.select(&cell_selector)
+            .map(normalise_text)
+            .filter(|cell| !cell.is_empty())
+            .collect();
+        if !cells.is_empty() {
+            blocks.push(cells.join(" | "));
+        }
+        return;
+    }
+
+    // Nested lists are kept as their own blocks rather than folded into the item.
+    let has_nested_blocks = name == "li" && element.children()
+        .filter_map(ElementRef::wrap)
+        .any(|child| matches!(child.value().name(), "ul" | "ol" | "dl" | "table" | "pre"));
+
+    if BLOCK_TAGS.contains(&name) && !has_nested_blocks {
+        // <pre> keeps its line breaks and indentation.
+        let text = if name == "pre" { element.text().collect::<String>().trim().to_string() } else { normalise_text(element) };
+        if !text.is_empty() {
+            blocks.push(text);
+        }
+        return;
+    }
+
+    // Loose text and inline elements directly inside a container (i.e. <div>text</div>,
+    // or the label of a list item holding a nested list) form blocks between its children.
+    let mut run = Run::default();
+    for child in element.children() {
+        match ElementRef::wrap(child) {
+            Some(child) if INLINE_TAGS.contains(&child.value().name()) => {
+                if !is_excluded(child) {
+                    let text = normalise_text(child);
+                    if child.value().name() == "a" {
+                        run.link_chars += non_whitespace_chars(&text);
+                    }
+                    run.text.push(' ');
+                    run.text.push_str(&text);
+                }
+            }
+            Some(child) => {
+                run.push_to(blocks);
+                collect_blocks(child, blocks);
+            }
+            None => {
+                if let Some(text) = child.value().as_text() {
+                    run.text.push(' ');
+                    run.text.push_str(text);
+                }
+            }
+        }
+    }
+    run.push_to(blocks);
+}
+
+// Inline text gathered between block children, with how much of it is link text.
+#[derive(Default)]
+struct Run {
+    text: String,
+    link_chars: usize,
+}
+
+impl Run {
+    // Adds the run as a block, unless it is mostly links (a bare row of links).
+    fn push_to(&mut self, blocks: &mut Vec<String>) {
+        let text = self.text.split_whitespace().collect::<Vec<&str>>().join(" ");
+        if !text.is_empty() && self.link_chars * 2 <= non_whitespace_chars(&text) {
+            blocks.push(text);
+        }
+        *self = Run::default();
+    }
+}
+
+pub fn extract_main_content(document: &Html) -> MainContent {
+    let body_selector = Selector::parse("body").unwrap();
+    let Some(body) = document.select(&body_selector).next() else {
+        return MainContent::default();
+    };
+
+    let scores = score_containers(document);
+    // Ties go to the container that comes first in the document.
+    let order: HashMap<NodeId, usize> = document.tree.root().descendants().enumerate().map(|(position, node)| (node.id(), position)).collect();
+    let best = scores.iter()
+        .max_by(|a, b| a.1.partial_cmp(b.1).unwrap_or(std::cmp::Ordering::Equal).then_with(|| order.get(b.0).cmp(&order.get(a.0))))
+        .and_then(|(id, score)| Some((document.tree.get(*id).and_then(ElementRef::wrap)?, *score)));
+
+    let mut blocks: Vec<String> = Vec::new();
+
+    match best {
+        Some((best, best_score)) => {
+            // Content split across sibling containers (i.e. one <section> per topic)
+            // is kept where the sibling scores close to the best.
+            let threshold = (best_score * SIBLING_FRACTION).max(10.0);
+            let siblings: Vec<ElementRef> = match best.parent().and_then(ElementRef::wrap) {
+                Some(parent) => parent.children().filter_map(ElementRef::wrap).collect(),
+                None => vec![best],
+            };
+
+            for sibling in siblings {
+                let keep = sibling.id() == best.id()
+                    || scores.get(&sibling.id()).is_some_and(|score| *score >= threshold);
+                if keep {
+                    collect_blocks(sibling, &mut blocks);
+                }
+            }
+        }
+        // Nothing long enough to score, i.e. a short landing page - keep what is
+        // outside the obvious boilerplate.
+        None => collect_blocks(body, &mut blocks),
+    }
+
+    // Compared without whitespace, which blocks and raw text nodes space differently.
+    let total = visible_chars(body);
+    let kept: usize = blocks.iter().map(|block| non_whitespace_chars(block)).sum();
+
+    let boilerplate_ratio = (kept.min(total) * 100)
+        .checked_div(total)
+        .map_or(0, |kept_percent| (100 - kept_percent) as u8);
+
+    MainContent { blocks, boilerplate_ratio }
+}
+
+fn non_whitespace_chars(text: &str) -> usize {
+    text.chars().filter(|c| !c.is_whitespace()).count()
+}
+
+// Visible characters of the whole page, boilerplate included, for the boilerplate ratio.
+fn visible_chars(element: ElementRef) -> usize {
+    if matches!(element.value().name(), "script" | "style" | "noscript" | "template" | "svg") {
+        return 0;
+    }
+
+    element.children()
+        .map(|child| match ElementRef::wrap(child) {
+            Some(child_element) => visible_chars(child_element),
+            None => child.value().as_text().map_or(0, |text| non_whitespace_chars(text)),
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_best_scoring_container() {
+        let page = Html::parse_document(r#"<html><body>
+            <nav><a href="/">Home</a> <a href="/courses">Courses</a> <a href="/people">People</a></nav>
+            <div class="cookie-banner"><p>We use cookies to improve your experience, please accept them all.</p></div>
+            <div id="main">
+                <h1>The heat equation</h1>
+                <p>The heat equation describes how temperature changes over time in a solid, given its conductivity.</p>
+                <p>Fourier solved it with sums of sines and cosines, each of which decays at its own rate.</p>
+            </div>
+            <div class="related-links">
+                <p><a href="/waves">The wave equation, its derivation and examples of its solutions</a></p>
+            </div>
+            <footer><p>Copyright 2024, Department of Mathematics, all rights reserved.</p></footer>
+        </body></html>"#);
+
+        let content = extract_main_content(&page);
+        assert_eq!(content.blocks, vec![
+            "The heat equation",
+            "The heat equation describes how temperature changes over time in a solid, given its conductivity.",
+            "Fourier solved it with sums of sines and cosines, each of which decays at its own rate.",
+        ]);
+        assert!(content.boilerplate_ratio > 20 && content.boilerplate_ratio < 80);
+    }
+
+    #[test]
+    fn breaks_ties_by_document_order() {
+        // Both sections score the same, and neither is a sibling of the other.
+        let page = Html::parse_document(r#"<html><body>
+            <div><section><p>Lecture one covers the heat equation, its derivation and boundary conditions.</p></section></div>
+            <div><section><p>Lecture two covers the wave equation, its derivation and boundary conditions.</p></section></div>
+        </body></html>"#);
+
+        // Scores are kept in a HashMap, so a tie broken by its iteration order would
+        // not pick the same container every time.
+        for _ in 0..20 {
+            assert_eq!(extract_main_content(&page).blocks, vec!["Lecture one covers the heat equation, its derivation and boundary conditions."]);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+// Module persists crawl progress under the indices directory so an interrupted crawl
+// can be resumed, and a finished crawl extended, without refetching pages.
+// Layout:
+//   state.bin - frontier, visited set and skipped URLs, replaced atomically on each save.
+//   pages.bin - append-only log of fetched CrawlResults, truncated on load to the
+//               length recorded by the last saved state.
+
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use crate::crawl::{CrawlResult, SkippedUrl, UrlToVisit};
+
+// Directory the checkpoint of crawls filling the indices in the directory is kept in.
+pub fn checkpoint_dir(indices_dir: &str) -> String {
+    format!("{}/crawl", indices_dir)
+}
+
+// Everything needed to carry on a crawl besides the fetched pages themselves.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct CrawlState {
+    pub max_depth: u32,
+    pub seeds: Vec<String>,
+    pub frontier: BinaryHeap<UrlToVisit>,
+    // URLs being fetched when the state was saved, keyed by URL, put back on the
+    // frontier on resume.
+    pub in_flight: HashMap<String, UrlToVisit>,
+    // URLs skipped because their host ran out of budget, retried on resume.
+    pub deferred: Vec<UrlToVisit>,
+    pub visited: HashSet<String>,
+    pub skipped: Vec<SkippedUrl>,
+    // Origins whose sitemaps have already been queued.
+    pub sitemap_hosts: HashSet<String>,
+    // Byte length of pages.bin covered by this state.
+    pages_len: u64,
+}
+
+// Saved state and the pages fetched up to it.
+pub type Resumed = (CrawlState, Vec<CrawlResult>);
+
+pub struct Checkpoint {
+    state_path: PathBuf,
+    pages: File,
+    pages_len: u64,
+}
+
+impl Checkpoint {
+    // Opens the checkpoint in dir, returning any previous state and pages where
+    // resume is set. Without resume, previous progress is discarded.
+    pub fn open(dir: &str, resume: bool) -> std::io::Result<(Checkpoint, Option<Resumed>)> {
+        fs::create_dir_all(dir)?;
+        let state_path = Path::new(dir).join("state.bin");
+        let pages_path = Path::new(dir).join("pages.bin");
+
+        let mut pages = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&pages_path)?;
+
+        // A page log shorter than the state claims cannot be resumed from.
+        let pages_available = pages.metadata()?.len();
+        let previous = if resume { read_state(dir) } else { None }
+            .filter(|state| state.pages_len <= pages_available);
+
+        let Some(state) = previous else {
+            // Remove the old state first, so a crash before the next save cannot
+            // pair it with the emptied page log.
+            if state_path.exists() {
+                fs::remove_file(&state_path)?;
+            }
+            pages.set_len(0)?;
+            let checkpoint = Checkpoint { state_path, pages, pages_len: 0 };
+            return Ok((checkpoint, None));
+        };
+
+        // Anything past the recorded length was written after the last save and
+        // belongs to URLs still on the saved frontier, so it is dropped.
+        pages.set_len(state.pages_len)?;
+        pages.seek(SeekFrom::Start(0))?;
+
+        let mut results: Vec<CrawlResult> = Vec::new();
+        let mut reader = BufReader::new(&pages);
+        while let Ok(result) = bincode::deserialize_from::<_, CrawlResult>(&mut reader) {
+            results.push(result);
+        }
+
+        pages.seek(SeekFrom::End(0))?;
+        let checkpoint = Checkpoint { state_path, pages, pages_len: state.pages_len };
+        Ok((checkpoint, Some((state, results))))
+    }
+
+    pub fn append_page(&mut self, result: &CrawlResult) -> Result<(), Box<dyn std::error::Error>> {
+        let bytes = bincode::serialize(result)?;
+        self.pages.write_all(&bytes)?;
+        self.pages_len += bytes.len() as u64;
+        Ok(())
+    }
+
+    // Writes state through a temporary file and rename, so a crash mid-save leaves
+    // the previous state intact.
+    pub fn save(&mut self, state: &mut CrawlState) -> Result<(), Box<dyn std::error::Error>> {
+        self.pages.sync_data()?;
+        state.pages_len = self.pages_len;
+
+        let temp_path = self.state_path.with_extension("bin.tmp");
+        let mut file = File::create(&temp_path)?;
+        bincode::serialize_into(&mut file, state)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, &self.state_path)?;
+        Ok(())
+    }
+}
+
+// Reads the saved crawl state in dir (without pages), if there is one.
+pub fn read_state(dir: &str) -> Option<CrawlState> {
+    let file = File::open(Path::new(dir).join("state.bin")).ok()?;
+    match bincode::deserialize_from(BufReader::new(file)) {
+        Ok(state) => Some(state),
+        Err(e) => {
+            eprintln!("Discarding unreadable crawl checkpoint: {}", e);
+            None
+        }
+    }
+}
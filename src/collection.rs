@@ -0,0 +1,215 @@
+// Module keeps named collections of indices - i.e. "university-courses",
+// "physics-preprints" or "my-reading-notes" - each filled from its own seeds and to its
+// own depth, analysed and ranked with its own settings, and searched alone or along
+// with others. Collections are listed in a registry file. The default collection is
+// kept in the indices directory itself, so indices filled before collections carry on
+// as it, and the others each in a directory of their own under it.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, PoisonError, RwLock};
+use serde::{Deserialize, Serialize};
+use crate::analysis::{Analyser, AnalysisConfig};
+use crate::crawl::CrawlConfig;
+use crate::index::INDEX_DIR;
+use crate::rank::ScoringConfig;
+use crate::segment::write_file;
+use crate::shared::SharedIndices;
+use crate::translate::TranslationConfig;
+use crate::writer::{disk_usage, Commit};
+
+
+// Collection searched and filled where a request names none.
+pub const DEFAULT_COLLECTION: &str = "default";
+// Directory of the collections other than the default, under the indices directory.
+const COLLECTIONS_DIR: &str = "collections";
+// Config of each collection, the default one included where it has been given one -
+// kept as JSON in the indices directory, so it can be read and edited by hand.
+const REGISTRY_FILE: &str = "collections.json";
+const MAX_NAME_LEN: usize = 64;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct CollectionConfig {
+    // Pages the crawl starts from. Where none are given, seeds are taken from the
+    // university domains (domains.json), as many as the fill request asks for.
+    pub seeds: Vec<String>,
+    // Depth crawled from the seeds, that of the fill request where None.
+    pub crawl_depth: Option<u8>,
+    // Analyser settings of the collection's indices, the server's where None. Changes
+    // take effect when the server next starts, which rebuilds the indices.
+    pub analysis: Option<AnalysisConfig>,
+    // Ranking settings of searches that do not give their own.
+    pub scoring: Option<ScoringConfig>,
+    // Politeness and translation settings the collection is crawled with, in place of
+    // those of fill and recrawl requests, and of the server for scheduled recrawls.
+    pub crawl: Option<CrawlConfig>,
+    pub translation: Option<TranslationConfig>,
+}
+
+// Body of /collections/create.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreateCollection {
+    pub name: String,
+    #[serde(flatten)]
+    pub config: CollectionConfig,
+}
+
+pub struct Collection {
+    pub name: String,
+    pub config: CollectionConfig,
+    pub indices: Arc<SharedIndices>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CollectionStats {
+    pub name: String,
+    pub config: CollectionConfig,
+    // Generation of the last commit, 0 before the collection is first filled.
+    pub generation: u64,
+    pub documents: usize,
+    pub segments: usize,
+    // Documents deleted from segments and not yet merged away.
+    pub deleted_documents: usize,
+    pub disk_bytes: u64,
+}
+
+fn collection_dir(dir: &str, name: &str) -> String {
+    if name == DEFAULT_COLLECTION {
+        dir.to_string()
+    } else {
+        format!("{}/{}/{}", dir, COLLECTIONS_DIR, name)
+    }
+}
+
+// Names become directory names, so are kept to letters, digits, '-' and '_'.
+fn check_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty() && name.len() <= MAX_NAME_LEN
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(format!("Invalid collection name {:?} - use up to {} letters, digits, '-' or '_'", name, MAX_NAME_LEN));
+    }
+    Ok(())
+}
+
+fn read_registry(dir: &str) -> Result<BTreeMap<String, CollectionConfig>, String> {
+    let path = format!("{}/{}", dir, REGISTRY_FILE);
+    if !Path::new(&path).exists() {
+        return Ok(BTreeMap::new());
+    }
+    let bytes = fs::read(&path).map_err(|e| e.to_string())?;
+    serde_json::from_slice(&bytes).map_err(|e| e.to_string())
+}
+
+fn write_registry(dir: &str, registry: &BTreeMap<String, CollectionConfig>) -> Result<(), String> {
+    if !Path::new(dir).exists() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let path = format!("{}/{}", dir, REGISTRY_FILE);
+    let bytes = serde_json::to_vec_pretty(registry).map_err(|e| e.to_string())?;
+    write_file(&path, &[&bytes]).map_err(|e| format!("{}: {}", path, e))
+}
+
+impl Collection {
+    fn open(dir: &str, name: String, config: CollectionConfig, default_analysis: &AnalysisConfig) -> Self {
+        let analyser = Analyser::from_config(config.analysis.as_ref().unwrap_or(default_analysis));
+        let indices = SharedIndices::load(&collection_dir(dir, &name), analyser);
+        Collection { name, config, indices }
+    }
+
+    pub fn stats(&self) -> CollectionStats {
+        let commit = Commit::read(&self.indices.dir).unwrap_or_default();
+        CollectionStats {
+            name: self.name.clone(),
+            config: self.config.clone(),
+            generation: commit.generation,
+            documents: self.indices.snapshot().map_or(0, |snapshot| snapshot.store.len()),
+            segments: commit.segments.len(),
+            deleted_documents: commit.segments.iter().map(|segment| segment.deleted_ids().len()).sum(),
+            disk_bytes: disk_usage(&self.indices.dir),
+        }
+    }
+}
+
+// Every collection of the registry, open for the lifetime of the server.
+pub struct Collections {
+    // Indices directory, holding the registry and the default collection.
+    dir: String,
+    // Analyser settings of collections that do not give their own.
+    default_analysis: AnalysisConfig,
+    collections: RwLock<BTreeMap<String, Arc<Collection>>>,
+}
+
+impl Collections {
+    // Loads the indices of each collection in the registry, and of the default one.
+    pub fn load(default_analysis: AnalysisConfig) -> Arc<Self> {
+        Self::load_from(INDEX_DIR, default_analysis)
+    }
+
+    // As load, from the indices directory given.
+    pub fn load_from(dir: &str, default_analysis: AnalysisConfig) -> Arc<Self> {
+        let mut registry = read_registry(dir).unwrap_or_else(|e| {
+            eprintln!("Could not read the collection registry, loading the default collection only: {}", e);
+            BTreeMap::new()
+        });
+        registry.entry(String::from(DEFAULT_COLLECTION)).or_default();
+
+        let collections = registry.into_iter()
+            .map(|(name, config)| (name.clone(), Arc::new(Collection::open(dir, name, config, &default_analysis))))
+            .collect();
+        Arc::new(Collections { dir: dir.to_string(), default_analysis, collections: RwLock::new(collections) })
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<Collection>> {
+        self.collections.read().unwrap_or_else(PoisonError::into_inner).get(name).cloned()
+    }
+
+    pub fn all(&self) -> Vec<Arc<Collection>> {
+        self.collections.read().unwrap_or_else(PoisonError::into_inner).values().cloned().collect()
+    }
+
+    // The collections a request names, the default one where it names none.
+    pub fn resolve(&self, names: &[String]) -> Result<Vec<Arc<Collection>>, String> {
+        if names.is_empty() {
+            return self.get(DEFAULT_COLLECTION).map(|collection| vec![collection]).ok_or_else(|| String::from("No default collection"));
+        }
+        let mut resolved: Vec<Arc<Collection>> = Vec::new();
+        for name in names {
+            let collection = self.get(name).ok_or_else(|| format!("No collection named {}", name))?;
+            if !resolved.iter().any(|other| other.name == collection.name) {
+                resolved.push(collection);
+            }
+        }
+        Ok(resolved)
+    }
+
+    // Adds a collection to the registry, or replaces the config of an existing one -
+    // its indices are kept, and the next fill extends them where the seeds or depth grew.
+    pub fn create(&self, name: &str, config: CollectionConfig) -> Result<Arc<Collection>, String> {
+        check_name(name)?;
+        let mut collections = self.collections.write().unwrap_or_else(PoisonError::into_inner);
+
+        let mut registry: BTreeMap<String, CollectionConfig> = collections.iter()
+            .map(|(name, collection)| (name.clone(), collection.config.clone()))
+            .collect();
+        registry.insert(name.to_string(), config.clone());
+        write_registry(&self.dir, &registry)?;
+
+        let collection = match collections.get(name) {
+            Some(existing) => {
+                if existing.config.analysis != config.analysis {
+                    println!("Analysis settings of collection {} changed, its indices are rebuilt when the server next starts", name);
+                }
+                Collection { name: name.to_string(), config, indices: existing.indices.clone() }
+            }
+            None => {
+                println!("Created collection {}", name);
+                Collection::open(&self.dir, name.to_string(), config, &self.default_analysis)
+            }
+        };
+        let collection = Arc::new(collection);
+        collections.insert(name.to_string(), collection.clone());
+        Ok(collection)
+    }
+}
@@ -4,6 +4,9 @@ use serde::{Serialize, Deserialize};
 use serde_json::json;
 use std::collections::HashMap;
 use serde_json::Value;
+use crate::crawl::CrawlConfig;
+use crate::translate::TranslationConfig;
+use crate::rank::ScoringConfig;
 
 /*
 Reference guide:
@@ -19,6 +22,23 @@ pub struct SearchParams {
     pub browsers: HashMap<String, bool>,
     pub index_type: u8,
     pub q: String,
+    // Politeness settings for /search/fill, optional for older clients.
+    #[serde(default)]
+    pub crawl: CrawlConfig,
+    // Translation of non-English documents, off unless configured.
+    #[serde(default)]
+    pub translation: TranslationConfig,
+    // Field weights and length normalisation of BM25F ranking, those of each collection
+    // searched where not given.
+    #[serde(default)]
+    pub scoring: Option<ScoringConfig>,
+    // Collections filled, recrawled or searched, the default collection where empty.
+    #[serde(default)]
+    pub collections: Vec<String>,
+    // ISO 639-1 code of the language of the query, the default language of each
+    // collection searched where not given.
+    #[serde(default)]
+    pub language: Option<String>,
     //location: String
 }
 
@@ -65,7 +85,7 @@ impl Config {
         let mut conn = client.get_connection()?;
         
         let key = format!("{}_config", self.user.username);
-        conn.set(key, config_data)?;
+        conn.set::<_, _, ()>(key, config_data)?;
 
         Ok(())
     }
@@ -1,126 +1,822 @@
 use scraper::{Html, Selector};
-use std::collections::BinaryHeap;
-use reqwest::get;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use futures::stream::{self, StreamExt};
+use reqwest::{Client, StatusCode};
+use reqwest::header::{HeaderValue, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use serde::{Serialize, Deserialize};
+use url::Url;
+use crate::robots::RobotsCache;
+use crate::links::{anchor_text, base_url, in_scope, normalise, registered_domain, resolve, LinkEdge, LinkScope};
+use crate::checkpoint::{checkpoint_dir, Checkpoint, CrawlState};
+use crate::index::INDEX_DIR;
+use crate::sitemap::{decode_body, parse_sitemap, well_known_sitemaps, Sitemap, SitemapEntry};
+use crate::feed::{feed_links, parse_feed, FeedItem};
+use crate::extract::{content_kind, decode_text, parse_content_type, ContentKind};
 
-#[derive(Debug, Eq, PartialEq, Clone)]
-struct UrlToVisit {
-    url: String,
-    crawl_depth: u32,
+// Upper bound on sitemap files read per host, sitemap indexes can nest widely.
+const MAX_SITEMAP_FILES: usize = 10;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UrlToVisit {
+    pub url: String,
+    pub crawl_depth: u32,
+    // Registered domain of the seed this URL was reached from, used for scoping.
+    pub seed_domain: String,
+    // Orders URLs of the same depth - the sitemap/feed lastmod timestamp, 0 if unknown.
+    pub priority: i64,
 }
-    
-#[derive(Clone, Debug)]
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CrawlResult {
     pub url: String,
     pub new_urls: Vec<String>,
+    // Every link on the page with its anchor text, including repeated targets.
+    pub edges: Vec<LinkEdge>,
+    // Decoded text of HTML and plain text resources, empty for binary formats
+    // whose bytes are kept in raw for their extractor.
     pub body: String,
+    pub raw: Vec<u8>,
+    pub content_type: ContentKind,
+    pub validators: Validators,
+    // Unix timestamp of the fetch.
+    pub fetched_at: i64,
+    // RSS/Atom feeds the page links to.
+    pub feeds: Vec<String>,
+}
+
+// Cache validators returned with a page, sent back on recrawl so that
+// unchanged pages only cost a 304 response.
+#[derive(Clone, Debug, Default, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+// Previously indexed URL to revisit, with the validators stored for it.
+#[derive(Clone, Debug)]
+pub struct RevisitTarget {
+    pub url: String,
+    pub validators: Validators,
+}
+
+#[derive(Debug)]
+pub enum Revisit {
+    Unchanged,
+    Modified(CrawlResult),
+    // The page no longer exists (404/410) or robots.txt now disallows it.
+    Gone,
+    Failed(SkipReason),
+}
+
+// Politeness settings for the crawler, supplied as part of SearchParams.
+// Missing fields fall back to the defaults below.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CrawlConfig {
+    pub user_agent: String,
+    pub respect_robots: bool,
+    // Maximum number of pages fetched from a single host in one crawl.
+    pub max_requests_per_host: u32,
+    // Maximum number of sitemaps and feeds fetched from a single host in one crawl,
+    // counted apart from pages so that discovery never uses up the page budget.
+    pub max_discovery_requests_per_host: u32,
+    // Minimum time between two requests to the same host, raised to the
+    // host's Crawl-delay where robots.txt asks for longer.
+    pub min_host_interval_ms: u64,
+    // Which extracted links are followed, see links::LinkScope.
+    pub scope: LinkScope,
+    // Query parameters removed when canonicalising links, '*' suffix matches by prefix.
+    pub tracking_params: Vec<String>,
+    // Number of fetches running at once across all hosts, and against any single host.
+    pub max_in_flight: usize,
+    pub max_per_host: usize,
+    // Carry on from the checkpoint in checkpoint_dir where one exists, otherwise
+    // start afresh. Progress is saved every checkpoint_every pages (0 disables it).
+    pub resume: bool,
+    pub checkpoint_every: usize,
+    // Directory of the checkpoint, the crawl directory of the collection being filled.
+    // Set by the server rather than by clients.
+    #[serde(skip)]
+    pub checkpoint_dir: String,
+    // Seed each host from its sitemaps (robots.txt Sitemap lines, else well-known paths),
+    // taking at most max_sitemap_urls per host, most recently modified first.
+    pub use_sitemaps: bool,
+    pub max_sitemap_urls: usize,
+    // Seed the crawl with items of RSS/Atom feeds linked from crawled pages.
+    pub follow_feeds: bool,
+    // Largest response body downloaded - larger resources are skipped, checked against
+    // Content-Length before downloading and against the bytes read while downloading.
+    pub max_body_bytes: u64,
+}
+
+impl Default for CrawlConfig {
+    fn default() -> Self {
+        CrawlConfig {
+            user_agent: String::from("EducateBot/0.1 (+https://github.com/ThomasMTurner/Educate)"),
+            respect_robots: true,
+            max_requests_per_host: 50,
+            max_discovery_requests_per_host: 20,
+            min_host_interval_ms: 1000,
+            scope: LinkScope::Any,
+            tracking_params: ["utm_*", "fbclid", "gclid", "dclid", "msclkid", "mc_cid", "mc_eid", "_ga", "_hsenc", "_hsmkt"]
+                .iter().map(|param| param.to_string()).collect(),
+            max_in_flight: 16,
+            max_per_host: 2,
+            resume: true,
+            checkpoint_every: 25,
+            checkpoint_dir: checkpoint_dir(INDEX_DIR),
+            use_sitemaps: true,
+            max_sitemap_urls: 500,
+            follow_feeds: true,
+            max_body_bytes: 10 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum SkipReason {
+    InvalidUrl,
+    DisallowedByRobots,
+    HostBudgetExhausted,
+    FetchFailed(String),
+    // Content-Type we have no extractor for.
+    UnsupportedContentType(String),
+    // Body larger than max_body_bytes.
+    TooLarge(u64),
+    // 304 Not Modified - the page is unchanged since we last fetched it, so has
+    // nothing new to index.
+    NotModified,
+}
+
+// Failure to fetch a resource - either the request failed or we refused the response.
+#[derive(Debug)]
+enum FetchError {
+    Request(reqwest::Error),
+    Rejected(SkipReason),
+}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(e: reqwest::Error) -> Self {
+        FetchError::Request(e)
+    }
 }
 
+impl From<FetchError> for SkipReason {
+    fn from(e: FetchError) -> Self {
+        match e {
+            FetchError::Request(e) => SkipReason::FetchFailed(e.to_string()),
+            FetchError::Rejected(reason) => reason,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SkippedUrl {
+    pub url: String,
+    pub reason: SkipReason,
+}
+
+// Everything produced by a crawl - fetched pages and the URLs we chose
+// (or were forced) not to fetch.
+#[derive(Debug, Default)]
+pub struct CrawlOutput {
+    pub results: Vec<CrawlResult>,
+    pub skipped: Vec<SkippedUrl>,
+}
+
+// Per-host bookkeeping for rate limiting. The semaphore caps concurrent requests
+// to the host, the schedule hands out request slots at least min interval apart.
+struct HostSlot {
+    permits: Arc<Semaphore>,
+    schedule: Mutex<HostSchedule>,
+}
+
+#[derive(Debug, Default)]
+struct HostSchedule {
+    requests: u32,
+    discovery_requests: u32,
+    next_request: Option<Instant>,
+}
+
+// What a request fetches, which decides the host budget it counts against.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RequestKind {
+    Page,
+    // Sitemaps and feeds.
+    Discovery,
+}
+
+// Politeness state shared by every in-flight fetch of a crawl.
+struct Politeness<'a> {
+    client: Client,
+    config: &'a CrawlConfig,
+    robots: RobotsCache,
+    hosts: Mutex<HashMap<String, Arc<HostSlot>>>,
+}
+
+// Equal where neither comes first in the ordering below - URLs are told apart by url.
+impl PartialEq for UrlToVisit {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for UrlToVisit {}
+
 // IMPLEMENTED:
 // Partial ordering to compare two CrawlResults for priority, based on smallest crawl_depth.
 impl PartialOrd for UrlToVisit {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.crawl_depth.cmp(&other.crawl_depth).reverse())
+        Some(self.cmp(other))
     }
 }
 
 // IMPLEMENTED:
 // Full ordering with error handling layer above to catch errors in comparing tuples.
+// Smallest crawl_depth first, then most recently modified (highest priority) first.
 impl Ord for UrlToVisit {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         self.crawl_depth.cmp(&other.crawl_depth).reverse()
+            .then(self.priority.cmp(&other.priority))
     }
 }
 
-async fn get_crawl_result(url: &str) -> Result<CrawlResult, reqwest::Error> {
+// Reads a response body, giving up once it exceeds max_bytes.
+async fn read_body(mut response: reqwest::Response, max_bytes: u64) -> Result<Vec<u8>, FetchError> {
+    if let Some(length) = response.content_length().filter(|length| *length > max_bytes) {
+        return Err(FetchError::Rejected(SkipReason::TooLarge(length)));
+    }
+
+    let mut body: Vec<u8> = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        // Content-Length may be missing or wrong, so the limit is enforced as we read.
+        if body.len() as u64 > max_bytes {
+            return Err(FetchError::Rejected(SkipReason::TooLarge(body.len() as u64)));
+        }
+    }
+    Ok(body)
+}
+
+// Fetches the page, sending conditional headers where validators are given.
+// Returns None where the server confirms the page is not modified.
+async fn get_crawl_result(client: &Client, url: &str, config: &CrawlConfig, previous: Option<&Validators>) -> Result<Option<CrawlResult>, FetchError> {
     let mut new_urls: Vec<String> = Vec::new();
-    let response = get(url).await?;
-    let body = response.text().await?;
+    let mut request = client.get(url);
+
+    if let Some(previous) = previous {
+        if let Some(etag) = &previous.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &previous.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let response = request.send().await?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+
+    let response = response.error_for_status()?;
+    let header = |name| response.headers().get(name)
+        .and_then(|value: &HeaderValue| value.to_str().ok())
+        .map(String::from);
+    let validators = Validators { etag: header(ETAG), last_modified: header(LAST_MODIFIED) };
+
+    let page_url = response.url().clone();
+    let fetched_at = chrono::Utc::now().timestamp();
+
+    // Decide from the headers whether the resource is worth downloading at all.
+    let (mime, charset) = match header(CONTENT_TYPE) {
+        Some(content_type) => {
+            let (mime, charset) = parse_content_type(&content_type);
+            (Some(mime), charset)
+        }
+        None => (None, None)
+    };
+    let Some(content_type) = content_kind(mime.as_deref(), &page_url) else {
+        return Err(FetchError::Rejected(SkipReason::UnsupportedContentType(mime.unwrap_or_default())));
+    };
+
+    let bytes = read_body(response, config.max_body_bytes).await?;
+
+    // Binary formats are left to their extractor, without links to follow.
+    if !content_type.is_text() {
+        let crawl_result = CrawlResult {
+            url: url.to_string(),
+            new_urls,
+            edges: Vec::new(),
+            body: String::new(),
+            raw: bytes,
+            content_type,
+            validators,
+            fetched_at,
+            feeds: Vec::new(),
+        };
+        return Ok(Some(crawl_result));
+    }
+
+    let body = decode_text(&bytes, charset.as_deref(), content_type);
     let fragment = Html::parse_document(&body);
     let url_selector = Selector::parse("a").unwrap();
-    
+
+    // Resolve links against the final (post-redirect) URL or <base href>,
+    // keeping only canonical http(s) links and dropping duplicates.
+    let base = base_url(&page_url, &fragment);
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut edges: Vec<LinkEdge> = Vec::new();
+
     for element in fragment.select(&url_selector) {
-        if let Some(url) = element.value().attr("href") {
-            new_urls.push(url.to_string());
+        if let Some(href) = element.value().attr("href") {
+            if let Some(link) = resolve(&base, href, &config.tracking_params) {
+                if seen.insert(link.to_string()) {
+                    new_urls.push(link.to_string());
+                }
+                edges.push(LinkEdge {
+                    source: url.to_string(),
+                    target: link.to_string(),
+                    anchor: anchor_text(element),
+                    rel: element.value().attr("rel")
+                        .map(|rel| rel.split_whitespace().map(str::to_lowercase).collect())
+                        .unwrap_or_default(),
+                });
+            }
         }
     }
-    
+
+    let feeds = feed_links(&fragment, &base);
+
     let crawl_result = CrawlResult {
         url: url.to_string(),
         new_urls,
+        edges,
         body,
+        raw: Vec::new(),
+        content_type,
+        validators,
+        fetched_at,
+        feeds,
     };
-    
-    Ok(crawl_result)
-}
-    
 
-async fn crawl(seed_urls: &mut Vec<String>, max_depth: u32) -> Vec<CrawlResult> {
-    // Initialise visited to not re-process
-    let mut visited: Vec<UrlToVisit> = Vec::new();
+    Ok(Some(crawl_result))
+}
 
-    // Initialise priority queue to crawl URL's
-    let mut url_queue: BinaryHeap<UrlToVisit> = BinaryHeap::new();
-    let mut results: Vec<CrawlResult> = Vec::new();
+impl<'a> Politeness<'a> {
+    fn new(config: &'a CrawlConfig) -> Result<Self, reqwest::Error> {
+        // Shared client so every request carries our user agent.
+        let client = Client::builder().user_agent(config.user_agent.as_str()).build()?;
 
-    for seed_url in seed_urls {
-        url_queue.push(UrlToVisit {
-            url: seed_url.to_string(),
-            crawl_depth: 0,
+        Ok(Politeness {
+            client,
+            config,
+            robots: RobotsCache::new(&config.user_agent),
+            hosts: Mutex::new(HashMap::new()),
         })
     }
-        
-    //while the url_queue is not empty and depth is less than 10
-    while !(url_queue.is_empty()) && (url_queue.peek().unwrap().crawl_depth < max_depth) {
 
-        //pop the next url from the queue
-        let next_url = match url_queue.pop() {
-            Some(url) => url,
-            None => {
+    fn host_slot(&self, host: String) -> Arc<HostSlot> {
+        let mut hosts = self.hosts.lock().unwrap();
+        hosts.entry(host).or_insert_with(|| Arc::new(HostSlot {
+            permits: Arc::new(Semaphore::new(self.config.max_per_host.max(1))),
+            schedule: Mutex::new(HostSchedule::default()),
+        })).clone()
+    }
+
+    // Checks robots.txt and the host budget of the kind of request, then waits for a free
+    // per-host slot and the host's minimum interval. Returns the permit to hold for the
+    // duration of the fetch, or the reason to skip the URL if it should not be fetched.
+    async fn wait_for_turn(&self, url: &str, kind: RequestKind) -> Result<OwnedSemaphorePermit, SkipReason> {
+        let parsed = match Url::parse(url) {
+            Ok(parsed) if parsed.host_str().is_some() => parsed,
+            _ => return Err(SkipReason::InvalidUrl)
+        };
+
+        let mut interval = Duration::from_millis(self.config.min_host_interval_ms);
+
+        if self.config.respect_robots {
+            let rules = self.robots.rules_for(&self.client, &parsed).await;
+
+            let mut path = parsed.path().to_string();
+            if let Some(query) = parsed.query() {
+                path = format!("{}?{}", path, query);
+            }
+
+            if !rules.is_allowed(&path) {
+                return Err(SkipReason::DisallowedByRobots);
+            }
+
+            if let Some(delay) = rules.crawl_delay {
+                interval = interval.max(delay);
+            }
+        }
+
+        let slot = self.host_slot(parsed.origin().ascii_serialization());
+
+        // Semaphore is never closed, so acquiring can only fail if that changes.
+        let permit = match slot.permits.clone().acquire_owned().await {
+            Ok(permit) => permit,
+            Err(_) => return Err(SkipReason::HostBudgetExhausted)
+        };
+
+        // Reserve the next request slot for this host, then sleep until it without
+        // holding the lock so other fetches can reserve the slots after ours.
+        let start = {
+            let mut schedule = slot.schedule.lock().unwrap();
+            let (requests, budget) = match kind {
+                RequestKind::Page => (&mut schedule.requests, self.config.max_requests_per_host),
+                RequestKind::Discovery => (&mut schedule.discovery_requests, self.config.max_discovery_requests_per_host),
+            };
+
+            if *requests >= budget {
+                return Err(SkipReason::HostBudgetExhausted);
+            }
+            *requests += 1;
+
+            let now = Instant::now();
+            let start = schedule.next_request.map_or(now, |next| next.max(now));
+            schedule.next_request = Some(start + interval);
+            start
+        };
+
+        tokio::time::sleep_until(start.into()).await;
+        Ok(permit)
+    }
+
+    // Fetches a single URL once politeness allows it.
+    async fn visit(&self, url: &str) -> Result<CrawlResult, SkipReason> {
+        let _permit = self.wait_for_turn(url, RequestKind::Page).await?;
+
+        match get_crawl_result(&self.client, url, self.config, None).await {
+            Ok(Some(result)) => Ok(result),
+            Ok(None) => Err(SkipReason::NotModified),
+            Err(e) => Err(e.into())
+        }
+    }
+
+    // Fetches a sitemap or feed once politeness allows it.
+    async fn fetch_bytes(&self, url: &str) -> Result<Vec<u8>, SkipReason> {
+        let _permit = self.wait_for_turn(url, RequestKind::Discovery).await?;
+
+        let response = self.client.get(url).send().await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| SkipReason::FetchFailed(e.to_string()))?;
+
+        read_body(response, self.config.max_body_bytes).await.map_err(SkipReason::from)
+    }
+
+    // Reads the sitemaps for the URL's host (following sitemap indexes), returning at
+    // most max_sitemap_urls entries with the most recently modified first.
+    async fn sitemap_entries(&self, url: &Url) -> Vec<SitemapEntry> {
+        let origin = url.origin().ascii_serialization();
+        let rules = self.robots.rules_for(&self.client, url).await;
+
+        let mut pending: Vec<String> = if rules.sitemaps.is_empty() {
+            well_known_sitemaps(&origin)
+        }
+        else {
+            rules.sitemaps.clone()
+        };
+
+        let mut read: HashSet<String> = HashSet::new();
+        let mut entries: Vec<SitemapEntry> = Vec::new();
+
+        while let Some(sitemap_url) = pending.pop() {
+            if read.len() >= MAX_SITEMAP_FILES || !read.insert(sitemap_url.clone()) {
                 continue;
             }
+
+            // Missing well-known sitemaps are expected, so failures are not reported.
+            let Ok(bytes) = self.fetch_bytes(&sitemap_url).await else { continue };
+            let Some(body) = decode_body(&bytes) else { continue };
+
+            match parse_sitemap(&body) {
+                Sitemap::Index(sitemaps) => pending.extend(sitemaps),
+                Sitemap::UrlSet(urls) => entries.extend(urls),
+            }
+        }
+
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.lastmod));
+        entries.truncate(self.config.max_sitemap_urls);
+        entries
+    }
+
+    async fn feed_items(&self, feed_url: &str) -> Vec<FeedItem> {
+        let Ok(parsed) = Url::parse(feed_url) else { return Vec::new() };
+
+        match self.fetch_bytes(feed_url).await {
+            Ok(bytes) => parse_feed(&String::from_utf8_lossy(&bytes), &parsed),
+            Err(reason) => {
+                eprintln!("Could not read feed {}: {:?}", feed_url, reason);
+                Vec::new()
+            }
+        }
+    }
+
+    // Conditionally refetches a previously indexed URL.
+    async fn revisit(&self, target: &RevisitTarget) -> Revisit {
+        let _permit = match self.wait_for_turn(&target.url, RequestKind::Page).await {
+            Ok(permit) => permit,
+            Err(SkipReason::DisallowedByRobots) => return Revisit::Gone,
+            Err(reason) => return Revisit::Failed(reason)
         };
-            
-        // If not visited
-        if !(visited.contains(&next_url)) {
-
-            // Increment depth for new crawl result
-            let new_depth: u32 = next_url.crawl_depth + 1;
-                
-            // Get body response from get request - do NOT propagate error to the caller,
-            // Simply print out the error response if it happens internally.
-            let crawl_result;
-
-            match get_crawl_result(&next_url.url).await {
-                Ok(result) => crawl_result = result,
-                Err(_) => continue
-            }
- 
+
+        match get_crawl_result(&self.client, &target.url, self.config, Some(&target.validators)).await {
+            Ok(Some(result)) => Revisit::Modified(result),
+            Ok(None) => Revisit::Unchanged,
+            Err(FetchError::Request(e)) if matches!(e.status(), Some(StatusCode::NOT_FOUND) | Some(StatusCode::GONE)) => Revisit::Gone,
+            // A page that turned into something we do not index is as good as gone.
+            Err(FetchError::Rejected(SkipReason::UnsupportedContentType(_))) => Revisit::Gone,
+            Err(e) => Revisit::Failed(e.into())
+        }
+    }
+}
+
+
+// Canonicalises and scope checks a URL, then queues it unless already visited.
+// Returns whether the URL was queued.
+fn enqueue(state: &mut CrawlState, config: &CrawlConfig, known_domains: &HashSet<String>, url: &str, crawl_depth: u32, seed_domain: &str, priority: i64) -> bool {
+    let Some(url) = Url::parse(url).ok().and_then(|url| normalise(url, &config.tracking_params)) else {
+        return false
+    };
+
+    if !in_scope(&url, config.scope, seed_domain, known_domains) || !state.visited.insert(url.to_string()) {
+        return false
+    }
+
+    state.frontier.push(UrlToVisit {
+        url: url.to_string(),
+        crawl_depth,
+        seed_domain: seed_domain.to_string(),
+        priority,
+    });
+    true
+}
+
+// Saves the checkpoint if there is one, logging rather than failing the crawl.
+fn save_checkpoint(checkpoint: &mut Option<Checkpoint>, state: &mut CrawlState) {
+    if let Some(checkpoint) = checkpoint {
+        if let Err(e) = checkpoint.save(state) {
+            eprintln!("Could not save crawl checkpoint: {}", e);
+        }
+    }
+}
+
+async fn crawl(seed_urls: &[String], max_depth: u32, config: &CrawlConfig, known_domains: &HashSet<String>) -> CrawlOutput {
+    // Restore the frontier, visited set and fetched pages from the last checkpoint.
+    // Visited is keyed by canonical URL, and URLs are marked visited when first queued
+    // so the same page is never queued twice.
+    let mut state = CrawlState::default();
+    let mut results: Vec<CrawlResult> = Vec::new();
+    let mut checkpoint: Option<Checkpoint> = None;
+
+    if config.checkpoint_every > 0 {
+        match Checkpoint::open(&config.checkpoint_dir, config.resume) {
+            Ok((opened, previous)) => {
+                if let Some((previous_state, previous_results)) = previous {
+                    println!("Resuming crawl with {} pages fetched and {} URLs queued",
+                        previous_results.len(), previous_state.frontier.len() + previous_state.in_flight.len());
+                    state = previous_state;
+                    results = previous_results;
+                }
+                checkpoint = Some(opened);
+            }
+            Err(e) => eprintln!("Could not open crawl checkpoint, crawling without one: {}", e)
+        }
+    }
+
+    // URLs interrupted mid-fetch or deferred for host budget are queued again, and
+    // dropped from the skipped list as they get another chance now.
+    let retry: Vec<UrlToVisit> = state.in_flight.drain().map(|(_, next_url)| next_url).chain(state.deferred.drain(..)).collect();
+    let retry_urls: HashSet<&str> = retry.iter().map(|next_url| next_url.url.as_str()).collect();
+    state.skipped.retain(|skipped| !retry_urls.contains(skipped.url.as_str()));
+    drop(retry_urls);
+    state.frontier.extend(retry);
+    state.max_depth = state.max_depth.max(max_depth);
+
+    let politeness = match Politeness::new(config) {
+        Ok(politeness) => politeness,
+        Err(e) => {
+            eprintln!("Could not build crawl client: {}", e);
+            return CrawlOutput { results, skipped: state.skipped }
+        }
+    };
+
+    for seed_url in seed_urls.iter() {
+        if !state.seeds.contains(seed_url) {
+            state.seeds.push(seed_url.to_string());
+        }
+
+        // Seeds are canonicalised the same way as extracted links.
+        let seed = Url::parse(seed_url.trim()).ok()
+            .and_then(|url| normalise(url, &config.tracking_params));
+
+        match seed {
+            Some(url) => {
+                if state.visited.insert(url.to_string()) {
+                    state.frontier.push(UrlToVisit {
+                        seed_domain: registered_domain(&url).unwrap_or_default(),
+                        url: url.to_string(),
+                        crawl_depth: 0,
+                        priority: 0,
+                    })
+                }
+            }
+            None => state.skipped.push(SkippedUrl { url: seed_url.to_string(), reason: SkipReason::InvalidUrl })
+        }
+    }
+
+    // Expand seeds with their hosts' sitemaps - once per host, including across resumes.
+    if config.use_sitemaps {
+        let seed_hosts: Vec<UrlToVisit> = state.frontier.iter()
+            .filter(|next_url| next_url.crawl_depth == 0)
+            .filter(|next_url| Url::parse(&next_url.url)
+                .is_ok_and(|parsed| state.sitemap_hosts.insert(parsed.origin().ascii_serialization())))
+            .cloned()
+            .collect();
+
+        let sitemaps: Vec<(UrlToVisit, Vec<SitemapEntry>)> = stream::iter(seed_hosts)
+            .map(|seed| {
+                let politeness = &politeness;
+                async move {
+                    let entries = match Url::parse(&seed.url) {
+                        Ok(parsed) => politeness.sitemap_entries(&parsed).await,
+                        Err(_) => Vec::new()
+                    };
+                    (seed, entries)
+                }
+            })
+            .buffer_unordered(config.max_in_flight.max(1))
+            .collect()
+            .await;
+
+        for (seed, entries) in sitemaps {
+            let queued = entries.into_iter()
+                .filter(|entry| enqueue(&mut state, config, known_domains, &entry.url, 0, &seed.seed_domain, entry.lastmod.unwrap_or(0)))
+                .count();
+            println!("Queued {} URLs from sitemaps of {}", queued, seed.url);
+        }
+    }
+
+    let mut unsaved_pages = 0;
+
+    // Breadth first - every URL at the current depth is fetched (concurrently) before
+    // any URL at the next depth is started. URLs beyond max_depth stay on the frontier
+    // so a later crawl with more depth can pick them up.
+    while let Some(depth) = state.frontier.peek().map(|next_url| next_url.crawl_depth) {
+        if depth >= max_depth {
+            break;
+        }
+
+        while state.frontier.peek().is_some_and(|next_url| next_url.crawl_depth == depth) {
+            if let Some(next_url) = state.frontier.pop() {
+                state.in_flight.insert(next_url.url.clone(), next_url);
+            }
+        }
+
+        // Get body responses with at most max_in_flight requests running - do NOT propagate
+        // errors to the caller, simply record them alongside the skipped URLs.
+        let level: Vec<UrlToVisit> = state.in_flight.values().cloned().collect();
+        let mut feeds: Vec<(String, String)> = Vec::new();
+        let mut fetches = stream::iter(level)
+            .map(|next_url| {
+                let politeness = &politeness;
+                async move {
+                    let outcome = politeness.visit(&next_url.url).await;
+                    (next_url, outcome)
+                }
+            })
+            .buffer_unordered(config.max_in_flight.max(1));
+
+        while let Some((next_url, outcome)) = fetches.next().await {
+            state.in_flight.remove(&next_url.url);
+
+            let crawl_result = match outcome {
+                Ok(result) => result,
+                Err(reason) => {
+                    if reason == SkipReason::HostBudgetExhausted {
+                        state.deferred.push(next_url.clone());
+                    }
+                    state.skipped.push(SkippedUrl { url: next_url.url, reason });
+                    continue
+                }
+            };
+
             for url in &crawl_result.new_urls {
-                url_queue.push( UrlToVisit {
-                    url: url.to_string(),
-                    crawl_depth: new_depth,
-                })
+                enqueue(&mut state, config, known_domains, url, depth + 1, &next_url.seed_domain, 0);
+            }
+
+            // Feeds are read once the level is done, marked visited so they are read once.
+            if config.follow_feeds {
+                for feed in &crawl_result.feeds {
+                    if state.visited.insert(feed.to_string()) {
+                        feeds.push((feed.to_string(), next_url.seed_domain.clone()));
+                    }
+                }
+            }
+
+            // Log the page before saving any state that depends on it.
+            if let Some(opened) = checkpoint.as_mut() {
+                if let Err(e) = opened.append_page(&crawl_result) {
+                    eprintln!("Could not checkpoint {}: {}", crawl_result.url, e);
+                }
             }
 
             // Also add the URL to results.
             results.push(crawl_result);
-    
-            // Add the URL now to visited
-            visited.push(next_url);
+
+            unsaved_pages += 1;
+            if unsaved_pages >= config.checkpoint_every {
+                save_checkpoint(&mut checkpoint, &mut state);
+                unsaved_pages = 0;
+            }
         }
 
+        drop(fetches);
+
+        // Feed items join the level of the page linking the feed - seeds' feeds that of
+        // the seeds - ordered by their updated time. They are fetched before the next
+        // level is started, so the crawl stays breadth first.
+        let items: Vec<(String, Vec<FeedItem>)> = stream::iter(feeds)
+            .map(|(feed, seed_domain)| {
+                let politeness = &politeness;
+                async move { (seed_domain, politeness.feed_items(&feed).await) }
+            })
+            .buffer_unordered(config.max_in_flight.max(1))
+            .collect()
+            .await;
+
+        for (seed_domain, feed_items) in items {
+            for item in feed_items {
+                enqueue(&mut state, config, known_domains, &item.url, depth, &seed_domain, item.updated.unwrap_or(0));
+            }
+        }
+
+        save_checkpoint(&mut checkpoint, &mut state);
+        unsaved_pages = 0;
     }
 
-    results
+    save_checkpoint(&mut checkpoint, &mut state);
+
+    CrawlOutput { results, skipped: state.skipped }
 
 }
 
-    
 
-pub async fn get_crawled (seed_urls: Vec<String>, max_depth: u32) -> Vec<CrawlResult> {
-    let mut seed_urls = seed_urls;
-    let results = crawl(&mut seed_urls, max_depth).await;
-    results
+
+// known_domains are the university domains from discover.rs, only used where
+// config.scope is LinkScope::KnownDomains.
+pub async fn get_crawled (seed_urls: Vec<String>, max_depth: u32, config: &CrawlConfig, known_domains: &[String]) -> CrawlOutput {
+    let known_domains: HashSet<String> = known_domains.iter().map(|domain| domain.to_lowercase()).collect();
+    crawl(&seed_urls, max_depth, config, &known_domains).await
+}
+
+// Revisits previously indexed URLs with conditional requests, under the same
+// politeness and concurrency limits as a crawl.
+pub async fn revisit(targets: Vec<RevisitTarget>, config: &CrawlConfig) -> Vec<(String, Revisit)> {
+    let politeness = match Politeness::new(config) {
+        Ok(politeness) => politeness,
+        Err(e) => {
+            eprintln!("Could not build crawl client: {}", e);
+            return Vec::new()
+        }
+    };
+
+    stream::iter(targets)
+        .map(|target| {
+            let politeness = &politeness;
+            async move {
+                let outcome = politeness.revisit(&target).await;
+                (target.url, outcome)
+            }
+        })
+        .buffer_unordered(config.max_in_flight.max(1))
+        .collect()
+        .await
 }
 
+// Reads the given feeds, used by recrawls to find changed and new pages cheaply.
+pub async fn read_feeds(feeds: Vec<String>, config: &CrawlConfig) -> Vec<FeedItem> {
+    let politeness = match Politeness::new(config) {
+        Ok(politeness) => politeness,
+        Err(e) => {
+            eprintln!("Could not build crawl client: {}", e);
+            return Vec::new()
+        }
+    };
 
+    stream::iter(feeds)
+        .map(|feed| {
+            let politeness = &politeness;
+            async move { politeness.feed_items(&feed).await }
+        })
+        .buffer_unordered(config.max_in_flight.max(1))
+        .collect::<Vec<Vec<FeedItem>>>()
+        .await
+        .into_iter()
+        .flatten()
+        .collect()
+}
@@ -0,0 +1,256 @@
+// Module finds near-duplicate documents - mirrored course pages, printer-friendly
+// versions, the same paper hosted by several universities - by the MinHash signature
+// of their content's word shingles, and collapses each cluster into one canonical
+// document that keeps the other URLs as alternates.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use url::Url;
+use crate::language::segment;
+use crate::links::registered_domain;
+use crate::parser::Document;
+
+
+// Words per shingle.
+const SHINGLE_WORDS: usize = 3;
+// Documents with fewer shingles than this are too short to compare reliably.
+const MIN_SHINGLES: usize = 8;
+// Hash functions in a signature, split into bands of rows for locality sensitive
+// hashing - documents sharing any band exactly are compared.
+const SIGNATURE_HASHES: usize = 64;
+const BAND_ROWS: usize = 4;
+// Estimated Jaccard similarity of shingles above which documents are near-duplicates.
+const MIN_SIMILARITY: f64 = 0.8;
+
+fn fnv1a(text: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in text.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+// Hash function i of the signature - SplitMix64 over the shingle hash and a seed.
+fn seeded(hash: u64, seed: u64) -> u64 {
+    let mut z = hash ^ seed.wrapping_mul(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+// MinHash signature of the content's word shingles - the minimum of each hash function
+// over the shingles. Empty where the content is too short to compare.
+pub fn minhash(content: &[String]) -> Vec<u64> {
+    let words: Vec<String> = content.iter()
+        .flat_map(|text| segment(text))
+        .map(|word| word.chars().filter(|c| c.is_alphanumeric()).collect::<String>())
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    if words.len() < SHINGLE_WORDS + MIN_SHINGLES - 1 {
+        return Vec::new();
+    }
+
+    let shingles: Vec<u64> = words.windows(SHINGLE_WORDS).map(|shingle| fnv1a(&shingle.join(" "))).collect();
+    (0..SIGNATURE_HASHES as u64)
+        .map(|seed| shingles.iter().map(|hash| seeded(*hash, seed)).min().unwrap_or(u64::MAX))
+        .collect()
+}
+
+// Share of hash functions on which the signatures agree, an estimate of the Jaccard
+// similarity of the shingle sets.
+fn similarity(a: &[u64], b: &[u64]) -> f64 {
+    if a.is_empty() || a.len() != b.len() {
+        return 0.0;
+    }
+    a.iter().zip(b).filter(|(x, y)| x == y).count() as f64 / a.len() as f64
+}
+
+fn find(parents: &mut [usize], index: usize) -> usize {
+    let mut root = index;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    // Path compression.
+    let mut index = index;
+    while parents[index] != root {
+        let next = parents[index];
+        parents[index] = root;
+        index = next;
+    }
+    root
+}
+
+fn union(parents: &mut [usize], a: usize, b: usize) {
+    let (root_a, root_b) = (find(parents, a), find(parents, b));
+    if root_a != root_b {
+        parents[root_b] = root_a;
+    }
+}
+
+// University and academic hosts (.edu, .ac.uk, .edu.au etc.) are preferred as canonical.
+fn is_academic(url: &Url) -> bool {
+    registered_domain(url).is_some_and(|domain| domain.split('.').skip(1).any(|label| matches!(label, "edu" | "ac")))
+}
+
+// Collapses each cluster of near-duplicates - by MinHash, or by one declaring another
+// as its rel=canonical - into a single document. The representative is the URL the
+// cluster declares canonical most often, then one on an academic host, then the one
+// with the most inbound anchor text, then https, then the shortest URL. The other
+// URLs are kept on it as alternates.
+pub fn collapse_duplicates(documents: Vec<Document>) -> Vec<Document> {
+    let mut parents: Vec<usize> = (0..documents.len()).collect();
+
+    let mut bands: HashMap<(usize, &[u64]), Vec<usize>> = HashMap::new();
+    for (index, document) in documents.iter().enumerate() {
+        for (band, rows) in document.minhash.chunks(BAND_ROWS).enumerate() {
+            bands.entry((band, rows)).or_default().push(index);
+        }
+    }
+
+    for candidates in bands.values() {
+        for (position, &a) in candidates.iter().enumerate() {
+            for &b in &candidates[position + 1..] {
+                if similarity(&documents[a].minhash, &documents[b].minhash) >= MIN_SIMILARITY {
+                    union(&mut parents, a, b);
+                }
+            }
+        }
+    }
+
+    let by_url: HashMap<&str, usize> = documents.iter().enumerate()
+        .flat_map(|(index, document)| std::iter::once(document.url.as_str())
+            .chain(document.alternates.iter().map(String::as_str))
+            .map(move |url| (url, index)))
+        .collect();
+    for (index, document) in documents.iter().enumerate() {
+        if let Some(&target) = document.canonical_url.as_deref().and_then(|canonical| by_url.get(canonical)) {
+            union(&mut parents, index, target);
+        }
+    }
+
+    let mut clusters: HashMap<usize, Vec<usize>> = HashMap::new();
+    for index in 0..documents.len() {
+        let root = find(&mut parents, index);
+        clusters.entry(root).or_default().push(index);
+    }
+    // Clusters in the order of their first document, so the output follows the input.
+    let mut clusters: Vec<Vec<usize>> = clusters.into_values().collect();
+    clusters.sort_unstable_by_key(|members| members[0]);
+
+    let mut slots: Vec<Option<Document>> = documents.into_iter().map(Some).collect();
+    let mut collapsed: Vec<Document> = Vec::new();
+
+    for members in clusters {
+        let mut documents: Vec<Document> = members.into_iter().filter_map(|index| slots[index].take()).collect();
+        if documents.len() == 1 {
+            collapsed.extend(documents);
+            continue;
+        }
+
+        let votes = |url: &str| documents.iter().filter(|document| document.canonical_url.as_deref() == Some(url)).count();
+        let best = documents.iter()
+            .enumerate()
+            .max_by_key(|(_, document)| {
+                let parsed = Url::parse(&document.url).ok();
+                (
+                    votes(&document.url),
+                    parsed.as_ref().is_some_and(is_academic),
+                    document.anchor_text.len(),
+                    parsed.as_ref().is_some_and(|url| url.scheme() == "https"),
+                    Reverse(document.url.len()),
+                    Reverse(document.url.clone()),
+                )
+            })
+            .map(|(index, _)| index)
+            .unwrap_or(0);
+
+        let mut canonical = documents.swap_remove(best);
+        for duplicate in documents {
+            canonical.alternates.push(duplicate.url);
+            canonical.alternates.extend(duplicate.alternates);
+        }
+        canonical.alternates.sort();
+        canonical.alternates.dedup();
+        collapsed.push(canonical);
+    }
+
+    collapsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "The heat equation describes how temperature changes over time in a solid. \
+        Fourier solved it by writing the initial temperature as a sum of sines and cosines, \
+        each of which decays at its own rate as the solid cools towards equilibrium.";
+    const UNRELATED: &str = "Lecture notes on graph algorithms cover breadth first search, \
+        depth first search, shortest paths with Dijkstra and minimum spanning trees with Kruskal and Prim.";
+
+    fn document(url: &str, content: &str, canonical_url: Option<&str>) -> Document {
+        let mut document = Document::default();
+        document.url = String::from(url);
+        document.minhash = minhash(&[String::from(content)]);
+        document.canonical_url = canonical_url.map(String::from);
+        document
+    }
+
+    fn urls(documents: &[Document]) -> Vec<&str> {
+        documents.iter().map(|document| document.url.as_str()).collect()
+    }
+
+    #[test]
+    fn identical_content_has_identical_signatures() {
+        let signature = minhash(&[String::from(TEXT)]);
+        assert_eq!(signature.len(), SIGNATURE_HASHES);
+        assert_eq!(signature, minhash(&[String::from(TEXT)]));
+        assert_eq!(similarity(&signature, &signature), 1.0);
+    }
+
+    #[test]
+    fn similar_content_scores_above_unrelated_content() {
+        let signature = minhash(&[String::from(TEXT)]);
+        let edited = minhash(&[TEXT.replace("solid cools", "solid slowly cools")]);
+        let unrelated = minhash(&[String::from(UNRELATED)]);
+
+        assert!(similarity(&signature, &edited) >= MIN_SIMILARITY);
+        assert!(similarity(&signature, &unrelated) < 0.2);
+    }
+
+    #[test]
+    fn short_content_is_not_compared() {
+        let short = minhash(&[String::from("Heat equation notes")]);
+        assert!(short.is_empty());
+        assert_eq!(similarity(&short, &short), 0.0);
+    }
+
+    #[test]
+    fn collapses_onto_the_declared_canonical_url() {
+        let collapsed = collapse_duplicates(vec![
+            document("https://example.org/heat?print=1", TEXT, Some("https://example.org/heat")),
+            document("https://example.org/graphs", UNRELATED, None),
+            document("https://example.org/heat", TEXT, None),
+            // Too short to compare, joined by its rel=canonical alone.
+            document("https://example.org/heat/summary", "Heat equation notes", Some("https://example.org/heat")),
+            document("https://www.example.org/heat", TEXT, Some("https://example.org/heat")),
+        ]);
+
+        assert_eq!(urls(&collapsed), vec!["https://example.org/heat", "https://example.org/graphs"]);
+        assert_eq!(collapsed[0].alternates, vec!["https://example.org/heat/summary", "https://example.org/heat?print=1", "https://www.example.org/heat"]);
+        assert!(collapsed[1].alternates.is_empty());
+    }
+
+    #[test]
+    fn prefers_academic_hosts_without_a_declared_canonical() {
+        let collapsed = collapse_duplicates(vec![
+            document("https://mirror.example.com/notes/heat.html", TEXT, None),
+            document("http://www.math.example.edu/~lovelace/heat.html", TEXT, None),
+            document("https://example.org/graphs", UNRELATED, None),
+        ]);
+
+        assert_eq!(urls(&collapsed), vec!["http://www.math.example.edu/~lovelace/heat.html", "https://example.org/graphs"]);
+        assert_eq!(collapsed[0].alternates, vec!["https://mirror.example.com/notes/heat.html"]);
+    }
+}
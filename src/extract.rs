@@ -0,0 +1,182 @@
+// Module classifies fetched resources by content type, decodes text bodies in their
+// declared (or sniffed) charset, and routes non-HTML resources to the extractor for
+// their format. HTML itself is handled by the parser.
+
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+use serde::{Serialize, Deserialize};
+use url::Url;
+use crate::crawl::CrawlResult;
+use crate::pdf::extract_pdf;
+use crate::office::{extract_docx, extract_odp, extract_pptx};
+
+
+// Bytes searched for a <meta charset>, as in the HTML prescan.
+const CHARSET_PRESCAN_BYTES: usize = 1024;
+// Length descriptions are cut to where a resource gives none.
+const DESCRIPTION_CHARS: usize = 200;
+
+// Resource types the crawler fetches - anything else is skipped before download.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ContentKind {
+    #[default]
+    Html,
+    PlainText,
+    Pdf,
+    Docx,
+    Pptx,
+    Odp,
+}
+
+impl ContentKind {
+    // Whether the body is text to be decoded, rather than a binary format.
+    pub fn is_text(self) -> bool {
+        matches!(self, ContentKind::Html | ContentKind::PlainText)
+    }
+}
+
+// Text pulled out of a non-HTML resource, made into a Document by the parser.
+#[derive(Debug, Default)]
+pub struct Extracted {
+    pub title: String,
+    pub description: String,
+    pub content: Vec<String>,
+    // Slide and section titles, for formats that mark them (empty otherwise).
+    pub headings: Vec<String>,
+    // Page number of each content entry, for paged formats (empty otherwise).
+    pub pages: Vec<u32>,
+}
+
+// Splits a Content-Type header into its lower case MIME type and charset parameter.
+pub fn parse_content_type(header: &str) -> (String, Option<String>) {
+    let mut parts = header.split(';');
+    let mime = parts.next().unwrap_or_default().trim().to_lowercase();
+    let charset = parts
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+        .map(|(_, value)| value.trim().trim_matches(|c| c == '"' || c == '\'').to_string());
+
+    (mime, charset)
+}
+
+// Kind of resource from its MIME type, falling back on the URL's extension where
+// the server gives no type or a generic one. None for types we do not index.
+pub fn content_kind(mime: Option<&str>, url: &Url) -> Option<ContentKind> {
+    match mime {
+        Some("text/html") | Some("application/xhtml+xml") => Some(ContentKind::Html),
+        Some("text/plain") => Some(ContentKind::PlainText),
+        Some("application/pdf") | Some("application/x-pdf") => Some(ContentKind::Pdf),
+        Some("application/vnd.openxmlformats-officedocument.wordprocessingml.document") => Some(ContentKind::Docx),
+        Some("application/vnd.openxmlformats-officedocument.presentationml.presentation") => Some(ContentKind::Pptx),
+        Some("application/vnd.oasis.opendocument.presentation") => Some(ContentKind::Odp),
+        // Servers without the office types configured often send these generic zip types.
+        Some("application/zip") | Some("application/x-zip-compressed") => kind_from_extension(url),
+        None | Some("") | Some("application/octet-stream") => kind_from_extension(url),
+        Some(_) => None,
+    }
+}
+
+fn kind_from_extension(url: &Url) -> Option<ContentKind> {
+    let path = url.path().to_lowercase();
+    let extension = match path.rsplit_once('/') {
+        Some((_, name)) => name.rsplit_once('.').map(|(_, extension)| extension),
+        None => None,
+    };
+
+    match extension {
+        None | Some("html") | Some("htm") | Some("xhtml") | Some("php") | Some("asp") | Some("aspx") => Some(ContentKind::Html),
+        Some("txt") | Some("text") => Some(ContentKind::PlainText),
+        Some("pdf") => Some(ContentKind::Pdf),
+        Some("docx") => Some(ContentKind::Docx),
+        Some("pptx") => Some(ContentKind::Pptx),
+        Some("odp") => Some(ContentKind::Odp),
+        Some(_) => None,
+    }
+}
+
+// Decodes a text body. The encoding is taken from a byte order mark, then the
+// Content-Type charset, then (for HTML) a <meta charset> in the first 1024 bytes.
+// Without any of those, valid UTF-8 is assumed and anything else read as Windows-1252.
+pub fn decode_text(bytes: &[u8], charset: Option<&str>, kind: ContentKind) -> String {
+    let declared = charset
+        .and_then(|label| Encoding::for_label(label.as_bytes()))
+        .or_else(|| match kind {
+            ContentKind::Html => meta_charset(bytes),
+            _ => None,
+        });
+
+    let encoding = declared.unwrap_or_else(|| {
+        if std::str::from_utf8(bytes).is_ok() { UTF_8 } else { WINDOWS_1252 }
+    });
+
+    // decode() lets a byte order mark override the given encoding.
+    let (text, _, _) = encoding.decode(bytes);
+    text.into_owned()
+}
+
+// Finds charset= in <meta charset="..."> or <meta http-equiv content="...; charset=...">.
+fn meta_charset(bytes: &[u8]) -> Option<&'static Encoding> {
+    let prescan = &bytes[..bytes.len().min(CHARSET_PRESCAN_BYTES)];
+    let head = String::from_utf8_lossy(prescan).to_lowercase();
+
+    for (start, _) in head.match_indices("<meta") {
+        let tag = &head[start..];
+        let tag = &tag[..tag.find('>').unwrap_or(tag.len())];
+
+        if let Some(position) = tag.find("charset=") {
+            let label: String = tag[position + "charset=".len()..]
+                .trim_start_matches(['"', '\''])
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':' | '.'))
+                .collect();
+
+            // A page that claims UTF-16 in ASCII-compatible bytes is really UTF-8.
+            return match Encoding::for_label(label.as_bytes()) {
+                Some(encoding) if encoding.is_single_byte() || encoding == UTF_8 => Some(encoding),
+                Some(_) => Some(UTF_8),
+                None => None,
+            };
+        }
+    }
+    None
+}
+
+// Routes a fetched non-HTML resource to the extractor for its format.
+pub fn extract(crawl_result: &CrawlResult) -> Result<Extracted, String> {
+    match crawl_result.content_type {
+        ContentKind::PlainText => Ok(extract_plain_text(&crawl_result.body)),
+        ContentKind::Pdf => extract_pdf(&crawl_result.raw),
+        ContentKind::Docx => extract_docx(&crawl_result.raw, &crawl_result.url),
+        ContentKind::Pptx => extract_pptx(&crawl_result.raw, &crawl_result.url),
+        ContentKind::Odp => extract_odp(&crawl_result.raw, &crawl_result.url),
+        ContentKind::Html => Err(String::from("HTML is parsed, not extracted")),
+    }
+}
+
+// Plain text - the first line is taken as the title, and each paragraph (separated
+// by blank lines) as a content entry.
+fn extract_plain_text(body: &str) -> Extracted {
+    let mut content: Vec<String> = Vec::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+
+    for line in body.lines().map(str::trim).chain(std::iter::once("")) {
+        if line.is_empty() {
+            if !paragraph.is_empty() {
+                content.push(paragraph.join(" "));
+                paragraph.clear();
+            }
+        }
+        else {
+            paragraph.push(line);
+        }
+    }
+
+    let title = body.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or_default().to_string();
+    let description = describe(&content);
+
+    Extracted { title, description, content, headings: Vec::new(), pages: Vec::new() }
+}
+
+// Description for resources without one - the start of their first content entry.
+pub fn describe(content: &[String]) -> String {
+    content.first().map(|first| first.chars().take(DESCRIPTION_CHARS).collect()).unwrap_or_default()
+}
@@ -0,0 +1,199 @@
+// Module finds RSS/Atom feeds linked from crawled pages and parses their items,
+// which are used both as crawl seeds and as a cheap signal of which pages changed.
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use scraper::{Html, Selector};
+use url::Url;
+use crate::sitemap::parse_datetime;
+
+
+#[derive(Debug, Clone)]
+pub struct FeedItem {
+    pub url: String,
+    // Unix timestamp of the item's updated / published / pubDate, where given.
+    pub updated: Option<i64>,
+}
+
+// Feeds advertised through <link rel="alternate" type="application/rss+xml"> (or atom+xml).
+pub fn feed_links(document: &Html, base: &Url) -> Vec<String> {
+    let feed_selector = Selector::parse("link[rel][type][href]").unwrap();
+    let mut feeds: Vec<String> = Vec::new();
+
+    for element in document.select(&feed_selector) {
+        let value = element.value();
+        let is_alternate = value.attr("rel").is_some_and(|rel| {
+            rel.split_whitespace().any(|token| token.eq_ignore_ascii_case("alternate"))
+        });
+        let is_feed = value.attr("type").is_some_and(|kind| {
+            let kind = kind.trim().to_lowercase();
+            kind == "application/rss+xml" || kind == "application/atom+xml"
+        });
+
+        if is_alternate && is_feed {
+            if let Some(feed) = value.attr("href").and_then(|href| base.join(href.trim()).ok()) {
+                if !feeds.contains(&feed.to_string()) {
+                    feeds.push(feed.to_string());
+                }
+            }
+        }
+    }
+
+    feeds
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Option<String> {
+    element.try_get_attribute(name).ok()?
+        .and_then(|attribute| attribute.unescape_value().ok())
+        .map(|value| value.trim().to_string())
+}
+
+// Link of an Atom <link> element, where it points at the entry itself.
+fn atom_link(element: &BytesStart) -> Option<String> {
+    let rel = attribute(element, "rel").unwrap_or_else(|| String::from("alternate"));
+    if rel == "alternate" { attribute(element, "href") } else { None }
+}
+
+// Reads the text of an item's child element into its link or updated time.
+fn read_text(current: &str, text: &str, link: &mut Option<String>, updated: &mut Option<i64>) {
+    match current {
+        "link" if link.is_none() => *link = Some(text.trim().to_string()),
+        // Atom prefers <updated>, RSS only has <pubDate> (or dc:date).
+        "updated" => *updated = parse_datetime(text).or(*updated),
+        "published" | "pubdate" | "date" if updated.is_none() => *updated = parse_datetime(text),
+        _ => ()
+    }
+}
+
+// Parses RSS 2.0, RSS 1.0 (RDF) and Atom feeds into their items. Relative item
+// links are resolved against the feed URL.
+pub fn parse_feed(body: &str, feed_url: &Url) -> Vec<FeedItem> {
+    let mut reader = Reader::from_str(body);
+    reader.config_mut().trim_text(true);
+
+    let mut items: Vec<FeedItem> = Vec::new();
+    let mut in_item = false;
+    let mut current = String::new();
+    let mut link: Option<String> = None;
+    let mut updated: Option<i64> = None;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(element)) => {
+                current = String::from_utf8_lossy(element.local_name().as_ref()).to_lowercase();
+                match current.as_str() {
+                    "item" | "entry" => {
+                        in_item = true;
+                        link = None;
+                        updated = None;
+                        // RSS 1.0 items carry their URL as rdf:about.
+                        if let Some(about) = attribute(&element, "rdf:about") {
+                            link = Some(about);
+                        }
+                    }
+                    "link" if in_item && link.is_none() => link = atom_link(&element).or(link),
+                    _ => ()
+                }
+            }
+            Ok(Event::Empty(element)) => {
+                let name = String::from_utf8_lossy(element.local_name().as_ref()).to_lowercase();
+                if in_item && name == "link" && link.is_none() {
+                    link = atom_link(&element);
+                }
+            }
+            Ok(Event::Text(text)) if in_item => {
+                if let Ok(text) = text.unescape() {
+                    read_text(&current, &text, &mut link, &mut updated);
+                }
+            }
+            Ok(Event::CData(text)) if in_item => {
+                read_text(&current, &String::from_utf8_lossy(&text), &mut link, &mut updated);
+            }
+            Ok(Event::End(element)) => {
+                let name = String::from_utf8_lossy(element.local_name().as_ref()).to_lowercase();
+                if in_item && (name == "item" || name == "entry") {
+                    in_item = false;
+                    if let Some(url) = link.take().and_then(|url| feed_url.join(&url).ok()) {
+                        items.push(FeedItem { url: url.to_string(), updated });
+                    }
+                }
+                current.clear();
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => {
+                eprintln!("Malformed feed {}: {}", feed_url, e);
+                break
+            }
+            _ => ()
+        }
+    }
+
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(body: &str, feed_url: &str) -> Vec<(String, Option<i64>)> {
+        parse_feed(body, &Url::parse(feed_url).unwrap()).into_iter().map(|item| (item.url, item.updated)).collect()
+    }
+
+    #[test]
+    fn parses_rss_items() {
+        let body = r#"<?xml version="1.0"?>
+<rss version="2.0"><channel>
+  <title>Course news</title>
+  <link>https://example.org/</link>
+  <item><title>Week 5 notes</title><link>/courses/heat/week5</link><pubDate>Tue, 05 Mar 2024 10:30:00 GMT</pubDate></item>
+  <item><title>Exam</title><link><![CDATA[https://example.org/courses/heat/exam]]></link></item>
+</channel></rss>"#;
+
+        assert_eq!(items(body, "https://example.org/feed.xml"), vec![
+            (String::from("https://example.org/courses/heat/week5"), Some(1709634600)),
+            (String::from("https://example.org/courses/heat/exam"), None),
+        ]);
+    }
+
+    #[test]
+    fn parses_atom_entries() {
+        let body = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <link rel="self" href="https://example.org/atom.xml"/>
+  <entry>
+    <link rel="edit" href="https://example.org/admin/1"/>
+    <link href="https://example.org/papers/heat"/>
+    <published>2024-01-01T00:00:00Z</published>
+    <updated>2024-03-05T10:30:00Z</updated>
+  </entry>
+  <entry><link rel="alternate" href="papers/waves"></link><published>2024-03-05T10:30:00Z</published></entry>
+</feed>"#;
+
+        assert_eq!(items(body, "https://example.org/atom.xml"), vec![
+            (String::from("https://example.org/papers/heat"), Some(1709634600)),
+            (String::from("https://example.org/papers/waves"), Some(1709634600)),
+        ]);
+    }
+
+    #[test]
+    fn parses_rdf_items_by_their_about_url() {
+        let body = r#"<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#" xmlns="http://purl.org/rss/1.0/">
+  <item rdf:about="https://example.org/talks/1"><title>Talk</title></item>
+</rdf:RDF>"#;
+
+        assert_eq!(items(body, "https://example.org/index.rdf"), vec![(String::from("https://example.org/talks/1"), None)]);
+    }
+
+    #[test]
+    fn finds_feeds_linked_from_pages() {
+        let page = Html::parse_document(r#"<html><head>
+            <link rel="alternate" type="application/rss+xml" href="/feed.xml">
+            <link rel="alternate" type="application/atom+xml" href="https://example.org/atom.xml">
+            <link rel="alternate" type="text/html" hreflang="fr" href="/fr/">
+            <link rel="stylesheet" type="text/css" href="/style.css">
+        </head></html>"#);
+
+        let base = Url::parse("https://example.org/courses/").unwrap();
+        assert_eq!(feed_links(&page, &base), vec!["https://example.org/feed.xml", "https://example.org/atom.xml"]);
+    }
+}
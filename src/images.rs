@@ -0,0 +1,176 @@
+// Module picks out the informative images of a page - figures, diagrams, photos -
+// along with the text describing them (alt, title, <figcaption> and the text around
+// them), leaving icons, logos, spacers and tracking pixels out.
+
+use std::collections::HashSet;
+use scraper::{ElementRef, Html, Selector};
+use serde::{Serialize, Deserialize};
+use url::Url;
+use crate::boilerplate::has_excluded_ancestor;
+
+
+// Images kept per page.
+const MAX_IMAGES: usize = 8;
+// Images declared smaller than this (in either dimension) are icons or pixels.
+const MIN_DIMENSION: u32 = 48;
+// Characters of surrounding text kept per image.
+const CONTEXT_CHARS: usize = 200;
+// Parts of an image's file name, class or id marking it as decoration.
+const DECORATIVE_NAMES: [&str; 16] = ["logo", "icon", "sprite", "avatar", "badge", "button", "spacer", "pixel", "tracking",
+    "emoji", "favicon", "social", "share", "banner", "blank", "transparent"];
+
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct Image {
+    // Absolute URL, for the frontend to show as a thumbnail.
+    pub url: String,
+    pub alt: String,
+    pub title: String,
+    // <figcaption> of the <figure> holding the image.
+    pub caption: String,
+    // Text of the paragraph or block the image sits in.
+    pub context: String,
+}
+
+impl Image {
+    // Text describing the image - caption, alt and title, without repeats.
+    pub fn description(&self) -> Vec<&str> {
+        let mut texts: Vec<&str> = Vec::new();
+        for text in [&self.caption, &self.alt, &self.title] {
+            if !text.is_empty() && !texts.contains(&text.as_str()) {
+                texts.push(text);
+            }
+        }
+        texts
+    }
+}
+
+fn normalise_text<'a>(texts: impl Iterator<Item = &'a str>) -> String {
+    texts.collect::<Vec<&str>>().join(" ").split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+fn attr_text(element: ElementRef, name: &str) -> String {
+    normalise_text(element.value().attr(name).into_iter())
+}
+
+// Declared width or height, i.e. "32" or "32px".
+fn dimension(element: ElementRef, name: &str) -> Option<u32> {
+    element.value().attr(name)?.trim().trim_end_matches("px").parse().ok()
+}
+
+// Source of the image - src, or the attributes lazy loading scripts read from, or
+// the first srcset candidate.
+fn source<'a>(element: ElementRef<'a>) -> Option<&'a str> {
+    let value = element.value();
+    ["src", "data-src", "data-lazy-src", "data-original"].iter()
+        .filter_map(|name| value.attr(name))
+        .map(str::trim)
+        // Placeholders swapped out by lazy loading are inline data URIs.
+        .find(|src| !src.is_empty() && !src.starts_with("data:"))
+        .or_else(|| value.attr("srcset")?.split(',').next()?.split_whitespace().next())
+}
+
+fn is_decorative(element: ElementRef, url: &Url, has_caption: bool) -> bool {
+    let value = element.value();
+
+    if value.attr("role") == Some("presentation") || value.attr("aria-hidden") == Some("true") {
+        return true;
+    }
+
+    // An explicitly empty alt marks the image as decoration, unless a caption says otherwise.
+    if value.attr("alt").is_some_and(|alt| alt.trim().is_empty()) && !has_caption {
+        return true;
+    }
+
+    if [dimension(element, "width"), dimension(element, "height")].into_iter().flatten().any(|size| size < MIN_DIMENSION) {
+        return true;
+    }
+
+    let file_name = url.path().rsplit('/').next().unwrap_or_default().to_lowercase();
+    let names = format!("{} {} {}", file_name, value.attr("class").unwrap_or_default(), value.attr("id").unwrap_or_default()).to_lowercase();
+    if DECORATIVE_NAMES.iter().any(|name| names.contains(name)) {
+        return true;
+    }
+
+    // Logos and icons of the site's header, navigation and footer.
+    has_excluded_ancestor(element)
+}
+
+// <figcaption> of the nearest <figure> around the image.
+fn caption(element: ElementRef) -> String {
+    let caption_selector = Selector::parse("figcaption").unwrap();
+    element.ancestors()
+        .filter_map(ElementRef::wrap)
+        .find(|ancestor| ancestor.value().name() == "figure")
+        .and_then(|figure| figure.select(&caption_selector).next())
+        .map(|caption| normalise_text(caption.text()))
+        .unwrap_or_default()
+}
+
+fn block_text(element: ElementRef, caption: &str) -> String {
+    let text = normalise_text(element.text());
+    if caption.is_empty() {
+        return text;
+    }
+    text.replace(caption, " ").split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+// Text of the block (paragraph, list item, figure etc.) the image sits in, without its
+// caption. Where the block holds nothing else, i.e. a <figure> or a <p> wrapping the
+// image alone, the text of the blocks either side of it.
+fn context(element: ElementRef, caption: &str) -> String {
+    let Some(block) = element.ancestors()
+        .filter_map(ElementRef::wrap)
+        .find(|ancestor| !matches!(ancestor.value().name(), "a" | "picture" | "span" | "em" | "strong" | "b" | "i"))
+    else {
+        return String::new();
+    };
+
+    let mut text = block_text(block, caption);
+    if text.is_empty() && !matches!(block.value().name(), "body" | "html") {
+        let previous = block.prev_siblings().filter_map(ElementRef::wrap).next();
+        let next = block.next_siblings().filter_map(ElementRef::wrap).next();
+        text = [previous, next].into_iter()
+            .flatten()
+            .map(|sibling| block_text(sibling, caption))
+            .filter(|sibling_text| !sibling_text.is_empty())
+            .collect::<Vec<String>>()
+            .join(" ");
+    }
+    text.chars().take(CONTEXT_CHARS).collect()
+}
+
+// Informative images of the page in document order, with URLs resolved against the
+// page's base URL.
+pub fn extract_images(document: &Html, base: &Url) -> Vec<Image> {
+    let image_selector = Selector::parse("img").unwrap();
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut images: Vec<Image> = Vec::new();
+
+    for element in document.select(&image_selector) {
+        if images.len() >= MAX_IMAGES {
+            break;
+        }
+
+        let Some(url) = source(element).and_then(|src| base.join(src).ok()) else {
+            continue;
+        };
+        if url.scheme() != "http" && url.scheme() != "https" {
+            continue;
+        }
+
+        let caption = caption(element);
+        if is_decorative(element, &url, !caption.is_empty()) || !seen.insert(url.to_string()) {
+            continue;
+        }
+
+        images.push(Image {
+            url: url.to_string(),
+            alt: attr_text(element, "alt"),
+            title: attr_text(element, "title"),
+            context: context(element, &caption),
+            caption,
+        });
+    }
+
+    images
+}
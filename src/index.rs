@@ -1,68 +1,87 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::fs;
-use std::fs::File;
-use std::path::Path;
 use serde::{Deserialize, Serialize};
-use crate::parser::Document;
+use crate::parser::{Document, LegacyDocument};
+use crate::store::{DocId, DocumentStore};
+use crate::analysis::Analyser;
 use rayon::prelude::*;
 
 
 //set the location to store indices at local subdirectory "indices"
-const INDEX_DIR: &str = "./indices";
-const DTERM_PATH: &str = "./indices/dterm.json";
-const INVERTED_PATH: &str = "./indices/inverted.json";
+pub const INDEX_DIR: &str = "./indices";
+// Files under the indices directory each index type was kept in before segments (see
+// writer.rs), holding whole documents, which are moved into the document store.
+pub const DTERM_FILE: &str = "dterm.json";
+pub const INVERTED_FILE: &str = "inverted.json";
+// Times each term of an image caption, alt text or title is counted - diagrams are
+// often described nowhere else on the page.
+const CAPTION_WEIGHT: usize = 2;
+// Positions skipped between separately tokenised texts of a document (fields, content
+// entries, image descriptions), so phrases and proximity never span two of them.
+const SEGMENT_GAP: u32 = 100;
 
-     
-// Pre-processing step before passing text content to indices.
-fn tokenise (content: String) -> Vec<String> {
-    //remove all non alphabetic characters - text encoded as a stream of UTF encoded bytes (UTF-8)
-    //gets all of these chars, applies filter (keeping alphabetic characters), and then collects this stream back into a String
-    let content: String = content.chars().filter(|c| c.is_alphabetic() || c.is_whitespace()).collect();
-
-    //delimit words by comma
-    let words: Vec<String> = content.split_whitespace().map(|s| s.to_string().to_lowercase()).collect();
-        
-    //remove stop words - words which do not add semantic value to the string as a sentence.
-    let stop_words = vec![
-   "a","your", "an", "the", "and", "but", "in", "on", "of", "with", "is", "was", "by", "at", "to", "from", "which", "you", "it", "this", "that", "or", "be", "are", "been", "were", "would", "will", "shall", "should", "can", "could", "has", "have", "had", "not", "if", "else", "then", "for", "but", "or", "so", "no", "nor", "on", "at", "to", "from", "by", "in", "of", "up", "out", "over", "under", "again", "further", "then", "once", "here", "there", "when", "where", "why", "how", "all", "any", "both", "each", "few", "more", "most", "other", "some", "such", "no", "nor", "not", "only", "own", "same", "so", "than", "too", "very", "s", "t", "can", "will", "just", "don", "should", "now", "we"
-    ];      
 
-    words.into_iter().filter(|word| !stop_words.contains(&word.as_str())).collect()
+// Pre-processing step before passing text content to indices.
+// The language (ISO 639-1 code) picks the stop words and stemmer, the configured
+// default where unknown. Terms come with their word positions, counted before stop
+// words are removed so that phrase queries line up with the original text.
+fn tokenise_positions (content: &str, language: Option<&str>, analyser: &Analyser) -> Vec<(u32, String)> {
+    analyser.analyse(content, language).into_iter()
+        .map(|token| (token.position, token.text))
+        .collect()
 }
 
 
-    
-// Create fresh or filled index and place at the file path specified.
-pub fn create_index_file(file_path: &str, index: &Indexer) -> Result<(), Box<dyn std::error::Error>> {
-    let path = Path::new(file_path);
-        
-    // If indices directory doesn't exist, create it.
-    if !path.exists() {
-        fs::create_dir_all(INDEX_DIR)?;
+// Indexes the documents into an index of each type - document-term, term-document and
+// B-tree, in that order. Each document is analysed once for all three.
+pub fn index_documents<'a>(documents: impl Iterator<Item = (DocId, &'a Document)>, analyser: &Analyser) -> [Indexer; 3] {
+    let mut indices = [Indexer::TermIndex(HashMap::new()), Indexer::InvertedIndex(HashMap::new()), Indexer::BTreeIndex(BTreeSet::new())];
+    for (id, document) in documents {
+        let terms = document_terms(document, analyser);
+        for index in indices.iter_mut() {
+            index.insert(id, terms.clone());
+        }
     }
+    indices
+}
 
-    let file = File::create(file_path)?;
-    bincode::serialize_into(file, index)?;
-    Ok(()) 
+// Index layout before document IDs, with a full Document in every key and posting.
+#[derive(Deserialize)]
+enum LegacyIndexer {
+    TermIndex(HashMap<LegacyDocument, Vec<String>>),
+    InvertedIndex(HashMap<String, Vec<LegacyInvertedInfo>>),
 }
-    
-// Read fresh or filled index at file path specified.
-// Originally set to place error on the heap (Box<dyn std::error::Error>)
-pub fn read_index_file(file_path: &str) -> Result<Indexer, String> {
-    let file;
-    let index;
 
-    match File::open(file_path) {
-        Ok(f) => file = f,
-        Err(e) => return Err(e.to_string())
-    }
+#[derive(Deserialize)]
+struct LegacyInvertedInfo {
+    document: LegacyDocument,
+    #[allow(dead_code)]
+    term_freq: usize
+}
 
-    match bincode::deserialize_from(file) {
-        Ok(i) => index = i,
-        Err(e) => return Err(e.to_string())
-    }
+// Moves the documents of an index file in the layout before document IDs into the
+// document store, to be indexed from there. Files that do not decode are an error, so
+// that they are kept rather than replaced by empty indices.
+pub fn migrate_legacy_index(file_path: &str, store: &mut DocumentStore) -> Result<(), String> {
+    let bytes = fs::read(file_path).map_err(|e| e.to_string())?;
+    let legacy = bincode::deserialize::<LegacyIndexer>(&bytes)
+        .map_err(|e| format!("Could not migrate {}: {}", file_path, e))?;
+
+    println!("Migrating {} to document IDs", file_path);
 
-    Ok(index)
+    match legacy {
+        LegacyIndexer::TermIndex(map) => {
+            for document in map.into_keys() {
+                store.insert(document.into());
+            }
+        }
+        LegacyIndexer::InvertedIndex(map) => {
+            for posting in map.into_values().flatten() {
+                store.insert(posting.document.into());
+            }
+        }
+    }
+    Ok(())
 }
 
 // Delete the file at the file path specified.
@@ -83,88 +102,258 @@ pub fn _delete_all() -> std::io::Result<()> {
     
 }
 // Create enum to store all index types as variants, currently includes document-term and
-// term-document indices. Later will want to include B-tree.
+// term-document indices, and a B-tree of the terms in order, for prefix and range
+// queries - their postings are read from the term-document index.
 #[derive(Serialize, Deserialize, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Indexer {
-    TermIndex(HashMap<Document, Vec<String>>),
+    TermIndex(HashMap<DocId, Vec<String>>),
     InvertedIndex(HashMap<String, Vec<InvertedInfo>>),
+    BTreeIndex(BTreeSet<String>),
 }
 
-// This will be later modified to 
-// include document as it's identifier as opposed
-// to the whole type serialised.
+// Fields of a document indexed apart, so that scoring can weigh them differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Field {
+    Title,
+    Description,
+    Headings,
+    Keywords,
+    // Inbound anchor text.
+    Anchor,
+    // Content, with image descriptions.
+    Body,
+}
+
+pub const FIELD_COUNT: usize = 6;
+pub const FIELDS: [Field; FIELD_COUNT] = [Field::Title, Field::Description, Field::Headings, Field::Keywords, Field::Anchor, Field::Body];
+
+// Posting of a term - the ID of a document in the document store, how often the
+// term occurs in it (in all and in each field, indexed by Field) and at which word
+// positions (ascending, across all fields).
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InvertedInfo {
-    pub document: Document,
-    pub term_freq: usize
+    pub doc_id: DocId,
+    pub term_freq: usize,
+    pub field_freqs: [u32; FIELD_COUNT],
+    pub positions: Vec<u32>
 }
 
 impl InvertedInfo {
-    pub fn new(document: Document, term_freq: usize) -> Self {
-        InvertedInfo { document, term_freq }
+    pub fn new(doc_id: DocId, occurrences: Vec<(Field, u32)>) -> Self {
+        let mut field_freqs = [0; FIELD_COUNT];
+        for (field, _) in &occurrences {
+            field_freqs[*field as usize] += 1;
+        }
+        let positions = occurrences.into_iter().map(|(_, position)| position).collect::<Vec<u32>>();
+        InvertedInfo { doc_id, term_freq: positions.len(), field_freqs, positions }
     }
 }
 
-impl Indexer {
-    pub fn new(&mut self, documents: Vec<Document>) -> &mut Self {
-        match self {
-            Indexer::TermIndex(_) => {
-                for document in documents {
-                    let pre_terms: Vec<Vec<String>> = document.content.par_iter()
-                        .map(|content| tokenise(String::from(content))).collect();
-                    let terms = pre_terms.into_iter().flatten().collect();
-                    self.insert(document.clone(), terms);
-                }
+// Length in terms of each field of each document, and its average over documents,
+// for length normalisation when scoring.
+#[derive(Debug, Default)]
+pub struct FieldLengths {
+    pub lengths: HashMap<DocId, [u32; FIELD_COUNT]>,
+    pub averages: [f64; FIELD_COUNT],
+}
 
-                let _ = create_index_file(DTERM_PATH, &self);
-                self
-            }
-            
-            Indexer::InvertedIndex(_) => {
-                // (1) Iterate through each document.
-                // (2) If term not in the map yet, add to map with document and
-                // default term frequency of 1.
-                // (3) If term already in map but document is not, add to map with
-                // new document and default term frequency of 1.
-                // (4) If term already in map and document is, increment term
-                // frequency for given document.
-                for document in documents {
-                    let pre_terms: Vec<Vec<String>> = document.content.par_iter()
-                        .map(|content| tokenise(String::from(content))).collect();
-                    let terms = pre_terms.into_iter().flatten().collect();
-                    self.insert(document.clone(), terms);
+impl FieldLengths {
+    // Summed from the postings of an inverted index, empty for other index types.
+    pub fn new(index: &Indexer) -> Self {
+        let mut lengths: HashMap<DocId, [u32; FIELD_COUNT]> = HashMap::new();
+        if let Some(postings) = index.all_postings() {
+            for info in postings {
+                let document_lengths = lengths.entry(info.doc_id).or_insert([0; FIELD_COUNT]);
+                for (length, freq) in document_lengths.iter_mut().zip(info.field_freqs) {
+                    *length += freq;
                 }
-            
-                
-                let _ = create_index_file(INVERTED_PATH, &self);
-                self
             }
         }
+        FieldLengths::from_lengths(lengths)
+    }
+
+    // Averages lengths read back from an index file.
+    pub fn from_lengths(lengths: HashMap<DocId, [u32; FIELD_COUNT]>) -> Self {
+        let mut averages = [0.0; FIELD_COUNT];
+        for document_lengths in lengths.values() {
+            for (average, length) in averages.iter_mut().zip(document_lengths) {
+                *average += *length as f64 / lengths.len() as f64;
+            }
+        }
+        FieldLengths { lengths, averages }
+    }
+}
+
+// Terms of each field of the document, with their positions in the document. Each
+// field and each text within one starts SEGMENT_GAP positions after the last.
+// Translated text is indexed alongside the original, so either language matches.
+fn document_terms(document: &Document, analyser: &Analyser) -> Vec<(Field, u32, String)> {
+    let language = document.language.as_deref();
+    let translation = document.translation.as_ref();
+
+    let mut segments: Vec<(Field, Vec<(u32, String)>)> = Vec::new();
+    let mut push = |field: Field, text: &str, language: Option<&str>| segments.push((field, tokenise_positions(text, language, analyser)));
+
+    push(Field::Title, &document.title, language);
+    push(Field::Description, &document.description, language);
+    if let Some(translation) = translation {
+        push(Field::Title, &translation.title, Some(translation.language.as_str()));
+        push(Field::Description, &translation.description, Some(translation.language.as_str()));
+    }
+    for heading in &document.headings {
+        push(Field::Headings, heading, language);
+    }
+    for keyword in &document.metadata.keywords {
+        push(Field::Keywords, keyword, language);
+    }
+    for anchor in &document.anchor_text {
+        push(Field::Anchor, anchor, language);
+    }
+
+    segments.extend(document.content.par_iter()
+        .map(|content| (Field::Body, tokenise_positions(content, language, analyser)))
+        .collect::<Vec<(Field, Vec<(u32, String)>)>>());
+
+    if let Some(translation) = translation {
+        segments.extend(translation.content.par_iter()
+            .map(|content| (Field::Body, tokenise_positions(content, Some(translation.language.as_str()), analyser)))
+            .collect::<Vec<(Field, Vec<(u32, String)>)>>());
+    }
+
+    for image in &document.images {
+        for text in image.description() {
+            let terms = tokenise_positions(text, language, analyser);
+            segments.extend(std::iter::repeat_n((Field::Body, terms), CAPTION_WEIGHT));
+        }
     }
 
-    fn insert(&mut self, document: Document, terms: Vec<String>){
+    let mut offset: u32 = 0;
+    let mut terms: Vec<(Field, u32, String)> = Vec::new();
+    for (field, segment_terms) in segments {
+        let Some(end) = segment_terms.last().map(|(position, _)| offset + position + 1) else { continue };
+        terms.extend(segment_terms.into_iter().map(|(position, term)| (field, offset + position, term)));
+        offset = end + SEGMENT_GAP;
+    }
+    terms
+}
+
+impl Indexer {
+    fn all_postings(&self) -> Option<Box<dyn Iterator<Item = &InvertedInfo> + '_>> {
         match self {
-            // Document-term implements the standard insert function.
-            Indexer::TermIndex(map) => { 
-                let _ = map.insert(document, terms);
-            },
-            // Term-document requires the reverse mapping, handled by below logic.
-            Indexer::InvertedIndex(map) => {
-                for term in terms {
-                    let info_vec = map.entry(term.clone()).or_insert_with(Vec::new);
-                
-                    // Find if the document already exists in the vector.
-                    if let Some(container) = info_vec.iter_mut().find(|container| container.document == document) {
-                        container.term_freq += 1; // Increment existing frequency.
-                    } else {
-                        info_vec.push(InvertedInfo::new(document.clone(), 1)); // Add new entry.
+            Indexer::TermIndex(_) | Indexer::BTreeIndex(_) => None,
+            Indexer::InvertedIndex(map) => Some(Box::new(map.values().flatten())),
+        }
+    }
+
+    // Adds the entries of another index of the same type, less those of deleted
+    // documents - i.e. to merge segments. The B-tree holds no documents, so its terms
+    // are all kept.
+    pub fn absorb(&mut self, other: Indexer, deleted: impl Fn(DocId) -> bool) {
+        match (self, other) {
+            (Indexer::TermIndex(map), Indexer::TermIndex(other)) => {
+                map.extend(other.into_iter().filter(|(id, _)| !deleted(*id)));
+            }
+            (Indexer::InvertedIndex(map), Indexer::InvertedIndex(other)) => {
+                for (term, postings) in other {
+                    let live: Vec<InvertedInfo> = postings.into_iter().filter(|info| !deleted(info.doc_id)).collect();
+                    if !live.is_empty() {
+                        map.entry(term).or_default().extend(live);
                     }
                 }
             }
+            (Indexer::BTreeIndex(terms), Indexer::BTreeIndex(other)) => terms.extend(other),
+            _ => {}
+        }
+    }
+
+    fn insert(&mut self, id: DocId, terms: Vec<(Field, u32, String)>){
+        // Document-term implements the standard insert function, over the body.
+        if let Indexer::TermIndex(map) = self {
+            let _ = map.insert(id, terms.into_iter().filter(|(field, _, _)| *field == Field::Body).map(|(_, _, term)| term).collect());
+            return;
+        }
+        if let Indexer::BTreeIndex(ordered) = self {
+            ordered.extend(terms.into_iter().map(|(_, _, term)| term));
+            return;
+        }
+
+        // Term-document requires the reverse mapping - occurrences are gathered first, so
+        // each term gets a single posting for the document.
+        let mut term_occurrences: HashMap<String, Vec<(Field, u32)>> = HashMap::new();
+        for (field, position, term) in terms {
+            term_occurrences.entry(term).or_default().push((field, position));
+        }
+
+        for (term, occurrences) in term_occurrences {
+            let info = InvertedInfo::new(id, occurrences);
+            if let Indexer::InvertedIndex(map) = self {
+                map.entry(term).or_default().push(info);
+            }
         }
     }
    
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
+    // Bincode of the fields of a Document as the baseline wrote them - url, content,
+    // description, images, links and title.
+    fn legacy_document(out: &mut Vec<u8>, url: &str, content: &[&str], links: &[&str], title: &str) {
+        let string = |out: &mut Vec<u8>, text: &str| {
+            out.extend((text.len() as u64).to_le_bytes());
+            out.extend(text.as_bytes());
+        };
+        let strings = |out: &mut Vec<u8>, texts: &[&str]| {
+            out.extend((texts.len() as u64).to_le_bytes());
+            for text in texts {
+                string(out, text);
+            }
+        };
+        string(out, url);
+        strings(out, content);
+        string(out, "");
+        strings(out, &["https://example.com/figure.png"]);
+        strings(out, links);
+        string(out, title);
+    }
+
+    fn migrate(name: &str, bytes: &[u8]) -> Result<DocumentStore, String> {
+        let path = std::env::temp_dir().join(format!("legacy-index-test-{}-{}", name, std::process::id())).to_string_lossy().into_owned();
+        fs::write(&path, bytes).unwrap();
+        let mut store = DocumentStore::default();
+        let result = migrate_legacy_index(&path, &mut store);
+        let _ = fs::remove_file(&path);
+        result.map(|_| store)
+    }
+
+    #[test]
+    fn migrates_inverted_index_files_in_the_baseline_layout() {
+        // InvertedIndex of one term with a posting in each of two documents.
+        let mut bytes: Vec<u8> = 1u32.to_le_bytes().to_vec();
+        bytes.extend(1u64.to_le_bytes());
+        bytes.extend(7u64.to_le_bytes());
+        bytes.extend(b"fourier");
+        bytes.extend(2u64.to_le_bytes());
+        legacy_document(&mut bytes, "https://example.com/a", &["Fourier series"], &["https://example.com/b"], "Series");
+        bytes.extend(1u64.to_le_bytes());
+        legacy_document(&mut bytes, "https://example.com/b", &["Fourier transform"], &[], "Transform");
+        bytes.extend(1u64.to_le_bytes());
 
+        let store = migrate("inverted", &bytes).unwrap();
+        let mut documents: Vec<Document> = store.documents().map(|(_, document)| document).collect();
+        documents.sort_by(|a, b| a.url.cmp(&b.url));
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[0].title, "Series");
+        assert_eq!(documents[0].content, vec!["Fourier series"]);
+        assert_eq!(documents[0].images[0].url, "https://example.com/figure.png");
+        assert_eq!(documents[1].url, "https://example.com/b");
+    }
+
+    #[test]
+    fn fails_on_files_that_do_not_decode() {
+        assert!(migrate("garbage", b"not an index").is_err());
+    }
+}
@@ -0,0 +1,135 @@
+// Module detects the language of parsed text and provides the language specific parts
+// of text analysis - stop words, and segmentation of scripts written without spaces.
+
+use std::sync::OnceLock;
+use lingua::{Language, LanguageDetector, LanguageDetectorBuilder};
+
+
+// Characters of text used to detect a document's language.
+const DETECTION_CHARS: usize = 1000;
+
+// Languages we detect, which LibreTranslate also has models for.
+const LANGUAGES: [Language; 20] = [Language::English, Language::Ukrainian, Language::Turkish, Language::Thai, Language::Swedish,
+    Language::Spanish, Language::Slovene, Language::Slovak, Language::Russian, Language::Romanian, Language::Portuguese,
+    Language::Polish, Language::Korean, Language::Japanese, Language::Italian, Language::Hungarian, Language::French,
+    Language::Chinese, Language::German, Language::Dutch];
+
+const STOP_WORDS_EN: [&str; 78] = [
+    "a", "your", "an", "the", "and", "but", "in", "on", "of", "with", "is", "was", "by", "at", "to", "from", "which", "you", "it", "this", "that", "or", "be", "are", "been", "were", "would", "will", "shall", "should", "can", "could", "has", "have", "had", "not", "if", "else", "then", "for", "so", "no", "nor", "up", "out", "over", "under", "again", "further", "once", "here", "there", "when", "where", "why", "how", "all", "any", "both", "each", "few", "more", "most", "other", "some", "such", "only", "own", "same", "than", "too", "very", "s", "t", "just", "don", "now", "we"
+];
+const STOP_WORDS_FR: [&str; 52] = [
+    "le", "la", "les", "un", "une", "des", "du", "de", "et", "ou", "mais", "donc", "car", "ni", "que", "qui", "quoi", "dans", "sur", "sous", "par", "pour", "avec", "sans", "en", "au", "aux", "ce", "cet", "cette", "ces", "il", "elle", "ils", "elles", "nous", "vous", "je", "tu", "on", "est", "sont", "être", "avoir", "a", "ont", "pas", "ne", "se", "son", "sa", "ses"
+];
+const STOP_WORDS_DE: [&str; 52] = [
+    "der", "die", "das", "den", "dem", "des", "ein", "eine", "einer", "eines", "einem", "einen", "und", "oder", "aber", "doch", "dass", "in", "im", "an", "am", "auf", "aus", "bei", "mit", "nach", "von", "vom", "zu", "zum", "zur", "für", "über", "unter", "ist", "sind", "war", "waren", "sein", "hat", "haben", "wird", "werden", "nicht", "es", "er", "sie", "wir", "ihr", "ich", "du", "sich"
+];
+const STOP_WORDS_ES: [&str; 50] = [
+    "el", "la", "los", "las", "un", "una", "unos", "unas", "y", "o", "pero", "que", "de", "del", "en", "a", "al", "por", "para", "con", "sin", "sobre", "entre", "es", "son", "fue", "ser", "está", "están", "se", "su", "sus", "lo", "le", "les", "no", "como", "más", "este", "esta", "estos", "estas", "ese", "esa", "yo", "tú", "él", "ella", "nosotros", "ellos"
+];
+const STOP_WORDS_IT: [&str; 48] = [
+    "il", "lo", "la", "i", "gli", "le", "un", "uno", "una", "e", "o", "ma", "che", "di", "del", "della", "dei", "delle", "in", "nel", "nella", "a", "al", "alla", "da", "dal", "per", "con", "su", "tra", "fra", "è", "sono", "essere", "ha", "hanno", "non", "si", "suo", "sua", "questo", "questa", "quello", "quella", "io", "tu", "lui", "lei"
+];
+const STOP_WORDS_PT: [&str; 46] = [
+    "o", "a", "os", "as", "um", "uma", "uns", "umas", "e", "ou", "mas", "que", "de", "do", "da", "dos", "das", "em", "no", "na", "nos", "nas", "por", "para", "com", "sem", "sobre", "é", "são", "foi", "ser", "está", "se", "seu", "sua", "não", "como", "mais", "este", "esta", "esse", "essa", "eu", "ele", "ela", "eles"
+];
+const STOP_WORDS_NL: [&str; 44] = [
+    "de", "het", "een", "en", "of", "maar", "dat", "die", "dit", "deze", "in", "op", "aan", "bij", "met", "naar", "van", "voor", "over", "onder", "uit", "is", "zijn", "was", "waren", "heeft", "hebben", "wordt", "worden", "niet", "geen", "er", "hij", "zij", "ze", "wij", "we", "ik", "jij", "je", "u", "zich", "als", "om"
+];
+
+fn detector() -> &'static LanguageDetector {
+    static DETECTOR: OnceLock<LanguageDetector> = OnceLock::new();
+    // Building the detector loads its models, so it is built once and shared.
+    DETECTOR.get_or_init(|| LanguageDetectorBuilder::from_languages(&LANGUAGES).build())
+}
+
+// ISO 639-1 code (i.e. "en", "fr") of the language the text is written in, using up
+// to the first 1000 characters.
+pub fn detect_language(text: &str) -> Option<String> {
+    let sample: String = text.chars().take(DETECTION_CHARS).collect();
+    if sample.trim().is_empty() {
+        return None;
+    }
+
+    detector().detect_language_of(sample)
+        .map(|language| language.iso_code_639_1().to_string().to_lowercase())
+}
+
+// Built-in stop words of the language, English where it is unknown or has no list.
+// The analyser starts from these, replacing any the analysis config lists.
+pub fn stop_words(language: Option<&str>) -> &'static [&'static str] {
+    match language {
+        Some("fr") => &STOP_WORDS_FR,
+        Some("de") => &STOP_WORDS_DE,
+        Some("es") => &STOP_WORDS_ES,
+        Some("it") => &STOP_WORDS_IT,
+        Some("pt") => &STOP_WORDS_PT,
+        Some("nl") => &STOP_WORDS_NL,
+        _ => &STOP_WORDS_EN,
+    }
+}
+
+// Scripts written without spaces between words - Chinese, Japanese kana and Thai.
+pub fn is_unsegmented(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}'     // Hiragana, Katakana
+        | '\u{3400}'..='\u{4DBF}'   // CJK Extension A
+        | '\u{4E00}'..='\u{9FFF}'   // CJK Unified Ideographs
+        | '\u{F900}'..='\u{FAFF}'   // CJK Compatibility Ideographs
+        | '\u{0E00}'..='\u{0E7F}')  // Thai
+}
+
+// Splits text into lower case words. Runs of unsegmented script are split into
+// overlapping character bigrams (a lone character stays a unigram), so queries
+// match without a dictionary-based word segmenter.
+pub fn segment(text: &str) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+
+    for token in text.split_whitespace() {
+        let mut run: Vec<char> = Vec::new();
+        let mut word = String::new();
+
+        for c in token.chars().chain(std::iter::once(' ')) {
+            if is_unsegmented(c) {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word).to_lowercase());
+                }
+                run.push(c);
+                continue;
+            }
+
+            if !run.is_empty() {
+                if run.len() == 1 {
+                    words.push(run[0].to_string());
+                }
+                words.extend(run.windows(2).map(|pair| pair.iter().collect::<String>()));
+                run.clear();
+            }
+            if c != ' ' {
+                word.push(c);
+            }
+        }
+
+        if !word.is_empty() {
+            words.push(word.to_lowercase());
+        }
+    }
+
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_unsegmented_runs_into_bigrams() {
+        assert_eq!(segment("北京大学"), vec!["北京", "京大", "大学"]);
+        assert_eq!(segment("中"), vec!["中"]);
+    }
+
+    #[test]
+    fn keeps_words_of_other_scripts_whole() {
+        assert_eq!(segment("Physics at 北京大学"), vec!["physics", "at", "北京", "京大", "大学"]);
+        assert_eq!(segment("CS101中文"), vec!["cs101", "中文"]);
+    }
+}
@@ -0,0 +1,200 @@
+// Module resolves raw hrefs found on crawled pages into canonical absolute URLs,
+// and decides whether they fall within the scope of the current crawl.
+
+use std::collections::HashSet;
+use scraper::{ElementRef, Html, Selector};
+use serde::{Serialize, Deserialize};
+use url::Url;
+
+
+// Which links are allowed into the crawl frontier.
+// SeedDomain -> same registered domain as the seed the link was found from.
+// KnownDomains -> any domain listed in discovery_data/domains.json.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum LinkScope {
+    #[default]
+    Any,
+    SeedDomain,
+    KnownDomains,
+}
+
+// Longest anchor text kept for a link.
+const MAX_ANCHOR_CHARS: usize = 200;
+
+// Link found on a crawled page - the canonical target, the text of the link and
+// its rel values (i.e. "nofollow"), lower case.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LinkEdge {
+    pub source: String,
+    pub target: String,
+    pub anchor: String,
+    pub rel: Vec<String>,
+}
+
+impl LinkEdge {
+    // Links the source does not vouch for - paid, user generated or nofollow.
+    pub fn is_endorsed(&self) -> bool {
+        !self.rel.iter().any(|rel| matches!(rel.as_str(), "nofollow" | "sponsored" | "ugc"))
+    }
+}
+
+// Text of a link - its own text, else the alt text of an image inside it, else
+// its title or aria-label.
+pub fn anchor_text(element: ElementRef) -> String {
+    let image_selector = Selector::parse("img[alt]").unwrap();
+    let text = element.text().collect::<Vec<&str>>().join(" ");
+    let text = match text.trim() {
+        "" => element.select(&image_selector)
+            .filter_map(|image| image.value().attr("alt"))
+            .find(|alt| !alt.trim().is_empty())
+            .or_else(|| element.value().attr("title"))
+            .or_else(|| element.value().attr("aria-label"))
+            .unwrap_or_default(),
+        _ => &text,
+    };
+
+    text.split_whitespace().collect::<Vec<&str>>().join(" ").chars().take(MAX_ANCHOR_CHARS).collect()
+}
+
+// Base URL for resolving relative links - the page URL, unless the page
+// declares its own <base href>.
+pub fn base_url(page_url: &Url, document: &Html) -> Url {
+    let base_selector = Selector::parse("base[href]").unwrap();
+
+    document.select(&base_selector)
+        .next()
+        .and_then(|element| element.value().attr("href"))
+        .and_then(|href| page_url.join(href.trim()).ok())
+        .unwrap_or_else(|| page_url.clone())
+}
+
+// URL the page declares as its canonical version with <link rel="canonical">.
+pub fn canonical_link(document: &Html, base: &Url) -> Option<String> {
+    let canonical_selector = Selector::parse("link[rel~=canonical][href]").unwrap();
+    let href = document.select(&canonical_selector).next()?.value().attr("href")?;
+    resolve(base, href, &[]).map(|url| url.to_string())
+}
+
+// Resolves a raw href against the base URL and canonicalises it. Returns None
+// for anything we cannot or should not fetch (fragments, mailto:, javascript:, etc).
+pub fn resolve(base: &Url, href: &str, tracking_params: &[String]) -> Option<Url> {
+    let href = href.trim();
+
+    // Same-page anchors never lead anywhere new.
+    if href.is_empty() || href.starts_with('#') {
+        return None;
+    }
+
+    let url = base.join(href).ok()?;
+    normalise(url, tracking_params)
+}
+
+// Canonical form of a URL so the same page is only queued once:
+// (1) http(s) only, (2) scheme & host lower case and default ports dropped (handled by Url),
+// (3) fragment removed, (4) tracking parameters removed and remaining query parameters
+// sorted, (5) trailing slash removed from non-root paths.
+pub fn normalise(mut url: Url, tracking_params: &[String]) -> Option<Url> {
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return None;
+    }
+
+    url.host_str()?;
+    url.set_fragment(None);
+
+    if url.query().is_some() {
+        let mut pairs: Vec<(String, String)> = url.query_pairs()
+            .filter(|(key, _)| !is_tracking_param(key, tracking_params))
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect();
+        pairs.sort();
+
+        if pairs.is_empty() {
+            url.set_query(None);
+        }
+        else {
+            url.query_pairs_mut().clear().extend_pairs(pairs);
+        }
+    }
+
+    let path = url.path().to_string();
+    if path.len() > 1 && path.ends_with('/') {
+        url.set_path(path.trim_end_matches('/'));
+    }
+
+    Some(url)
+}
+
+// Tracking parameters are matched exactly, or by prefix where the configured
+// name ends in '*' (i.e. "utm_*").
+fn is_tracking_param(key: &str, tracking_params: &[String]) -> bool {
+    let key = key.to_lowercase();
+    tracking_params.iter().any(|param| match param.strip_suffix('*') {
+        Some(prefix) => key.starts_with(prefix),
+        None => key == *param,
+    })
+}
+
+// Registered domain (eTLD+1) of the URL's host, i.e. "www.cs.ox.ac.uk" -> "ox.ac.uk".
+// Falls back on the host itself for IP addresses and unknown suffixes.
+pub fn registered_domain(url: &Url) -> Option<String> {
+    let host = url.host_str()?.to_lowercase();
+    match psl::domain_str(&host) {
+        Some(domain) => Some(domain.to_string()),
+        None => Some(host),
+    }
+}
+
+// Checks the link against the crawl scope. seed_domain is the registered domain of
+// the seed the link was reached from, known_domains those read from domains.json.
+pub fn in_scope(url: &Url, scope: LinkScope, seed_domain: &str, known_domains: &HashSet<String>) -> bool {
+    match scope {
+        LinkScope::Any => true,
+        LinkScope::SeedDomain => registered_domain(url).is_some_and(|domain| domain == seed_domain),
+        LinkScope::KnownDomains => {
+            let Some(host) = url.host_str() else { return false };
+            let host = host.to_lowercase();
+
+            // Check the host and each of its parent domains against the list.
+            let mut candidate = host.as_str();
+            loop {
+                if known_domains.contains(candidate) {
+                    return true;
+                }
+                match candidate.split_once('.') {
+                    Some((_, parent)) => candidate = parent,
+                    None => return false,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalised(url: &str) -> Option<String> {
+        let tracking_params = [String::from("utm_*"), String::from("fbclid")];
+        normalise(Url::parse(url).ok()?, &tracking_params).map(String::from)
+    }
+
+    #[test]
+    fn canonicalises_urls() {
+        assert_eq!(normalised("HTTP://Example.ORG:80/Courses/#syllabus").as_deref(), Some("http://example.org/Courses"));
+        assert_eq!(normalised("https://example.org:443/").as_deref(), Some("https://example.org/"));
+        assert_eq!(normalised("https://example.org/search?q=heat&a=1").as_deref(), Some("https://example.org/search?a=1&q=heat"));
+    }
+
+    #[test]
+    fn removes_tracking_parameters() {
+        assert_eq!(normalised("https://example.org/page?utm_source=feed&UTM_Medium=rss&fbclid=x").as_deref(), Some("https://example.org/page"));
+        assert_eq!(normalised("https://example.org/page?id=7&utm_campaign=spring").as_deref(), Some("https://example.org/page?id=7"));
+    }
+
+    #[test]
+    fn rejects_urls_we_cannot_fetch() {
+        assert_eq!(normalised("mailto:someone@example.org"), None);
+        assert_eq!(normalised("ftp://example.org/file"), None);
+        assert_eq!(normalised("file:///etc/hosts"), None);
+    }
+}
@@ -10,6 +10,28 @@ mod discover;
 mod auth;
 mod config;
 mod meta;
+mod robots;
+mod links;
+mod checkpoint;
+mod sitemap;
+mod feed;
+mod extract;
+mod pdf;
+mod office;
+mod boilerplate;
+mod scholarly;
+mod language;
+mod translate;
+mod images;
+mod anchors;
+mod dedup;
+mod store;
+mod shared;
+mod query;
+mod analysis;
+mod segment;
+mod writer;
+mod collection;
 
 use crate::api::rocket;
 
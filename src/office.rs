@@ -0,0 +1,466 @@
+// Module extracts text from zip+XML office formats linked from course pages -
+// PowerPoint (.pptx), Word (.docx) and OpenDocument presentations (.odp).
+// Slide and section titles become headings, and body text and speaker notes content.
+
+use std::io::{Cursor, Read};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::name::QName;
+use quick_xml::Reader;
+use percent_encoding::percent_decode_str;
+use url::Url;
+use zip::ZipArchive;
+use crate::extract::{describe, Extracted};
+
+
+// Upper bound on the decompressed size of any one part, so a zip bomb cannot
+// exhaust memory.
+const MAX_PART_BYTES: u64 = 32 * 1024 * 1024;
+
+// Placeholders holding page furniture rather than slide text.
+const FURNITURE_PLACEHOLDERS: [&str; 5] = ["sldNum", "sldImg", "dt", "ftr", "hdr"];
+
+type Archive = ZipArchive<Cursor<Vec<u8>>>;
+
+// Text of a slide (or notes page), split into its title and everything else.
+#[derive(Debug, Default)]
+struct SlideText {
+    title: Vec<String>,
+    body: Vec<String>,
+}
+
+fn open_archive(bytes: &[u8]) -> Result<Archive, String> {
+    ZipArchive::new(Cursor::new(bytes.to_vec())).map_err(|e| e.to_string())
+}
+
+fn read_part(archive: &mut Archive, name: &str) -> Option<String> {
+    let part = archive.by_name(name).ok()?;
+    let mut xml = String::new();
+    part.take(MAX_PART_BYTES).read_to_string(&mut xml).ok()?;
+    Some(xml)
+}
+
+fn local_name(name: QName) -> String {
+    String::from_utf8_lossy(name.local_name().as_ref()).into_owned()
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Option<String> {
+    element.try_get_attribute(name).ok()?
+        .and_then(|attribute| attribute.unescape_value().ok())
+        .map(|value| value.into_owned())
+}
+
+fn push_paragraph(paragraphs: &mut Vec<String>, paragraph: &str) {
+    let paragraph = paragraph.split_whitespace().collect::<Vec<&str>>().join(" ");
+    if !paragraph.is_empty() {
+        paragraphs.push(paragraph);
+    }
+}
+
+// dc:title from the document properties (docProps/core.xml or meta.xml).
+fn property_title(xml: &str) -> Option<String> {
+    let mut reader = Reader::from_str(xml);
+    let mut in_title = false;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(element)) => in_title = local_name(element.name()) == "title",
+            Ok(Event::Text(text)) if in_title => {
+                let title = text.unescape().ok()?.trim().to_string();
+                return if title.is_empty() { None } else { Some(title) };
+            }
+            Ok(Event::End(_)) => in_title = false,
+            Ok(Event::Eof) | Err(_) => return None,
+            _ => ()
+        }
+    }
+}
+
+// File name without its extension, i.e. "Lecture 7 – Dynamic Programming".
+fn file_title(url: &str) -> String {
+    let Ok(url) = Url::parse(url) else { return String::new() };
+    let name = url.path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .unwrap_or_default();
+    let name = percent_decode_str(name).decode_utf8_lossy();
+
+    match name.rsplit_once('.') {
+        Some((stem, _)) => stem.trim().to_string(),
+        None => name.trim().to_string(),
+    }
+}
+
+// Builds the extracted document from slides in order - each slide's title as a heading,
+// then its body and notes. The document title falls back on the first slide title,
+// then the file name.
+fn from_slides(slides: Vec<(SlideText, Vec<String>)>, property_title: Option<String>, url: &str) -> Extracted {
+    let mut content: Vec<String> = Vec::new();
+    let mut headings: Vec<String> = Vec::new();
+
+    for (slide, notes) in slides {
+        let title = slide.title.join(" ");
+        if !title.is_empty() {
+            headings.push(title.clone());
+            content.push(title);
+        }
+        content.extend(slide.body);
+        content.extend(notes);
+    }
+
+    let title = property_title
+        .or_else(|| headings.first().cloned())
+        .unwrap_or_else(|| file_title(url));
+    let description = describe(&content);
+
+    Extracted { title, description, content, headings, pages: Vec::new() }
+}
+
+// Slide number from a part name such as "ppt/slides/slide12.xml".
+fn slide_number(name: &str) -> Option<u32> {
+    name.strip_prefix("ppt/slides/slide")?.strip_suffix(".xml")?.parse().ok()
+}
+
+// Text of a PowerPoint slide or notes page. Paragraphs in a title placeholder make up
+// the title, those in page furniture (slide numbers, footers) are dropped.
+fn pptx_slide_text(xml: &str) -> SlideText {
+    let mut reader = Reader::from_str(xml);
+    let mut slide = SlideText::default();
+
+    let mut placeholder: Option<String> = None;
+    let mut paragraph: Option<String> = None;
+    let mut in_text = false;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(element)) => match local_name(element.name()).as_str() {
+                "sp" => placeholder = None,
+                "ph" => placeholder = Some(attribute(&element, "type").unwrap_or_default()),
+                "p" => paragraph = Some(String::new()),
+                "t" => in_text = true,
+                _ => ()
+            },
+            Ok(Event::Empty(element)) => match local_name(element.name()).as_str() {
+                "ph" => placeholder = Some(attribute(&element, "type").unwrap_or_default()),
+                "br" | "tab" => if let Some(paragraph) = paragraph.as_mut() { paragraph.push(' ') },
+                _ => ()
+            },
+            Ok(Event::Text(text)) if in_text => {
+                if let (Some(paragraph), Ok(text)) = (paragraph.as_mut(), text.unescape()) {
+                    paragraph.push_str(&text);
+                }
+            }
+            Ok(Event::End(element)) => match local_name(element.name()).as_str() {
+                "t" => in_text = false,
+                "p" => {
+                    let Some(text) = paragraph.take() else { continue };
+                    match placeholder.as_deref() {
+                        Some("title") | Some("ctrTitle") => push_paragraph(&mut slide.title, &text),
+                        Some(kind) if FURNITURE_PLACEHOLDERS.contains(&kind) => (),
+                        _ => push_paragraph(&mut slide.body, &text),
+                    }
+                }
+                "sp" => placeholder = None,
+                _ => ()
+            },
+            Ok(Event::Eof) => break,
+            Err(e) => {
+                eprintln!("Malformed slide XML: {}", e);
+                break
+            }
+            _ => ()
+        }
+    }
+
+    slide
+}
+
+// Part name of the notes page a slide's relationships point at, if it has one.
+fn pptx_notes_part(archive: &mut Archive, slide_number: u32) -> Option<String> {
+    let rels = read_part(archive, &format!("ppt/slides/_rels/slide{}.xml.rels", slide_number))?;
+    let mut reader = Reader::from_str(&rels);
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Empty(element)) | Ok(Event::Start(element)) => {
+                let is_notes = attribute(&element, "Type").is_some_and(|kind| kind.ends_with("/notesSlide"));
+                if is_notes {
+                    // Targets are relative to ppt/slides, i.e. "../notesSlides/notesSlide1.xml".
+                    let target = attribute(&element, "Target")?;
+                    return match target.strip_prefix('/') {
+                        Some(absolute) => Some(absolute.to_string()),
+                        None => Some(format!("ppt/{}", target.trim_start_matches("../"))),
+                    };
+                }
+            }
+            Ok(Event::Eof) | Err(_) => return None,
+            _ => ()
+        }
+    }
+}
+
+pub fn extract_pptx(bytes: &[u8], url: &str) -> Result<Extracted, String> {
+    let mut archive = open_archive(bytes)?;
+
+    let mut numbers: Vec<u32> = archive.file_names().filter_map(slide_number).collect();
+    numbers.sort_unstable();
+
+    let mut slides: Vec<(SlideText, Vec<String>)> = Vec::new();
+    for number in numbers {
+        let Some(xml) = read_part(&mut archive, &format!("ppt/slides/slide{}.xml", number)) else { continue };
+        let slide = pptx_slide_text(&xml);
+
+        // Speaker notes repeat the slide image and number, only their text is kept.
+        let notes = pptx_notes_part(&mut archive, number)
+            .and_then(|part| read_part(&mut archive, &part))
+            .map(|xml| pptx_slide_text(&xml).body)
+            .unwrap_or_default();
+
+        slides.push((slide, notes));
+    }
+
+    let title = read_part(&mut archive, "docProps/core.xml").and_then(|xml| property_title(&xml));
+    Ok(from_slides(slides, title, url))
+}
+
+// Word paragraphs in order, each with its style (i.e. "Heading1", "Title").
+fn docx_paragraphs(xml: &str) -> Vec<(Option<String>, String)> {
+    let mut reader = Reader::from_str(xml);
+    let mut paragraphs: Vec<(Option<String>, String)> = Vec::new();
+
+    let mut style: Option<String> = None;
+    let mut paragraph: Option<String> = None;
+    let mut in_text = false;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(element)) => match local_name(element.name()).as_str() {
+                "p" => {
+                    style = None;
+                    paragraph = Some(String::new());
+                }
+                "t" => in_text = true,
+                _ => ()
+            },
+            Ok(Event::Empty(element)) => match local_name(element.name()).as_str() {
+                "pStyle" => style = attribute(&element, "w:val"),
+                "tab" | "br" | "cr" => if let Some(paragraph) = paragraph.as_mut() { paragraph.push(' ') },
+                _ => ()
+            },
+            Ok(Event::Text(text)) if in_text => {
+                if let (Some(paragraph), Ok(text)) = (paragraph.as_mut(), text.unescape()) {
+                    paragraph.push_str(&text);
+                }
+            }
+            Ok(Event::End(element)) => match local_name(element.name()).as_str() {
+                "t" => in_text = false,
+                "p" => {
+                    let mut text: Vec<String> = Vec::new();
+                    push_paragraph(&mut text, &paragraph.take().unwrap_or_default());
+                    if let Some(text) = text.pop() {
+                        paragraphs.push((style.take(), text));
+                    }
+                }
+                _ => ()
+            },
+            Ok(Event::Eof) => break,
+            Err(e) => {
+                eprintln!("Malformed document XML: {}", e);
+                break
+            }
+            _ => ()
+        }
+    }
+
+    paragraphs
+}
+
+pub fn extract_docx(bytes: &[u8], url: &str) -> Result<Extracted, String> {
+    let mut archive = open_archive(bytes)?;
+    let xml = read_part(&mut archive, "word/document.xml")
+        .ok_or_else(|| String::from("No word/document.xml"))?;
+
+    let paragraphs = docx_paragraphs(&xml);
+
+    // Section headings (and Title paragraphs) stay in the content in order, and are
+    // kept as headings too - the first one stands in for a missing document title.
+    let headings: Vec<String> = paragraphs.iter()
+        .filter(|(style, _)| style.as_deref().is_some_and(|style| {
+            let style = style.to_lowercase();
+            style == "title" || style.starts_with("heading")
+        }))
+        .map(|(_, text)| text.clone())
+        .collect();
+
+    let content: Vec<String> = paragraphs.into_iter().map(|(_, text)| text).collect();
+    let title = read_part(&mut archive, "docProps/core.xml")
+        .and_then(|xml| property_title(&xml))
+        .or_else(|| headings.first().cloned())
+        .unwrap_or_else(|| file_title(url));
+    let description = describe(&content);
+
+    Ok(Extracted { title, description, content, headings, pages: Vec::new() })
+}
+
+// Slides of an OpenDocument presentation with their notes. Frames of class "title"
+// hold the slide title.
+fn odp_slides(xml: &str) -> Vec<(SlideText, Vec<String>)> {
+    let mut reader = Reader::from_str(xml);
+    let mut slides: Vec<(SlideText, Vec<String>)> = Vec::new();
+
+    let mut slide = SlideText::default();
+    let mut notes: Vec<String> = Vec::new();
+    let mut in_title = false;
+    let mut in_notes = false;
+    let mut paragraph: Option<String> = None;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(element)) => match local_name(element.name()).as_str() {
+                "page" if !in_notes => {
+                    slide = SlideText::default();
+                    notes = Vec::new();
+                }
+                "notes" => in_notes = true,
+                "frame" => in_title = attribute(&element, "presentation:class").as_deref() == Some("title"),
+                "p" | "h" => paragraph = Some(String::new()),
+                _ => ()
+            },
+            Ok(Event::Empty(element)) => match local_name(element.name()).as_str() {
+                "s" | "tab" | "line-break" => if let Some(paragraph) = paragraph.as_mut() { paragraph.push(' ') },
+                _ => ()
+            },
+            Ok(Event::Text(text)) => {
+                if let (Some(paragraph), Ok(text)) = (paragraph.as_mut(), text.unescape()) {
+                    paragraph.push_str(&text);
+                }
+            }
+            Ok(Event::End(element)) => match local_name(element.name()).as_str() {
+                "p" | "h" => {
+                    let Some(text) = paragraph.take() else { continue };
+                    if in_notes {
+                        push_paragraph(&mut notes, &text);
+                    }
+                    else if in_title {
+                        push_paragraph(&mut slide.title, &text);
+                    }
+                    else {
+                        push_paragraph(&mut slide.body, &text);
+                    }
+                }
+                "frame" => in_title = false,
+                "notes" => in_notes = false,
+                "page" if !in_notes => slides.push((std::mem::take(&mut slide), std::mem::take(&mut notes))),
+                _ => ()
+            },
+            Ok(Event::Eof) => break,
+            Err(e) => {
+                eprintln!("Malformed presentation XML: {}", e);
+                break
+            }
+            _ => ()
+        }
+    }
+
+    slides
+}
+
+pub fn extract_odp(bytes: &[u8], url: &str) -> Result<Extracted, String> {
+    let mut archive = open_archive(bytes)?;
+    let xml = read_part(&mut archive, "content.xml")
+        .ok_or_else(|| String::from("No content.xml"))?;
+
+    let slides = odp_slides(&xml);
+    let title = read_part(&mut archive, "meta.xml").and_then(|xml| property_title(&xml));
+    Ok(from_slides(slides, title, url))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+    use super::*;
+
+    fn archive(parts: &[(&str, String)]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, xml) in parts {
+            writer.start_file(*name, SimpleFileOptions::default()).unwrap();
+            writer.write_all(xml.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn pptx_shape(placeholder: &str, text: &str) -> String {
+        format!("<p:sp><p:nvSpPr><p:nvPr>{}</p:nvPr></p:nvSpPr><p:txBody><a:p><a:r><a:t>{}</a:t></a:r></a:p></p:txBody></p:sp>", placeholder, text)
+    }
+
+    fn pptx_slide(shapes: &[String]) -> String {
+        format!("<p:sld><p:cSld><p:spTree>{}</p:spTree></p:cSld></p:sld>", shapes.concat())
+    }
+
+    #[test]
+    fn pptx_slide_titles_are_headings_and_notes_are_content() {
+        let bytes = archive(&[
+            ("ppt/slides/slide1.xml", pptx_slide(&[
+                pptx_shape(r#"<p:ph type="title"/>"#, "The heat equation"),
+                pptx_shape(r#"<p:ph idx="1"/>"#, "u_t = k u_xx"),
+                pptx_shape(r#"<p:ph type="sldNum"/>"#, "1"),
+            ])),
+            ("ppt/slides/_rels/slide1.xml.rels", String::from(
+                r#"<Relationships><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/notesSlide" Target="../notesSlides/notesSlide1.xml"/></Relationships>"#)),
+            ("ppt/notesSlides/notesSlide1.xml", pptx_slide(&[
+                pptx_shape(r#"<p:ph type="sldImg"/>"#, ""),
+                pptx_shape(r#"<p:ph type="body" idx="1"/>"#, "Remind them of Fourier's solution"),
+            ])),
+            ("ppt/slides/slide2.xml", pptx_slide(&[
+                pptx_shape(r#"<p:ph type="ctrTitle"/>"#, "Separation of variables"),
+                pptx_shape("", "u = X(x) T(t)"),
+            ])),
+        ]);
+
+        let extracted = extract_pptx(&bytes, "https://example.org/slides/lecture.pptx").unwrap();
+        assert_eq!(extracted.title, "The heat equation");
+        assert_eq!(extracted.headings, vec!["The heat equation", "Separation of variables"]);
+        assert_eq!(extracted.content, vec!["The heat equation", "u_t = k u_xx", "Remind them of Fourier's solution",
+            "Separation of variables", "u = X(x) T(t)"]);
+    }
+
+    #[test]
+    fn docx_heading_paragraphs_are_headings() {
+        let paragraph = |style: &str, text: &str| format!(
+            r#"<w:p><w:pPr><w:pStyle w:val="{}"/></w:pPr><w:r><w:t>{}</w:t></w:r></w:p>"#, style, text);
+        let body = [
+            paragraph("Title", "Thermodynamics"),
+            paragraph("Heading1", "The first law"),
+            String::from("<w:p><w:r><w:t>Energy is conserved.</w:t></w:r></w:p>"),
+            paragraph("Heading2", "Worked example"),
+        ];
+        let bytes = archive(&[
+            ("word/document.xml", format!("<w:document><w:body>{}</w:body></w:document>", body.concat())),
+            ("docProps/core.xml", String::from("<cp:coreProperties><dc:title>Lecture 3</dc:title></cp:coreProperties>")),
+        ]);
+
+        let extracted = extract_docx(&bytes, "https://example.org/notes/lecture3.docx").unwrap();
+        assert_eq!(extracted.title, "Lecture 3");
+        assert_eq!(extracted.headings, vec!["Thermodynamics", "The first law", "Worked example"]);
+        assert_eq!(extracted.content, vec!["Thermodynamics", "The first law", "Energy is conserved.", "Worked example"]);
+    }
+
+    #[test]
+    fn odp_title_frames_are_headings_and_notes_are_content() {
+        let xml = r#"<office:document-content><office:body><office:presentation>
+            <draw:page draw:name="page1">
+                <draw:frame presentation:class="title"><draw:text-box><text:p>Wave equation</text:p></draw:text-box></draw:frame>
+                <draw:frame presentation:class="outline"><draw:text-box><text:p>Strings and membranes</text:p></draw:text-box></draw:frame>
+                <presentation:notes>
+                    <draw:frame presentation:class="notes"><draw:text-box><text:p>Pluck a string in class</text:p></draw:text-box></draw:frame>
+                </presentation:notes>
+            </draw:page>
+        </office:presentation></office:body></office:document-content>"#;
+        let bytes = archive(&[("content.xml", String::from(xml))]);
+
+        let extracted = extract_odp(&bytes, "https://example.org/slides/Week%205.odp").unwrap();
+        assert_eq!(extracted.title, "Wave equation");
+        assert_eq!(extracted.headings, vec!["Wave equation"]);
+        assert_eq!(extracted.content, vec!["Wave equation", "Strings and membranes", "Pluck a string in class"]);
+    }
+}
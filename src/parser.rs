@@ -1,103 +1,98 @@
-use reqwest::Error;
-use serde_json::json;
-use crate::crawl::CrawlResult;
+use crate::crawl::{CrawlResult, Validators};
+use crate::extract::{extract, ContentKind};
+use crate::boilerplate::{extract_main_content, has_excluded_ancestor};
+use crate::scholarly::{extract_metadata, ScholarlyMetadata};
 use scraper::{Html, Selector};
 use serde::{Serialize, Deserialize};
-use std::process::Command;
-use lingua::{Language, LanguageDetector, LanguageDetectorBuilder};
+use crate::language::detect_language;
+use crate::translate::Translation;
+use crate::images::{extract_images, Image};
+use crate::links::{base_url, canonical_link};
+use crate::dedup::minhash;
+use url::Url;
    
 
 // Structure to store parsed data into Document types which can be indexed.
 // Omitted body as this is wasted information.
-#[derive(Debug, Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Default, Serialize, Deserialize)]
 pub struct Document {
     pub url: String,
     pub content: Vec<String>,
     pub description: String,
-    images: Vec<String>, 
+    // Text of the page's headings (h1-h6) outside navigation, headers and footers.
+    pub headings: Vec<String>,
+    // Informative images with their alt text and captions, shown as thumbnails.
+    pub images: Vec<Image>,
     links: Vec<String>,
     pub title: String,
+    // Validators from the fetch and a hash of the parsed text, used by
+    // recrawls to tell whether the document needs re-indexing.
+    pub validators: Validators,
+    pub content_hash: u64,
+    // Unix timestamp of the fetch, and RSS/Atom feeds linked from the page.
+    pub fetched_at: i64,
+    pub feeds: Vec<String>,
+    // Page number of each content entry for paged documents (PDFs), empty otherwise.
+    pub pages: Vec<u32>,
+    // Percentage (0-100) of the page's text dropped as boilerplate, a quality signal.
+    pub boilerplate_ratio: u8,
+    // Authors, DOI, publication date etc. from citation_*, JSON-LD, Dublin Core and OpenGraph.
+    pub metadata: ScholarlyMetadata,
+    // Detected language (ISO 639-1 code), and the translation where one was made.
+    pub language: Option<String>,
+    pub translation: Option<Translation>,
+    // Text of links from other pages to this one, set from the crawl's link graph.
+    pub anchor_text: Vec<String>,
+    // Declared rel=canonical URL, MinHash signature of the content (empty where too
+    // short to compare), and URLs of near-duplicates collapsed into this document.
+    pub canonical_url: Option<String>,
+    pub minhash: Vec<u64>,
+    pub alternates: Vec<String>,
 }
 
-// Directly spawns child process to docker instance of LibreTranslate server.
-fn _start_translate_server() {
-    let run_translate_server = "docker run -ti --rm -p 127.0.0.1:5000:5000 libretranslate/libretranslate";
-    let output = Command::new("sh").arg("-c").arg(run_translate_server).output().expect("Failed to run translation server.");
-    println!("Starting translation server.");
-    println!("Status of output {}", output.status)
-}
-    
-// Obtains the source language of the text using lingua library.
-fn get_source_language(text: String) -> Option<Language> {
-    let languages: Vec<Language> = vec![Language::English, Language::Ukrainian, Language::Turkish, Language::Thai, Language::Swedish,
-    Language::Spanish, Language::Slovene, Language::Slovak, Language::Russian, Language::Romanian, Language::Portuguese,
-    Language::Polish, Language::Korean, Language::Japanese, Language::Italian, Language::Hungarian, Language::French,
-    Language::Chinese];
-
-    let detector: LanguageDetector = LanguageDetectorBuilder::from_languages(&languages).build();
-    return detector.detect_language_of(text);
+// Layout of Document in the index files kept before segments, frozen so that they
+// still decode as Document grows.
+#[derive(Debug, Hash, Eq, PartialEq, Deserialize)]
+pub struct LegacyDocument {
+    url: String,
+    content: Vec<String>,
+    description: String,
+    images: Vec<String>,
+    links: Vec<String>,
+    title: String,
 }
-    
-// Sends POST request to LibreTranslate API, auto-detecting source language and finally
-// converting to English.
-pub async fn _convert_text_to_english(text: String) -> Result<String, Error> {
-    let mut source = String::new();
-    let mut skip_translation: bool = false;
-
-    //Get the source language of the text
-    let source_response = get_source_language(text.clone());
-
-    match source_response {
-        Some(language) => {
-            let language = language.to_string();
-            if language == "English" {
-                // Skip translation if English (English -> English already satisfied).
-                println!("Body of the text is English  should be skipping");
-                skip_translation = true;
-            }
-            else {
-                source = language;
-            }
-        }
-        None => println!("No language detected.")
-    }
-        
-    if !skip_translation {
-        let client = reqwest::Client::new();
-        let res = client.post("http://0.0.0.0:5000/translate")
-            .header("Content-Type", "application/json")
-            .json(&json!({
-                "q": text,
-                "source": source,
-                "target": "en"
-            }))
-            .send().await?;
-
-        let status = res.status();
-        if status.is_success() {
-            let text_response = res.text().await;
-            match text_response {
-                Ok(text) => {
-                    println!("Outputs the translated text {}", text);
-                    Ok(text)
-                }
-                Err(e) => panic!("Translation error: {}", e)
-            }
-        }
 
-        else {
-            println!("Translation error: {}", status);
-            Ok("Skip".to_string())
+impl From<LegacyDocument> for Document {
+    fn from(legacy: LegacyDocument) -> Self {
+        Document {
+            content_hash: content_hash(&legacy.title, &legacy.description, &legacy.content),
+            images: legacy.images.into_iter().map(|url| Image { url, ..Default::default() }).collect(),
+            url: legacy.url,
+            content: legacy.content,
+            description: legacy.description,
+            links: legacy.links,
+            title: legacy.title,
+            ..Default::default()
         }
     }
+}
 
-    else {
-        Ok("Skip".to_string())
+// FNV-1a over the parsed title, description and content. Stable across builds
+// (unlike DefaultHasher) so it can be stored in the indices.
+pub fn content_hash(title: &str, description: &str, content: &[String]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let fields = [title, description].into_iter().chain(content.iter().map(String::as_str));
+
+    for field in fields {
+        // Separator byte so field boundaries affect the hash.
+        for byte in field.bytes().chain(std::iter::once(0xff)) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
     }
+    hash
 }
 
-
-    
 // Implement behaviour for println! on Document type.
 impl std::fmt::Display for Document {
     fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -107,43 +102,61 @@ impl std::fmt::Display for Document {
 }
 
 
+impl Document {
+    // Link for a search result - for paged documents, the URL with a #page= fragment
+    // pointing at the page sharing the most words with the query.
+    pub fn page_link(&self, query: &str) -> String {
+        let query_words: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+
+        let best_page = self.content.iter()
+            .zip(&self.pages)
+            .map(|(text, page)| {
+                let text = text.to_lowercase();
+                let matches = query_words.iter().filter(|word| text.contains(word.as_str())).count();
+                (matches, std::cmp::Reverse(*page))
+            })
+            .filter(|(matches, _)| *matches > 0)
+            .max();
+
+        match best_page {
+            Some((_, std::cmp::Reverse(page))) => format!("{}#page={}", self.url, page),
+            None => self.url.clone()
+        }
+    }
+}
+
+// Headings of the page in document order, leaving out those of navigation, banners
+// and footers.
+fn parse_headings(document: &Html, heading_selector: &Selector) -> Vec<String> {
+    document.select(heading_selector)
+        .filter(|element| !has_excluded_ancestor(*element))
+        .map(|element| element.text().collect::<Vec<&str>>().join(" ").split_whitespace().collect::<Vec<&str>>().join(" "))
+        .filter(|text| !text.is_empty())
+        .collect()
+}
+
 // Parse all non-metadata text content.
-fn parse_content(document: Html, content_selector: &Selector, mut content:Vec<String>, skip_translation: bool) -> Result<Vec<String>, Error> {
+fn parse_content(document: Html, content_selector: &Selector, mut content:Vec<String>) -> Vec<String> {
     for element in document.select(content_selector) {
         let text = element.text().collect::<String>();
         if !text.is_empty() {
-            if !skip_translation {
-                println!("Skipping translation for {}", text);
-                    /*
-                    let text_translation = convert_text_to_english(text.clone()).await;
-                    match text_translation {
-                        Err(e) => panic!("Error in translation: {}", e),
-                        Ok(result) => {
-                            if result != "Skip" {
-                                text = result;
-                            }
-                            else {
-                                println!("Skipping translation for {}", text);
-                
-                            }
-                        }       
-                    }
-                    */
-            }
             content.push(text);
         }
     }
 
-    Ok(content)
+    content
 
 }
     
 // Parse all results from crawl bot.
 pub fn parse_crawl_results(crawl_results: Vec<CrawlResult>) -> Vec<Document> {
-    //start_translate_server();
     let mut parsed_results: Vec<Document> = Vec::new();
     for crawl_result in crawl_results {
-        let result = parse_crawl_result(crawl_result);
+        // HTML is parsed here, anything else goes through the extractor for its format.
+        let result = match crawl_result.content_type {
+            ContentKind::Html => parse_crawl_result(crawl_result),
+            _ => parse_extracted(crawl_result)
+        };
         match result {
             Ok(document) => parsed_results.push(document),
             Err(_) => println!("Skipping parsing the result")
@@ -152,26 +165,67 @@ pub fn parse_crawl_results(crawl_results: Vec<CrawlResult>) -> Vec<Document> {
     parsed_results
 }
     
-// Parses raw HTML data from crawler and returns Document type.
-fn parse_crawl_result(crawl_result: CrawlResult) -> Result<Document, String> {
+// Title followed by the start of the content, enough to tell the language from.
+fn language_sample(title: &str, content: &[String]) -> String {
+    let mut sample = title.to_string();
+    for text in content {
+        if sample.chars().count() >= 1000 {
+            break;
+        }
+        sample.push(' ');
+        sample.push_str(text);
+    }
+    sample
+}
+
+// Builds a Document from the text an extractor pulls out of a non-HTML resource.
+fn parse_extracted(crawl_result: CrawlResult) -> Result<Document, String> {
+    let extracted = extract(&crawl_result)?;
 
-    let mut skip_translation: bool = false;
+    if crawl_result.url.is_empty() || extracted.title.is_empty() || extracted.content.is_empty() {
+        return Err(String::from("Skip"));
+    }
 
-    let available_languages: Vec<&str> = vec!["English", "Ukrainian", "Turkish", "Thai", "Swedish", 
-        "Spanish", "Slovene", "Slovak", "Russian", "Romanian", "Portuguese", "Polish", "Korean", "Japanese", 
-        "Italian", "Hungarian", "French"];
+    let content_hash = content_hash(&extracted.title, &extracted.description, &extracted.content);
+    let language = detect_language(&language_sample(&extracted.title, &extracted.content));
+    let minhash = minhash(&extracted.content);
+
+    Ok(Document {
+        url: crawl_result.url,
+        content: extracted.content,
+        description: extracted.description,
+        headings: extracted.headings,
+        images: Vec::new(),
+        links: crawl_result.new_urls,
+        title: extracted.title,
+        validators: crawl_result.validators,
+        content_hash,
+        fetched_at: crawl_result.fetched_at,
+        feeds: crawl_result.feeds,
+        pages: extracted.pages,
+        boilerplate_ratio: 0,
+        metadata: ScholarlyMetadata::default(),
+        language,
+        translation: None,
+        anchor_text: Vec::new(),
+        canonical_url: None,
+        minhash,
+        alternates: Vec::new(),
+    })
+}
+
+// Parses raw HTML data from crawler and returns Document type.
+fn parse_crawl_result(crawl_result: CrawlResult) -> Result<Document, String> {
 
     // first initialise entries already in the crawled results
     let url     = crawl_result.url;
     let body    = crawl_result.body;
     let links   = crawl_result.new_urls;
-
-    //metadata entries
-    let mut description = String::new();
+    let validators = crawl_result.validators;
+    let fetched_at = crawl_result.fetched_at;
+    let feeds   = crawl_result.feeds;
 
     //non-metadata entries
-    let mut content: Vec<String> = Vec::new();
-    let mut images: Vec<String> = Vec::new();
     let mut title                = String::new(); 
     let document = Html::parse_document(&body);
         
@@ -181,68 +235,11 @@ fn parse_crawl_result(crawl_result: CrawlResult) -> Result<Document, String> {
     let title_selector    = Selector::parse("title").unwrap();
     let p_selector        = Selector::parse("p").unwrap();
     let h_selector        = Selector::parse("h1, h2, h3, h4, h5, h6").unwrap();
-    let metadata_selector = Selector::parse("meta").unwrap();
-    let image_selector    = Selector::parse("img").unwrap();
 
     
     // First select the title to include as part of the results
     if let Some(title_element) = document.select(&title_selector).next() {
-
-        //Get the title element
         title = title_element.text().collect::<String>();
-        println!("Originally setting title: {}", title);
-
-        // Check to see if title is in the list of available languages
-        let lang = get_source_language(title.clone());
-
-        match lang {
-            Some(language) => {
-                let language_str = language.to_string();
-                if (!available_languages.contains(&language_str.as_str())) || language_str != "English" {
-                    println!("Not translatable (or bypass - text is non-english): {}", title);
-
-                    // Only return error - that is skip parsing the current page - if language
-                    // is not in available LibreTranslate models.
-                        
-                    // BYPASS: default skip translation if non-English.
-
-                    return Err("Skip".to_string())
-                }
-                    
-                if language_str == "English" {
-                    skip_translation = true;
-                }
-                    
-            },
-
-            None => ()
-        }
-        
-        // In the event that it is non-English.
-        if !skip_translation {
-            ();
-
-                /*
-                let title_translation = convert_text_to_english(title.clone()).await;
-                match title_translation {
-                    // Immediately handle the error case from convert_text_to_english function. 
-                    Err(e) => panic!("Translation error: {}", e),
-                    // Modify to keep the title as is without translation.
-                    Ok(text) => {
-                        if text != "Skip" {
-                            title = text;
-                        }
-                        else {
-                            ();
-                        }
-                    }
-                }
-                */
-        }
-        // In event text data is English.
-        else {
-            println!("Title is fine as is without translation: {}", title);
-        }
     }
 
     else {
@@ -250,78 +247,65 @@ fn parse_crawl_result(crawl_result: CrawlResult) -> Result<Document, String> {
     }
          
 
-    // Parse first h elements and then p elements to gather all of the text content.
-        
-    content = parse_content(document.clone(), &p_selector, parse_content(document.clone(), &h_selector, content, skip_translation).unwrap(), skip_translation).unwrap();
-
-    // Working with metadata: consists of two attributes (name) and (content), where name is covered by cases we want to include, such as description,
-    //author, keywords, etc.
-
-    // Hence need to parse all meta tags and then provide cases for all the above we would like to include.
+    // Keep the main content only - headings, paragraphs, lists, tables, <pre> and definition
+    // lists - leaving navigation, banners and footers out of the index.
+    let main_content = extract_main_content(&document);
+    let headings = parse_headings(&document, &h_selector);
+    let boilerplate_ratio = main_content.boilerplate_ratio;
+    let mut content = main_content.blocks;
+
+    // Parse first h elements and then p elements to gather all of the text content,
+    // where no main content could be found.
+    if content.is_empty() {
+        content = parse_content(document.clone(), &p_selector, parse_content(document.clone(), &h_selector, content));
+    }
 
-    // Alternatively use the following syntax inside of the selector: let description_selector = Selector::parse("meta[name='description']").unwrap();
-        
-        
-    for element in document.select(&metadata_selector) {
-        if let Some(name) = element.value().attr("name") {
-            match name {
-                "description" => {
-                    if let Some(content) = element.value().attr("content") {
-                        if !skip_translation {
-                            println!("Skipping translation");
-                                /*
-                                let description_translation = convert_text_to_english(content.to_string()).await;
-                                match description_translation {
-                                    Err(e) => panic!("Translation error: {}", e),
-                                    Ok(text) => description = text
-                                }
-                                */
-                                
-                        }
-                        else {
-                            if description != "Skip" {
-                                description = content.to_string();
-                            }
-                        }
-                    }
-                },
-                _ => {
-                    println!("Do nothing");
-                }
-            }
-        }
+    // Meta tags are read by extract_metadata - the description tag, else the abstract of
+    // the structured metadata, and a title standing in where the page has none.
+    let page_metadata = extract_metadata(&document);
+    if title.trim().is_empty() {
+        title = page_metadata.title.clone().unwrap_or_default();
     }
+    let description = page_metadata.description.clone().unwrap_or_default();
          
 
        
-    // Working with images: need to convert string stored in the src attribute of the img tag as a U8 vector (vector of integer bytes), this can
-    // then be converted back to base64 format which allows us to display the images in the search interface.
-    // Currently, we assume the logo is at the root of the DOM tree - and will be the first image
-    // processed by the selector.
-   
-    // TO DO: may need to modify to extract the MIME type string to decode the image.
-
-    for element in document.select(&image_selector) {
-        if images.len() > 3 {
-            break
-        }
-        match element.value().attr("src") {
-            Some(src) => images.push(String::from(src)),
-            None => ()
-        }
-    }
+    // Images are kept as absolute URLs (resolved against <base href> where the page has one)
+    // with their alt text, title, caption and surrounding text - icons, logos and tracking
+    // pixels are dropped.
+    let base = Url::parse(&url).ok().map(|page_url| base_url(&page_url, &document));
+    let images = base.as_ref().map_or_else(Vec::new, |base| extract_images(&document, base));
+    let canonical_url = base.as_ref().and_then(|base| canonical_link(&document, base));
 
     if url.is_empty() || body.is_empty() || title.is_empty() || content.is_empty() {
         return Err(String::from("Skip"));
     }
 
+    let content_hash = content_hash(&title, &description, &content);
+    let language = detect_language(&language_sample(&title, &content));
+    let minhash = minhash(&content);
+
     let new_document = Document {
         url,
         content,
         description,
+        headings,
         images,
         links,
         title,
+        validators,
+        content_hash,
+        fetched_at,
+        feeds,
+        pages: Vec::new(),
+        boilerplate_ratio,
+        metadata: page_metadata.metadata,
+        language,
+        translation: None,
+        anchor_text: Vec::new(),
+        canonical_url,
+        minhash,
+        alternates: Vec::new(),
     };
 
     Ok(new_document)
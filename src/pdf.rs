@@ -0,0 +1,62 @@
+// Module extracts text from PDFs (lecture notes, problem sheets, preprints). Each page
+// becomes one content entry, with its page number kept so results can link to it.
+
+use lopdf::{decode_text_string, Document as PdfDocument};
+use crate::extract::{describe, Extracted};
+
+
+// Longest line taken to be a heading where the PDF has no usable Title.
+const MAX_HEADING_CHARS: usize = 150;
+
+pub fn extract_pdf(bytes: &[u8]) -> Result<Extracted, String> {
+    // pdf-extract panics on some malformed fonts and streams, which must not take
+    // down the rest of the parse.
+    let pages = std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem_by_pages(bytes))
+        .map_err(|_| String::from("PDF extraction panicked"))?
+        .map_err(|e| e.to_string())?;
+
+    let mut content: Vec<String> = Vec::new();
+    let mut page_numbers: Vec<u32> = Vec::new();
+
+    for (index, page) in pages.iter().enumerate() {
+        let text = page.split_whitespace().collect::<Vec<&str>>().join(" ");
+        if !text.is_empty() {
+            content.push(text);
+            page_numbers.push(index as u32 + 1);
+        }
+    }
+
+    let title = metadata_title(bytes)
+        .or_else(|| first_heading(&pages))
+        .unwrap_or_default();
+    let description = describe(&content);
+
+    Ok(Extracted { title, description, content, headings: Vec::new(), pages: page_numbers })
+}
+
+// Title from the document information dictionary, ignoring the file names and
+// placeholders that authoring tools often leave there.
+fn metadata_title(bytes: &[u8]) -> Option<String> {
+    let document = PdfDocument::load_mem(bytes).ok()?;
+    let info = document.trailer.get_deref(b"Info", &document).ok()?.as_dict().ok()?;
+    let title = decode_text_string(info.get_deref(b"Title", &document).ok()?).ok()?;
+    let title = title.trim();
+
+    let lower = title.to_lowercase();
+    let is_placeholder = lower.is_empty()
+        || lower == "untitled"
+        || lower.starts_with("microsoft word - ")
+        || [".pdf", ".doc", ".docx", ".dvi", ".tex", ".ps"].iter().any(|extension| lower.ends_with(extension));
+
+    if is_placeholder { None } else { Some(title.to_string()) }
+}
+
+// First line of text that reads like a heading, i.e. has letters in and is not
+// a whole paragraph.
+fn first_heading(pages: &[String]) -> Option<String> {
+    pages.iter()
+        .flat_map(|page| page.lines())
+        .map(str::trim)
+        .find(|line| line.chars().any(char::is_alphabetic) && line.chars().count() <= MAX_HEADING_CHARS)
+        .map(String::from)
+}
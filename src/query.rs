@@ -0,0 +1,341 @@
+// Module parses search queries into the terms BM25 scores and the positional clauses
+// documents must satisfy - "exact phrases", and proximity operators (i.e.
+// fourier NEAR/3 transform - within 3 words of each other, in either order). Prefixes
+// (algor*) and term ranges ([algebra TO algorithm]) are expanded from the vocabulary of
+// the B-Tree index.
+
+use std::cmp::Reverse;
+use std::ops::Bound;
+use crate::analysis::Analyser;
+use crate::segment::SegmentedIndex;
+
+
+// Distance of a NEAR written without one.
+pub const DEFAULT_NEAR_DISTANCE: u32 = 10;
+// Shortest prefix expanded, so that i.e. a* does not match most of the vocabulary.
+const MIN_PREFIX_CHARS: usize = 2;
+// Terms of the vocabulary walked per prefix or range, and the most widely used of
+// them kept.
+const MAX_SCANNED: usize = 10000;
+const MAX_EXPANSIONS: usize = 50;
+
+// Terms of a phrase with their offsets from its first word.
+#[derive(Debug, Clone)]
+pub struct Phrase {
+    pub terms: Vec<(u32, String)>,
+}
+
+impl Phrase {
+    fn new(text: &str, analyser: &Analyser, language: Option<&str>) -> Self {
+        Phrase { terms: analyser.analyse_query(text, language).into_iter().enumerate().map(|(offset, term)| (offset as u32, term)).collect() }
+    }
+
+    // Number of words the phrase spans.
+    fn len(&self) -> u32 {
+        self.terms.last().map(|(offset, _)| offset + 1).unwrap_or(0)
+    }
+
+    // Positions the phrase starts at in a document, given the positions of each term
+    // there. Terms is_skipped holds for (stop words left out of the document's index)
+    // match any word. None where every term is skipped, so the phrase cannot be checked.
+    pub fn starts<'a>(&self, positions: impl Fn(&str) -> Option<&'a [u32]>, is_skipped: impl Fn(&str) -> bool) -> Option<Vec<u32>> {
+        let indexed: Vec<&(u32, String)> = self.terms.iter().filter(|(_, term)| !is_skipped(term)).collect();
+        let (first_offset, first_term) = indexed.first()?;
+
+        let starts = positions(first_term).unwrap_or_default().iter()
+            .filter_map(|position| position.checked_sub(*first_offset))
+            .filter(|start| indexed[1..].iter().all(|(offset, term)| {
+                positions(term).is_some_and(|found| found.binary_search(&(start + offset)).is_ok())
+            }))
+            .collect();
+        Some(starts)
+    }
+}
+
+// Two phrases (or single words) within distance words of each other.
+#[derive(Debug, Clone)]
+pub struct Near {
+    pub left: Phrase,
+    pub right: Phrase,
+    pub distance: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct Query {
+    // Every term of the query in order, phrases and NEAR operands included.
+    pub terms: Vec<String>,
+    pub phrases: Vec<Phrase>,
+    pub near: Vec<Near>,
+    pub prefixes: Vec<String>,
+    // Inclusive bounds of each range, in order.
+    pub ranges: Vec<(String, String)>,
+}
+
+enum Token {
+    Word(String),
+    Quoted(String),
+    Near(u32),
+    Prefix(String),
+    Range(String, String),
+}
+
+// The single term a query word analyses to, i.e. the prefix of algor* or a range bound.
+fn single_term(word: &str, analyser: &Analyser, language: Option<&str>) -> Option<String> {
+    let mut terms = analyser.analyse_query(word, language);
+    if terms.len() != 1 {
+        return None;
+    }
+    terms.pop()
+}
+
+fn word_token(word: &str, analyser: &Analyser, language: Option<&str>) -> Token {
+    if let Some(distance) = near_operator(word) {
+        return Token::Near(distance);
+    }
+    if let Some(prefix) = word.strip_suffix('*').and_then(|prefix| single_term(prefix, analyser, language)).filter(|prefix| prefix.chars().count() >= MIN_PREFIX_CHARS) {
+        return Token::Prefix(prefix);
+    }
+    Token::Word(word.to_string())
+}
+
+// Bounds of a range written as [from TO to].
+fn range_token(body: &str, analyser: &Analyser, language: Option<&str>) -> Option<Token> {
+    let (from, to) = body.split_once(" TO ")?;
+    let (from, to) = (single_term(from, analyser, language)?, single_term(to, analyser, language)?);
+    Some(if from <= to { Token::Range(from, to) } else { Token::Range(to, from) })
+}
+
+// NEAR or NEAR/n, upper case so that "near" as a word is still searched for.
+pub fn near_operator(word: &str) -> Option<u32> {
+    match word.strip_prefix("NEAR")? {
+        "" => Some(DEFAULT_NEAR_DISTANCE),
+        rest => rest.strip_prefix('/')?.parse().ok(),
+    }
+}
+
+fn tokens(query: &str, analyser: &Analyser, language: Option<&str>) -> Vec<Token> {
+    let mut tokens: Vec<Token> = Vec::new();
+    // Text between an opening quote and the next (or the end of the query) is a phrase.
+    for (index, part) in query.split('"').enumerate() {
+        if index % 2 == 1 {
+            tokens.push(Token::Quoted(part.to_string()));
+            continue;
+        }
+        // Text between square brackets is a range, where it has the form of one.
+        for (index, piece) in part.split('[').enumerate() {
+            let rest = match piece.split_once(']') {
+                Some((body, rest)) if index > 0 => match range_token(body, analyser, language) {
+                    Some(range) => {
+                        tokens.push(range);
+                        rest
+                    }
+                    None => piece,
+                },
+                _ => piece,
+            };
+            tokens.extend(rest.split_whitespace().map(|word| word_token(word, analyser, language)));
+        }
+    }
+    tokens
+}
+
+impl Query {
+    // Parses the query with the analyser of the indices it is run against, in the
+    // language of the query (the default language of the analyser where not given).
+    pub fn parse(query: &str, analyser: &Analyser, language: Option<&str>) -> Self {
+        let mut operands: Vec<Phrase> = Vec::new();
+        let mut quoted: Vec<bool> = Vec::new();
+        // Indices of the operands either side of each NEAR.
+        let mut near: Vec<(usize, usize, u32)> = Vec::new();
+        let mut pending: Option<u32> = None;
+        let mut prefixes: Vec<String> = Vec::new();
+        let mut ranges: Vec<(String, String)> = Vec::new();
+
+        for token in tokens(query, analyser, language) {
+            let (phrase, is_quoted) = match token {
+                Token::Near(distance) => {
+                    pending = Some(distance);
+                    continue;
+                }
+                // Expanded terms have no positions of their own, so cannot be NEAR operands.
+                Token::Prefix(prefix) => {
+                    prefixes.push(prefix);
+                    pending = None;
+                    continue;
+                }
+                Token::Range(from, to) => {
+                    ranges.push((from, to));
+                    pending = None;
+                    continue;
+                }
+                Token::Word(word) => (Phrase::new(&word, analyser, language), false),
+                Token::Quoted(text) => (Phrase::new(&text, analyser, language), true),
+            };
+            // Words with nothing left to search for (i.e. punctuation) are dropped.
+            if phrase.terms.is_empty() {
+                continue;
+            }
+
+            if let Some(distance) = pending.take() {
+                if !operands.is_empty() {
+                    near.push((operands.len() - 1, operands.len(), distance));
+                }
+            }
+            operands.push(phrase);
+            quoted.push(is_quoted);
+        }
+
+        let in_near = |index: usize| near.iter().any(|(left, right, _)| *left == index || *right == index);
+        Query {
+            terms: operands.iter().flat_map(|phrase| phrase.terms.iter().map(|(_, term)| term.clone())).collect(),
+            phrases: operands.iter().enumerate()
+                .filter(|(index, _)| quoted[*index] && operands[*index].terms.len() > 1 && !in_near(*index))
+                .map(|(_, phrase)| phrase.clone())
+                .collect(),
+            near: near.iter()
+                .map(|(left, right, distance)| Near { left: operands[*left].clone(), right: operands[*right].clone(), distance: *distance })
+                .collect(),
+            prefixes,
+            ranges,
+        }
+    }
+
+    // Terms of the B-Tree index matching the query's prefixes and ranges - for each, the
+    // ones found in the most documents of the inverted index.
+    pub fn expansions(&self, btree: &SegmentedIndex, index: &SegmentedIndex) -> Vec<String> {
+        let prefixes = self.prefixes.iter().map(|prefix| {
+            btree.vocabulary((Bound::Included(prefix.as_str()), Bound::Unbounded))
+                .take_while(|term| term.starts_with(prefix.as_str()))
+                .take(MAX_SCANNED)
+                .collect::<Vec<&str>>()
+        });
+        let ranges = self.ranges.iter().map(|(from, to)| {
+            btree.vocabulary((Bound::Included(from.as_str()), Bound::Included(to.as_str())))
+                .take(MAX_SCANNED)
+                .collect::<Vec<&str>>()
+        });
+
+        prefixes.chain(ranges)
+            .flat_map(|mut terms| {
+                terms.sort_by_key(|term| Reverse(index.doc_freq(term)));
+                terms.truncate(MAX_EXPANSIONS);
+                terms
+            })
+            .map(str::to_string)
+            .collect()
+    }
+
+    // Whether the query holds clauses needing term positions.
+    pub fn is_positional(&self) -> bool {
+        !self.phrases.is_empty() || !self.near.is_empty()
+    }
+
+    // Whether a document satisfies every phrase and NEAR clause, given the positions
+    // of each term in it. Clauses made up of skipped terms alone are not checked.
+    pub fn matches<'a>(&self, positions: impl Fn(&str) -> Option<&'a [u32]> + Copy, is_skipped: impl Fn(&str) -> bool + Copy) -> bool {
+        let phrases = self.phrases.iter()
+            .all(|phrase| phrase.starts(positions, is_skipped).is_none_or(|starts| !starts.is_empty()));
+
+        phrases && self.near.iter().all(|near| {
+            match (near.left.starts(positions, is_skipped), near.right.starts(positions, is_skipped)) {
+                (Some(left), Some(right)) => left.iter().any(|a| right.iter().any(|b| {
+                    // Words between the end of one and the start of the other.
+                    let gap = if a <= b { b.saturating_sub(a + near.left.len()) } else { a.saturating_sub(b + near.right.len()) };
+                    gap <= near.distance
+                })),
+                (Some(starts), None) | (None, Some(starts)) => !starts.is_empty(),
+                (None, None) => true,
+            }
+        })
+    }
+}
+
+// Smallest distance between a position in a and one in b, both sorted.
+pub fn min_distance(a: &[u32], b: &[u32]) -> Option<u32> {
+    let (mut i, mut j) = (0, 0);
+    let mut best: Option<u32> = None;
+    while i < a.len() && j < b.len() {
+        let distance = a[i].abs_diff(b[j]);
+        best = Some(best.map_or(distance, |best| best.min(distance)));
+        if a[i] < b[j] { i += 1 } else { j += 1 }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::analysis::AnalysisConfig;
+    use super::*;
+
+    fn parse(query: &str) -> Query {
+        Query::parse(query, &Analyser::from_config(&AnalysisConfig::default()), None)
+    }
+
+    fn phrase_terms(phrase: &Phrase) -> Vec<&str> {
+        phrase.terms.iter().map(|(_, term)| term.as_str()).collect()
+    }
+
+    #[test]
+    fn parses_quoted_phrases() {
+        let query = parse("\"fourier transform\" heat");
+        assert_eq!(query.terms, vec!["fourier", "transform", "heat"]);
+        assert_eq!(query.phrases.len(), 1);
+        assert_eq!(query.phrases[0].terms, vec![(0, String::from("fourier")), (1, String::from("transform"))]);
+        assert!(query.near.is_empty());
+
+        // A single quoted word needs no positions.
+        assert!(!parse("\"fourier\"").is_positional());
+    }
+
+    #[test]
+    fn parses_near_operators() {
+        let query = parse("fourier NEAR/3 \"heat equation\"");
+        assert_eq!(query.near.len(), 1);
+        assert_eq!(phrase_terms(&query.near[0].left), vec!["fourier"]);
+        assert_eq!(phrase_terms(&query.near[0].right), vec!["heat", "equat"]);
+        assert_eq!(query.near[0].distance, 3);
+        assert!(query.phrases.is_empty());
+
+        assert_eq!(parse("fourier NEAR transform").near[0].distance, DEFAULT_NEAR_DISTANCE);
+        // Lower case near is searched for as a word.
+        let query = parse("fourier near transform");
+        assert!(query.near.is_empty());
+        assert_eq!(query.terms, vec!["fourier", "near", "transform"]);
+    }
+
+    #[test]
+    fn parses_prefixes_and_ranges() {
+        let query = parse("algor* [zeta TO alpha] a*");
+        assert_eq!(query.prefixes, vec!["algor"]);
+        assert_eq!(query.ranges, vec![(String::from("alpha"), String::from("zeta"))]);
+        // Prefixes too short to expand are searched for as words.
+        assert_eq!(query.terms, vec!["a"]);
+    }
+
+    #[test]
+    fn analyses_queries_in_their_language() {
+        let analyser = Analyser::from_config(&AnalysisConfig::default());
+        let indexed: Vec<String> = analyser.analyse("ordinateurs portables", Some("fr")).into_iter().map(|token| token.text).collect();
+
+        // Stemmed as English, a French query misses the terms of French documents.
+        assert_ne!(Query::parse("ordinateurs portables", &analyser, None).terms, indexed);
+        assert_eq!(Query::parse("ordinateurs portables", &analyser, Some("fr")).terms, indexed);
+
+        // Collections in French analyse queries as French by default.
+        let french = Analyser::from_config(&AnalysisConfig { default_language: String::from("fr"), ..Default::default() });
+        assert_eq!(Query::parse("ordinateurs portables", &french, None).terms, indexed);
+    }
+
+    #[test]
+    fn matches_phrases_and_near_clauses_by_position() {
+        let positions: HashMap<&str, Vec<u32>> = HashMap::from([("fourier", vec![4]), ("transform", vec![5]), ("heat", vec![12])]);
+        let lookup = |term: &str| positions.get(term).map(Vec::as_slice);
+        let never_skipped = |_: &str| false;
+
+        assert!(parse("\"fourier transform\"").matches(lookup, never_skipped));
+        assert!(!parse("\"transform fourier\"").matches(lookup, never_skipped));
+        assert!(parse("transform NEAR/6 heat").matches(lookup, never_skipped));
+        assert!(!parse("transform NEAR/5 heat").matches(lookup, never_skipped));
+    }
+}
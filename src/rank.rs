@@ -7,7 +7,12 @@
     use linfa::Dataset;
     use linfa::traits::{Fit, Predict};
     use crate::parser::Document;
-    use crate::index::{Indexer, InvertedInfo, read_index_file};
+    use crate::index::{Field, FieldLengths, InvertedInfo, FIELDS};
+    use crate::segment::{SegmentedIndex, SegmentKind};
+    use crate::store::{DocId, DocumentStore};
+    use crate::shared::IndexSnapshot;
+    use crate::query::{min_distance, Query};
+    use std::collections::HashSet;
     use std::process::Command;
     use serde_json::Value;
     use serde::{Serialize, Deserialize};
@@ -50,10 +55,15 @@
         }
     }
 
-    fn collect_terms (index: Indexer) -> Option<HashMap<Document, Vec<String>>> {
-        match index {
-            Indexer::TermIndex (map) => { Some(map) }
-            Indexer::InvertedIndex (_map) => { None }
+    fn collect_terms (index: &SegmentedIndex, store: &DocumentStore, matching: Option<&HashSet<DocId>>) -> Option<HashMap<Document, Vec<String>>> {
+        match index.kind() {
+            SegmentKind::TermIndex => {
+                Some(index.documents()
+                    .filter(|(id, _)| matching.is_none_or(|matching| matching.contains(id)))
+                    .filter_map(|(id, terms)| Some((store.get(id)?, terms)))
+                    .collect())
+            }
+            _ => { None }
         }
     }
 
@@ -283,9 +293,12 @@
     // TO DO:
     // Prevent use of PCA where cosine similarity is used.
     // ENSURE cosine similarity is not implemented as cosine distance for the above.
-    pub fn get_clustered_rankings (query: String, index: Indexer, script: &str) -> Result<Vec<Document>, String> { 
+    pub fn get_clustered_rankings (query: String, language: Option<&str>, snapshot: &IndexSnapshot, script: &str) -> Result<Vec<Document>, String> { 
+        // Phrases and NEAR clauses narrow down the documents clustered.
+        let matching = matching_documents(&Query::parse(&query, &snapshot.analyser, language), &snapshot.inverted, snapshot);
+
         let document_terms;
-        match collect_terms (index) {
+        match collect_terms (&snapshot.forward, &snapshot.store, matching.as_ref()) {
             Some (map) => {
                 document_terms = map;
             },
@@ -317,7 +330,8 @@
             Err(_) => return Err(String::from("4"))
         }
 
-        let parsed_query = query.to_string().replace("\"", "").trim().split_whitespace().map(str::to_string).collect();
+        // The query is analysed as the terms of the documents embedded were.
+        let parsed_query = Query::parse(&query, &snapshot.analyser, language).terms;
         
         let query_embeddings = make_embeddings(parsed_query, script)?;
         
@@ -358,44 +372,126 @@
     }
      
 
-    struct BM25 {
-        k1: f64,
-        b: f64,
-        avg_doc_len: f64,
-        doc_terms: HashMap<Document, Vec<String>>,
-        inverted: HashMap<String, Vec<InvertedInfo>>,
-        doc_count: usize
+    // Weight of a field's term frequencies, and how far they are normalised by the
+    // field's length (b, from 0 - not at all - to 1).
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+    pub struct FieldWeight {
+        pub weight: f64,
+        pub b: f64,
     }
 
-    impl BM25 {
-        fn new(k1: f64, b: f64, document_terms: HashMap<Document, Vec<String>>, inverted: HashMap<String, Vec<InvertedInfo>>) -> Self {
-            // We can compute doc_count using the document_terms map.
-            // We can compute doc lengths on demand with document_terms map.
-            // We can compute average document length using document_terms map.
-            let mut total: f64 = 0.0;
-            let doc_count = document_terms.len();
-
-            // Compute avg_doc_len.
-            for (_, terms) in &document_terms {
-                // Compute doc length.
-                let doc_len = terms.len() as f64;
-                total += doc_len;
+    // BM25F settings, part of SearchParams. Missing fields fall back to the defaults below.
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    #[serde(default)]
+    pub struct ScoringConfig {
+        pub k1: f64,
+        pub title: FieldWeight,
+        pub description: FieldWeight,
+        pub headings: FieldWeight,
+        pub keywords: FieldWeight,
+        pub anchor: FieldWeight,
+        pub body: FieldWeight,
+    }
+
+    impl Default for ScoringConfig {
+        fn default() -> Self {
+            ScoringConfig {
+                k1: 1.5,
+                title: FieldWeight { weight: 3.0, b: 0.5 },
+                description: FieldWeight { weight: 1.5, b: 0.5 },
+                headings: FieldWeight { weight: 2.0, b: 0.6 },
+                keywords: FieldWeight { weight: 2.0, b: 0.3 },
+                anchor: FieldWeight { weight: 1.0, b: 0.75 },
+                body: FieldWeight { weight: 1.0, b: 0.75 },
             }
-            
-            let avg_doc_len = total / doc_count as f64;
-            BM25 { k1, b, avg_doc_len, doc_terms: document_terms, inverted, doc_count }
+        }
+    }
+
+    impl ScoringConfig {
+        fn field(&self, field: Field) -> FieldWeight {
+            match field {
+                Field::Title => self.title,
+                Field::Description => self.description,
+                Field::Headings => self.headings,
+                Field::Keywords => self.keywords,
+                Field::Anchor => self.anchor,
+                Field::Body => self.body,
+            }
+        }
+    }
+    // Boost for consecutive query terms found close together in a document - the weight
+    // over the distance between them, for distances up to the window.
+    const PROXIMITY_WEIGHT: f64 = 1.0;
+    const PROXIMITY_WINDOW: u32 = 10;
+
+    // Postings of each query term, keyed by document.
+    type QueryPostings = HashMap<String, HashMap<DocId, InvertedInfo>>;
+
+    fn query_postings(terms: &[String], index: &SegmentedIndex) -> QueryPostings {
+        terms.iter()
+            .filter_map(|term| Some((term.clone(), index.postings(term)?.into_iter().map(|info| (info.doc_id, info)).collect())))
+            .collect()
+    }
+
+    fn positions_in<'a>(postings: &'a QueryPostings, term: &str, id: DocId) -> Option<&'a [u32]> {
+        postings.get(term)?.get(&id).map(|info| info.positions.as_slice())
+    }
+
+    // Whether the document satisfies the query's phrases and NEAR clauses. Stop words
+    // of the document's language (or the default language, which translations and query
+    // analysis fall back to) are not indexed, so match any word in a phrase.
+    fn document_matches(query: &Query, postings: &QueryPostings, id: DocId, snapshot: &IndexSnapshot) -> bool {
+        let language = snapshot.store.language(id);
+        let analyser = &snapshot.analyser;
+        query.matches(|term| positions_in(postings, term, id), |term| analyser.is_stop_word(term, language.as_deref()) || analyser.is_stop_word(term, None))
+    }
+
+    // Documents satisfying the query's phrases and NEAR clauses, None where it has none.
+    fn matching_documents(query: &Query, index: &SegmentedIndex, snapshot: &IndexSnapshot) -> Option<HashSet<DocId>> {
+        if !query.is_positional() {
+            return None;
+        }
+
+        let postings = query_postings(&query.terms, index);
+        Some(snapshot.store.ids().filter(|id| document_matches(query, &postings, *id, snapshot)).collect())
+    }
+
+    // Proximity boost of the document, over each pair of consecutive query terms it holds.
+    fn proximity_score(terms: &[String], postings: &QueryPostings, id: DocId) -> f64 {
+        let present: Vec<(&String, &[u32])> = terms.iter()
+            .filter_map(|term| Some((term, positions_in(postings, term, id)?)))
+            .collect();
+
+        present.windows(2)
+            .filter(|pair| pair[0].0 != pair[1].0)
+            .filter_map(|pair| min_distance(pair[0].1, pair[1].1))
+            .filter(|distance| *distance <= PROXIMITY_WINDOW)
+            .map(|distance| PROXIMITY_WEIGHT / distance.max(1) as f64)
+            .sum()
+    }
+
+    struct BM25<'a> {
+        config: &'a ScoringConfig,
+        lengths: &'a FieldLengths,
+        // Inverted index.
+        index: &'a SegmentedIndex,
+        // B-Tree index prefixes and ranges are expanded from, where ranking over it.
+        btree: Option<&'a SegmentedIndex>,
+        doc_count: usize,
+    }
+
+    impl<'a> BM25<'a> {
+        fn new(config: &'a ScoringConfig, lengths: &'a FieldLengths, index: &'a SegmentedIndex, btree: Option<&'a SegmentedIndex>) -> Self {
+            BM25 { config, lengths, index, btree, doc_count: lengths.lengths.len() }
         }
         
       
         // Implement idf ranking used within bm25 score.
         fn idf(&self, term: String) -> f64 {
-            // Empty created for longer lifetime.
-            let empty: Vec<InvertedInfo> = vec![];
-            let docs_containing_term = self.inverted.get(&term).unwrap_or(&empty);
-            let num_docs_containing_term = docs_containing_term.len();
+            let num_docs_containing_term = self.index.doc_freq(&term);
 
             let idf = if num_docs_containing_term > 0 {
-                ((self.doc_count - num_docs_containing_term) as f64 / (num_docs_containing_term as f64) + 0.5).log(10.0)
+                (self.doc_count.saturating_sub(num_docs_containing_term) as f64 / (num_docs_containing_term as f64) + 0.5).log(10.0)
             } else {
                 0.0 // or some other value to handle the case when no documents contain the term
             };
@@ -403,80 +499,80 @@
             return idf
         }
         
-        // Implement bm25 score for individual document & query.
-        fn score(&self, query: &str, document: Document) -> f64 {
+        // Implement bm25f score for individual document & query - the term's frequency in
+        // each field is normalised by the field's length and weighted, and the sum saturated
+        // once, so a term found in several fields gains less than their separate scores.
+        fn score(&self, terms: &[String], postings: &QueryPostings, id: DocId) -> f64 {
+            let Some(lengths) = self.lengths.lengths.get(&id) else { return 0.0 };
             let mut bm25_score = 0.0;
-            // Assuming query form is delimited by 
-            for term in query.split_whitespace() {
-                let term = term.trim();
-                let idf = self.idf(term.to_string());
-                let mut tf = 0.0;
-                if let Some(containers) = self.inverted.get(term) {
-                    if let Some(container) = containers.iter().find(|container| container.document == document) {
-                        tf = container.term_freq as f64;
-                    }
-                }
+            for term in terms {
+                let Some(container) = postings.get(term).and_then(|containers| containers.get(&id)) else { continue };
+
+                let tf: f64 = FIELDS.iter()
+                    .map(|field| {
+                        let (index, FieldWeight { weight, b }) = (*field as usize, self.config.field(*field));
+                        let average = self.lengths.averages[index];
+                        let norm = if average > 0.0 { 1.0 - b + b * (lengths[index] as f64 / average) } else { 1.0 };
+                        weight * container.field_freqs[index] as f64 / norm
+                    })
+                    .sum();
 
-                // Taking document length as term count.
-                let doc_length = self.doc_terms.get(&document).unwrap().len() as f64;
-                let rhs = idf * (tf * (self.k1 + 1.0)) / (tf + self.k1 * (1.0 - self.b + self.b * (doc_length / self.avg_doc_len)));
-                bm25_score += rhs;
+                let k1 = self.config.k1;
+                bm25_score += self.idf(term.to_string()) * (tf * (k1 + 1.0)) / (tf + k1);
             }
             bm25_score
         }
         
         // Apply score() and sort entries by the score.
-        pub fn rank_documents(&self, query: String) -> Result<Vec<Document>, String> {
-            // Obtain all documents (within the document term index).
-            let mut documents: Vec<Document> = self.doc_terms.keys().cloned().collect();
+        pub fn rank_documents(&self, query: String, language: Option<&str>, snapshot: &IndexSnapshot) -> Result<Vec<Document>, String> {
+            // Query terms go through the same analysis as indexed terms. Prefixes and ranges
+            // add the terms they expand to, scored alike but left out of proximity.
+            let query = Query::parse(&query, &snapshot.analyser, language);
+            let mut terms = query.terms.clone();
+            if let Some(btree) = self.btree {
+                terms.extend(query.expansions(btree, self.index));
+            }
+            let postings = query_postings(&terms, self.index);
+
+            // Obtain all documents (with indexed terms), less those missing
+            // a phrase or NEAR clause of the query.
+            let mut scored: Vec<(DocId, f64)> = self.lengths.lengths.keys()
+                .filter(|id| !query.is_positional() || document_matches(&query, &postings, **id, snapshot))
+                .map(|id| (*id, self.score(&terms, &postings, *id) + proximity_score(&query.terms, &postings, *id)))
+                .collect();
 
             // Sort documents by bm25 scoring.
-            documents.sort_by(|a, b| {
-                let score_a = self.score(&query, a.clone());
-                let score_b = self.score(&query, b.clone());
-                score_b.partial_cmp(&score_a).unwrap_or(std::cmp::Ordering::Equal)
-            });
+            scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
 
-            Ok(documents)
+            Ok(scored.into_iter().filter_map(|(id, _)| snapshot.store.get(id)).collect())
         }
     }
 
 
-    pub fn get_bm25_rankings (query: String) -> Result<Vec<Document>, String> {
-        let document_terms;
-        let inverted;
-
-        // Need to read out both indices.
-        match read_index_file("./indices/dterm.json") {
-            Ok(Indexer::TermIndex(map)) => document_terms = map,
-            Ok(Indexer::InvertedIndex(_)) => return Err(String::from("2")),
-            Err(_) => return Err(String::from("2"))
-        }
-
-        match read_index_file("./indices/inverted.json") {
-            Ok(Indexer::InvertedIndex(map)) => inverted = map,
-            Ok(Indexer::TermIndex(_)) => return Err(String::from("2")),
-            Err(_) => return Err(String::from("2"))
-        }
-
-        let bm25 = BM25::new(1.5, 0.75, document_terms, inverted);
-
-        match BM25::rank_documents(&bm25, query) {
-            Ok(documents) => Ok(documents),
-            Err(e) => return Err(e)
-        }
+    // Ranks with the postings of the inverted index of the snapshot, expanding prefixes
+    // and ranges from the B-Tree index where given.
+    pub fn get_bm25_rankings (query: String, language: Option<&str>, btree: Option<&SegmentedIndex>, snapshot: &IndexSnapshot, scoring: &ScoringConfig) -> Result<Vec<Document>, String> {
+        let bm25 = BM25::new(scoring, &snapshot.lengths, &snapshot.inverted, btree);
+        bm25.rank_documents(query, language, snapshot)
     }
    
-    pub fn get_ranked_documents (query: String, index: Indexer, script: &str) -> Result<Vec<Document>, String> {
-        if script.is_empty() {
+    // Language is that of the query, analysed in the default language of the snapshot's
+    // analyser where None.
+    pub fn get_ranked_documents (query: String, language: Option<&str>, snapshot: &IndexSnapshot, index_type: u8, script: &str, scoring: &ScoringConfig) -> Result<Vec<Document>, String> {
+        if index_type == 2 {
+            println!("Using bm25 ranking over the B-Tree index");
+            get_bm25_rankings(query, language, Some(&snapshot.btree), snapshot, scoring)
+        }
+
+        else if script.is_empty() {
             println!("Using bm25 ranking");
-            let bm = get_bm25_rankings(query);
+            let bm = get_bm25_rankings(query, language, None, snapshot, scoring);
             println!("bm25 result: {:?}", bm);
-            return bm
+            bm
         }  
 
         else {
-            return get_clustered_rankings(query, index, script);
+            get_clustered_rankings(query, language, snapshot, script)
         }
     }
     
@@ -0,0 +1,305 @@
+// Module handles fetching, parsing and caching robots.txt files so the crawler
+// only requests pages that each host allows for our user agent.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use reqwest::{Client, StatusCode};
+use tokio::sync::OnceCell;
+use url::Url;
+
+
+// Single Allow / Disallow line from the group matching our user agent.
+#[derive(Debug, Clone)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+// Agents named by a group and the (key, value) lines that follow them.
+type Group = (Vec<String>, Vec<(String, String)>);
+
+// Rules which apply to our user agent on a single host, alongside the
+// Crawl-delay and any Sitemap entries listed in the file.
+#[derive(Debug, Clone, Default)]
+pub struct RobotsRules {
+    rules: Vec<Rule>,
+    pub crawl_delay: Option<Duration>,
+    pub sitemaps: Vec<String>,
+}
+
+impl RobotsRules {
+    // Used where robots.txt does not exist (4xx), everything is allowed.
+    pub fn allow_all() -> Self {
+        RobotsRules::default()
+    }
+
+    // Used where the host could not serve robots.txt (5xx or network error),
+    // we treat the whole host as off limits until the next crawl.
+    pub fn disallow_all() -> Self {
+        RobotsRules {
+            rules: vec![Rule { allow: false, pattern: String::from("/") }],
+            crawl_delay: None,
+            sitemaps: Vec::new(),
+        }
+    }
+
+    // Parses a robots.txt body, keeping only the group(s) with the most specific
+    // user agent token matching ours - falling back on the '*' group otherwise.
+    pub fn parse(body: &str, user_agent: &str) -> Self {
+        let agent = product_token(user_agent);
+
+        let mut groups: Vec<Group> = Vec::new();
+        let mut sitemaps: Vec<String> = Vec::new();
+        let mut reading_agents = false;
+
+        for line in body.lines() {
+            // Strip comments and surrounding whitespace.
+            let line = line.split('#').next().unwrap_or("").trim();
+            let Some((key, value)) = line.split_once(':') else { continue };
+            let key = key.trim().to_lowercase();
+            let value = value.trim().to_string();
+
+            match key.as_str() {
+                "user-agent" => {
+                    // Consecutive user-agent lines share the same group.
+                    if !reading_agents {
+                        groups.push((Vec::new(), Vec::new()));
+                    }
+                    if let Some(group) = groups.last_mut() {
+                        group.0.push(value.to_lowercase());
+                    }
+                    reading_agents = true;
+                }
+                "sitemap" => {
+                    // Sitemap lines are not tied to any group.
+                    if !value.is_empty() {
+                        sitemaps.push(value);
+                    }
+                }
+                _ => {
+                    reading_agents = false;
+                    if let Some(group) = groups.last_mut() {
+                        group.1.push((key, value));
+                    }
+                }
+            }
+        }
+
+        // Find the longest agent token contained in our own product token.
+        let mut best_len: Option<usize> = None;
+        for (agents, _) in &groups {
+            for name in agents {
+                if name != "*" && agent.contains(name.as_str()) {
+                    best_len = Some(best_len.map_or(name.len(), |len| len.max(name.len())));
+                }
+            }
+        }
+
+        let matches_group = |agents: &Vec<String>| match best_len {
+            Some(len) => agents.iter().any(|name| name != "*" && name.len() == len && agent.contains(name.as_str())),
+            None => agents.iter().any(|name| name == "*"),
+        };
+
+        let mut rules: Vec<Rule> = Vec::new();
+        let mut crawl_delay: Option<Duration> = None;
+
+        for (agents, lines) in &groups {
+            if !matches_group(agents) {
+                continue;
+            }
+            for (key, value) in lines {
+                match key.as_str() {
+                    // An empty Disallow means everything is allowed, so no rule is needed.
+                    "disallow" if !value.is_empty() => rules.push(Rule { allow: false, pattern: value.clone() }),
+                    "allow" if !value.is_empty() => rules.push(Rule { allow: true, pattern: value.clone() }),
+                    "crawl-delay" => {
+                        if let Ok(seconds) = value.parse::<f64>() {
+                            if seconds.is_finite() && seconds >= 0.0 {
+                                crawl_delay = Some(Duration::from_secs_f64(seconds));
+                            }
+                        }
+                    }
+                    _ => ()
+                }
+            }
+        }
+
+        RobotsRules { rules, crawl_delay, sitemaps }
+    }
+
+    // The most specific (longest) matching rule decides, with Allow winning ties.
+    // No matching rule means the path is allowed.
+    pub fn is_allowed(&self, path: &str) -> bool {
+        let mut best: Option<&Rule> = None;
+        for rule in &self.rules {
+            if !pattern_matches(&rule.pattern, path) {
+                continue;
+            }
+            best = match best {
+                Some(current) if current.pattern.len() > rule.pattern.len() => Some(current),
+                Some(current) if current.pattern.len() == rule.pattern.len() && current.allow => Some(current),
+                _ => Some(rule),
+            };
+        }
+        best.is_none_or(|rule| rule.allow)
+    }
+}
+
+// Agent name without version, i.e. "EducateBot/0.1 (+https://...)" -> "educatebot".
+fn product_token(user_agent: &str) -> String {
+    user_agent
+        .split(|c: char| c == '/' || c.is_whitespace())
+        .next()
+        .unwrap_or("")
+        .to_lowercase()
+}
+
+// Matches robots.txt path patterns supporting '*' wildcards and a trailing '$' anchor.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(stripped) => (stripped, true),
+        None => (pattern, false),
+    };
+
+    let pieces: Vec<&str> = pattern.split('*').collect();
+    let mut position = 0;
+
+    for (i, piece) in pieces.iter().enumerate() {
+        if i == 0 {
+            // First piece must be a prefix of the path.
+            if !path.starts_with(piece) {
+                return false;
+            }
+            position = piece.len();
+        }
+        else if i == pieces.len() - 1 && anchored {
+            // Last piece of an anchored pattern must end the path.
+            return path.len() >= position + piece.len() && path.ends_with(piece);
+        }
+        else {
+            match path[position..].find(piece) {
+                Some(found) => position += found + piece.len(),
+                None => return false,
+            }
+        }
+    }
+
+    !anchored || position == path.len()
+}
+
+
+// Caches parsed robots.txt per origin for the lifetime of a crawl. Shared between
+// concurrent fetches - each origin has its own cell so only the first request to a
+// host waits on robots.txt, and requests to other hosts are not held up.
+pub struct RobotsCache {
+    user_agent: String,
+    hosts: Mutex<HashMap<String, Arc<OnceCell<RobotsRules>>>>,
+}
+
+impl RobotsCache {
+    pub fn new(user_agent: &str) -> Self {
+        RobotsCache { user_agent: user_agent.to_string(), hosts: Mutex::new(HashMap::new()) }
+    }
+
+    // Returns the rules for the URL's origin, fetching robots.txt on first use.
+    pub async fn rules_for(&self, client: &Client, url: &Url) -> RobotsRules {
+        let origin = url.origin().ascii_serialization();
+
+        let cell = {
+            let mut hosts = self.hosts.lock().unwrap();
+            hosts.entry(origin.clone()).or_default().clone()
+        };
+
+        cell.get_or_init(|| fetch_rules(client, &origin, &self.user_agent)).await.clone()
+    }
+}
+
+async fn fetch_rules(client: &Client, origin: &str, user_agent: &str) -> RobotsRules {
+    let robots_url = format!("{}/robots.txt", origin);
+
+    match client.get(&robots_url).send().await {
+        Ok(response) => {
+            let status = response.status();
+            if status.is_success() {
+                match response.text().await {
+                    Ok(body) => RobotsRules::parse(&body, user_agent),
+                    Err(_) => RobotsRules::disallow_all()
+                }
+            }
+            else if status.is_client_error() && status != StatusCode::TOO_MANY_REQUESTS {
+                RobotsRules::allow_all()
+            }
+            else {
+                RobotsRules::disallow_all()
+            }
+        }
+        Err(e) => {
+            eprintln!("Could not fetch {}: {}", robots_url, e);
+            RobotsRules::disallow_all()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROBOTS: &str = "
+User-agent: *
+Disallow: /private
+
+# Our own group replaces the '*' one.
+User-agent: OtherBot
+User-agent: EducateBot
+Disallow: /drafts/
+Allow: /drafts/public
+Disallow: /*.pdf$
+Allow: /page
+Disallow: /page
+Crawl-delay: 2.5
+
+Sitemap: https://example.org/sitemap.xml
+";
+
+    #[test]
+    fn applies_the_group_of_our_agent() {
+        let rules = RobotsRules::parse(ROBOTS, "EducateBot/0.1 (+https://example.org/bot)");
+        assert!(rules.is_allowed("/private"));
+        assert!(!rules.is_allowed("/drafts/notes.html"));
+        assert_eq!(rules.crawl_delay, Some(Duration::from_millis(2500)));
+        assert_eq!(rules.sitemaps, vec!["https://example.org/sitemap.xml"]);
+
+        let rules = RobotsRules::parse(ROBOTS, "SomeBot/2.0");
+        assert!(!rules.is_allowed("/private/index.html"));
+        assert!(rules.is_allowed("/drafts/notes.html"));
+        assert_eq!(rules.crawl_delay, None);
+    }
+
+    #[test]
+    fn longest_match_decides_and_allow_wins_ties() {
+        let rules = RobotsRules::parse(ROBOTS, "EducateBot");
+        assert!(rules.is_allowed("/drafts/public/notes.html"));
+        assert!(rules.is_allowed("/page"));
+        assert!(rules.is_allowed("/"));
+    }
+
+    #[test]
+    fn matches_wildcards_and_end_anchors() {
+        let rules = RobotsRules::parse(ROBOTS, "EducateBot");
+        assert!(!rules.is_allowed("/papers/thesis.pdf"));
+        assert!(rules.is_allowed("/papers/thesis.pdf?download=1"));
+        assert!(rules.is_allowed("/papers/thesis.pdfx"));
+
+        assert!(pattern_matches("/*/lectures/*.html", "/cs101/lectures/week1.html"));
+        assert!(!pattern_matches("/*/lectures/", "/cs101/notes/"));
+        assert!(pattern_matches("/", "/anything"));
+    }
+
+    #[test]
+    fn empty_disallow_allows_everything() {
+        let rules = RobotsRules::parse("User-agent: *\nDisallow:\n", "EducateBot");
+        assert!(rules.is_allowed("/private"));
+        assert!(!RobotsRules::disallow_all().is_allowed("/"));
+    }
+}
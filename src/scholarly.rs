@@ -0,0 +1,423 @@
+// Module reads the structured metadata academic pages carry - Highwire citation_* tags,
+// schema.org JSON-LD, Dublin Core and OpenGraph - into the fields stored on a Document.
+// Where sources disagree the more specific wins: citation_* over JSON-LD over Dublin
+// Core over OpenGraph and plain author/keywords tags. The page's own description tag
+// is read here too, ahead of the abstracts of all of them.
+
+use chrono::DateTime;
+use scraper::{Html, Selector};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use crate::sitemap::parse_datetime;
+
+
+// schema.org types preferred when a page's JSON-LD describes several things.
+const SCHOLARLY_TYPES: [&str; 12] = ["ScholarlyArticle", "Course", "LearningResource", "Thesis", "Report", "Book", "Chapter",
+    "Dataset", "PresentationDigitalDocument", "Article", "TechArticle", "CreativeWork"];
+
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct ScholarlyMetadata {
+    pub authors: Vec<String>,
+    // Bare DOI, i.e. "10.1145/3132747.3132763".
+    pub doi: Option<String>,
+    // ISO 8601 date (YYYY-MM-DD), or the year alone where that is all that is given.
+    pub publication_date: Option<String>,
+    pub publisher: Option<String>,
+    // University or institution a thesis, report or course comes from.
+    pub institution: Option<String>,
+    pub keywords: Vec<String>,
+    // i.e. "ScholarlyArticle", "Course", "Thesis", "article".
+    pub resource_type: Option<String>,
+    pub license: Option<String>,
+}
+
+// Metadata along with the title and abstract the sources give, used where the page
+// has no <title> or description of its own.
+#[derive(Debug, Default)]
+pub struct PageMetadata {
+    pub metadata: ScholarlyMetadata,
+    pub title: Option<String>,
+    pub description: Option<String>,
+}
+
+impl PageMetadata {
+    // Fills anything still missing from a lower precedence source.
+    fn merge(&mut self, other: PageMetadata) {
+        let metadata = &mut self.metadata;
+        if metadata.authors.is_empty() {
+            metadata.authors = other.metadata.authors;
+        }
+        if metadata.keywords.is_empty() {
+            metadata.keywords = other.metadata.keywords;
+        }
+        metadata.doi = metadata.doi.take().or(other.metadata.doi);
+        metadata.publication_date = metadata.publication_date.take().or(other.metadata.publication_date);
+        metadata.publisher = metadata.publisher.take().or(other.metadata.publisher);
+        metadata.institution = metadata.institution.take().or(other.metadata.institution);
+        metadata.resource_type = metadata.resource_type.take().or(other.metadata.resource_type);
+        metadata.license = metadata.license.take().or(other.metadata.license);
+        self.title = self.title.take().or(other.title);
+        self.description = self.description.take().or(other.description);
+    }
+}
+
+fn clean(text: &str) -> Option<String> {
+    let text = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+    if text.is_empty() { None } else { Some(text) }
+}
+
+fn push_unique(values: &mut Vec<String>, value: &str) {
+    if let Some(value) = clean(value) {
+        if !values.iter().any(|existing| existing.eq_ignore_ascii_case(&value)) {
+            values.push(value);
+        }
+    }
+}
+
+// Splits a keyword list on ';' or ',' (whichever the page used).
+fn push_keywords(keywords: &mut Vec<String>, list: &str) {
+    let separator = if list.contains(';') { ';' } else { ',' };
+    for keyword in list.split(separator) {
+        push_unique(keywords, keyword);
+    }
+}
+
+// Bare DOI from "doi:10.x/y", "https://doi.org/10.x/y" and the like.
+pub fn normalise_doi(text: &str) -> Option<String> {
+    let text = text.trim();
+    let start = text.find("10.")?;
+    let prefix = text[..start].to_lowercase();
+
+    let is_doi = prefix.is_empty()
+        || prefix.ends_with("doi:")
+        || prefix.ends_with("doi: ")
+        || prefix.ends_with("doi.org/");
+    let doi = text[start..].split_whitespace().next()?;
+
+    if is_doi && doi.contains('/') { Some(doi.to_string()) } else { None }
+}
+
+// ISO date from the formats metadata uses - "2021/05/03" (Highwire), W3C dates and
+// datetimes, RFC 2822 - keeping a lone year as is.
+pub fn normalise_date(text: &str) -> Option<String> {
+    let text = text.trim().replace('/', "-");
+
+    if text.len() == 4 && text.chars().all(|c| c.is_ascii_digit()) {
+        return Some(text);
+    }
+    // Year and month only, i.e. "2021-05".
+    if text.len() == 7 && text.as_bytes()[4] == b'-' {
+        return Some(text);
+    }
+
+    let timestamp = parse_datetime(&text)?;
+    DateTime::from_timestamp(timestamp, 0).map(|datetime| datetime.format("%Y-%m-%d").to_string())
+}
+
+// Meta tags as (lower case name or property, content) pairs in document order.
+fn meta_tags(document: &Html) -> Vec<(String, String)> {
+    let meta_selector = Selector::parse("meta[content]").unwrap();
+
+    document.select(&meta_selector)
+        .filter_map(|element| {
+            let value = element.value();
+            let name = value.attr("name").or_else(|| value.attr("property"))?;
+            Some((name.trim().to_lowercase(), value.attr("content")?.to_string()))
+        })
+        .collect()
+}
+
+// The page's own description tag, which comes before the abstract of any other source.
+fn from_description_tag(tags: &[(String, String)]) -> PageMetadata {
+    let description = tags.iter().rev().find(|(name, _)| name == "description").and_then(|(_, content)| clean(content));
+    PageMetadata { description, ..Default::default() }
+}
+
+// Highwire Press tags, as read by Google Scholar.
+fn from_citation_tags(tags: &[(String, String)]) -> PageMetadata {
+    let mut page = PageMetadata::default();
+    let metadata = &mut page.metadata;
+
+    for (name, content) in tags {
+        let Some(field) = name.strip_prefix("citation_") else { continue };
+        match field {
+            "title" => page.title = page.title.take().or_else(|| clean(content)),
+            "abstract" => page.description = page.description.take().or_else(|| clean(content)),
+            "author" => push_unique(&mut metadata.authors, content),
+            "authors" => content.split(';').for_each(|author| push_unique(&mut metadata.authors, author)),
+            "doi" => metadata.doi = metadata.doi.take().or_else(|| normalise_doi(content)),
+            "publication_date" | "date" | "online_date" | "cover_date" => {
+                metadata.publication_date = metadata.publication_date.take().or_else(|| normalise_date(content))
+            }
+            "publisher" => metadata.publisher = metadata.publisher.take().or_else(|| clean(content)),
+            "dissertation_institution" | "technical_report_institution" | "author_institution" => {
+                metadata.institution = metadata.institution.take().or_else(|| clean(content))
+            }
+            "keywords" | "keyword" => push_keywords(&mut metadata.keywords, content),
+            "license" => metadata.license = metadata.license.take().or_else(|| clean(content)),
+            _ => ()
+        }
+
+        // The kind of publication follows from which of the container tags is present.
+        let resource_type = match field {
+            "journal_title" => Some("ScholarlyArticle"),
+            "conference_title" => Some("ConferencePaper"),
+            "dissertation_institution" => Some("Thesis"),
+            "technical_report_institution" | "technical_report_number" => Some("Report"),
+            "book_title" | "isbn" => Some("Book"),
+            _ => None,
+        };
+        if let Some(resource_type) = resource_type {
+            metadata.resource_type.get_or_insert_with(|| resource_type.to_string());
+        }
+    }
+
+    page
+}
+
+// Dublin Core, written as DC.creator, dc.creator or DCTERMS.creator.
+fn from_dublin_core(tags: &[(String, String)]) -> PageMetadata {
+    let mut page = PageMetadata::default();
+    let metadata = &mut page.metadata;
+
+    for (name, content) in tags {
+        let Some(field) = name.strip_prefix("dc.").or_else(|| name.strip_prefix("dcterms.")) else { continue };
+        match field {
+            "title" => page.title = page.title.take().or_else(|| clean(content)),
+            "description" | "abstract" => page.description = page.description.take().or_else(|| clean(content)),
+            "creator" | "contributor" => push_unique(&mut metadata.authors, content),
+            "identifier" => metadata.doi = metadata.doi.take().or_else(|| normalise_doi(content)),
+            "date" | "issued" | "created" | "date.issued" => {
+                metadata.publication_date = metadata.publication_date.take().or_else(|| normalise_date(content))
+            }
+            "publisher" => metadata.publisher = metadata.publisher.take().or_else(|| clean(content)),
+            "subject" => push_keywords(&mut metadata.keywords, content),
+            "type" => metadata.resource_type = metadata.resource_type.take().or_else(|| clean(content)),
+            "rights" | "license" => metadata.license = metadata.license.take().or_else(|| clean(content)),
+            _ => ()
+        }
+    }
+
+    page
+}
+
+// OpenGraph (og:*, article:*) and the plain author and keywords tags.
+fn from_open_graph(tags: &[(String, String)]) -> PageMetadata {
+    let mut page = PageMetadata::default();
+    let metadata = &mut page.metadata;
+
+    for (name, content) in tags {
+        match name.as_str() {
+            "og:title" => page.title = page.title.take().or_else(|| clean(content)),
+            "og:description" => page.description = page.description.take().or_else(|| clean(content)),
+            "og:type" => metadata.resource_type = metadata.resource_type.take().or_else(|| clean(content)),
+            "og:site_name" => metadata.publisher = metadata.publisher.take().or_else(|| clean(content)),
+            // article:author is often a profile URL rather than a name.
+            "article:author" | "author" if !content.starts_with("http") => push_unique(&mut metadata.authors, content),
+            "article:published_time" => {
+                metadata.publication_date = metadata.publication_date.take().or_else(|| normalise_date(content))
+            }
+            "article:tag" => push_unique(&mut metadata.keywords, content),
+            "keywords" => push_keywords(&mut metadata.keywords, content),
+            _ => ()
+        }
+    }
+
+    page
+}
+
+// Text of a JSON-LD value that may be a string, an object with a name (or url), or
+// a list of either.
+fn json_names(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(text) => clean(text).into_iter().collect(),
+        Value::Array(values) => values.iter().flat_map(json_names).collect(),
+        Value::Object(object) => ["name", "url", "@id"].iter()
+            .find_map(|key| object.get(*key).and_then(Value::as_str).and_then(clean))
+            .into_iter()
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn json_name(value: Option<&Value>) -> Option<String> {
+    value.map(json_names).and_then(|names| names.into_iter().next())
+}
+
+fn json_types(node: &Value) -> Vec<String> {
+    node.get("@type").map(json_names).unwrap_or_default()
+}
+
+// Every node of a JSON-LD block, looking inside lists and @graph.
+fn json_nodes(value: &Value, nodes: &mut Vec<Value>) {
+    match value {
+        Value::Array(values) => values.iter().for_each(|value| json_nodes(value, nodes)),
+        Value::Object(object) => {
+            if let Some(graph) = object.get("@graph") {
+                json_nodes(graph, nodes);
+            }
+            if object.contains_key("@type") {
+                nodes.push(value.clone());
+            }
+        }
+        _ => ()
+    }
+}
+
+// schema.org JSON-LD, taking the first scholarly node (or the first node at all).
+fn from_json_ld(document: &Html) -> PageMetadata {
+    let script_selector = Selector::parse(r#"script[type="application/ld+json"]"#).unwrap();
+    let mut nodes: Vec<Value> = Vec::new();
+
+    for element in document.select(&script_selector) {
+        match serde_json::from_str::<Value>(&element.text().collect::<String>()) {
+            Ok(value) => json_nodes(&value, &mut nodes),
+            Err(e) => println!("Skipping malformed JSON-LD: {}", e)
+        }
+    }
+
+    let node = SCHOLARLY_TYPES.iter()
+        .find_map(|kind| nodes.iter().find(|node| json_types(node).iter().any(|node_type| node_type == kind)))
+        .or_else(|| nodes.first());
+
+    let Some(node) = node else { return PageMetadata::default() };
+
+    let mut page = PageMetadata::default();
+    let metadata = &mut page.metadata;
+
+    page.title = json_name(node.get("headline")).or_else(|| json_name(node.get("name")));
+    page.description = json_name(node.get("abstract")).or_else(|| json_name(node.get("description")));
+
+    for key in ["author", "creator"] {
+        if let Some(value) = node.get(key) {
+            json_names(value).iter().for_each(|author| push_unique(&mut metadata.authors, author));
+        }
+    }
+
+    // DOIs turn up as "doi", an identifier (string or PropertyValue) or a doi.org sameAs.
+    metadata.doi = ["doi", "identifier", "sameAs", "@id", "url"].iter()
+        .filter_map(|key| node.get(*key))
+        .flat_map(|value| match value {
+            Value::Object(object) => object.get("value").map(json_names).unwrap_or_default(),
+            _ => json_names(value),
+        })
+        .find_map(|text| normalise_doi(&text));
+
+    metadata.publication_date = ["datePublished", "dateCreated", "startDate"].iter()
+        .find_map(|key| json_name(node.get(*key)))
+        .and_then(|date| normalise_date(&date));
+
+    metadata.publisher = json_name(node.get("publisher"));
+    metadata.institution = ["provider", "sourceOrganization", "educationalCredentialAwarded"].iter()
+        .find_map(|key| json_name(node.get(*key)));
+
+    if let Some(keywords) = node.get("keywords") {
+        match keywords {
+            Value::String(list) => push_keywords(&mut metadata.keywords, list),
+            _ => json_names(keywords).iter().for_each(|keyword| push_unique(&mut metadata.keywords, keyword)),
+        }
+    }
+
+    metadata.resource_type = json_types(node).into_iter().next();
+    metadata.license = json_name(node.get("license"));
+
+    page
+}
+
+pub fn extract_metadata(document: &Html) -> PageMetadata {
+    let tags = meta_tags(document);
+
+    let mut page = from_description_tag(&tags);
+    page.merge(from_citation_tags(&tags));
+    page.merge(from_json_ld(document));
+    page.merge(from_dublin_core(&tags));
+    page.merge(from_open_graph(&tags));
+
+    // A <link rel="license"> stands in where no source names a license.
+    if page.metadata.license.is_none() {
+        let license_selector = Selector::parse(r#"link[rel="license"][href], a[rel="license"][href]"#).unwrap();
+        page.metadata.license = document.select(&license_selector)
+            .next()
+            .and_then(|element| element.value().attr("href"))
+            .and_then(clean);
+    }
+
+    page
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JSON_LD: &str = r#"<script type="application/ld+json">{"@context": "https://schema.org", "@type": "ScholarlyArticle",
+        "headline": "JSON-LD title", "abstract": "JSON-LD abstract.", "author": {"@type": "Person", "name": "Mary Somerville"},
+        "datePublished": "2021-05-03", "publisher": {"@type": "Organization", "name": "JSON-LD Press"}}</script>"#;
+    const DUBLIN_CORE: &str = r#"<meta name="DC.title" content="Dublin Core title">
+        <meta name="DC.description" content="Dublin Core abstract.">
+        <meta name="DC.creator" content="Charles Babbage">
+        <meta name="DC.date" content="2020-01-15">
+        <meta name="DC.publisher" content="Dublin Core Press">
+        <meta name="DC.subject" content="analysis; partial differential equations">"#;
+    const OPEN_GRAPH: &str = r#"<meta property="og:title" content="OpenGraph title">
+        <meta property="og:description" content="OpenGraph description.">
+        <meta property="og:type" content="article">
+        <meta property="og:site_name" content="Example University">
+        <meta name="keywords" content="heat, fourier">"#;
+
+    fn metadata(head: &str) -> PageMetadata {
+        extract_metadata(&Html::parse_document(&format!("<html><head>{}</head><body></body></html>", head)))
+    }
+
+    #[test]
+    fn citation_tags_come_first() {
+        let page = metadata(&[
+            r#"<meta name="citation_title" content="The Heat Equation">
+            <meta name="citation_author" content="Ada Lovelace">
+            <meta name="citation_abstract" content="Citation abstract.">
+            <meta name="citation_journal_title" content="Journal of Heat">"#,
+            JSON_LD, DUBLIN_CORE, OPEN_GRAPH,
+        ].concat());
+
+        assert_eq!(page.title.as_deref(), Some("The Heat Equation"));
+        assert_eq!(page.description.as_deref(), Some("Citation abstract."));
+        assert_eq!(page.metadata.authors, vec!["Ada Lovelace"]);
+        assert_eq!(page.metadata.resource_type.as_deref(), Some("ScholarlyArticle"));
+        // Fields the citation tags leave out come from the next source that has them.
+        assert_eq!(page.metadata.publication_date.as_deref(), Some("2021-05-03"));
+        assert_eq!(page.metadata.publisher.as_deref(), Some("JSON-LD Press"));
+        assert_eq!(page.metadata.keywords, vec!["analysis", "partial differential equations"]);
+    }
+
+    #[test]
+    fn json_ld_comes_before_dublin_core_and_open_graph() {
+        let page = metadata(&[JSON_LD, DUBLIN_CORE, OPEN_GRAPH].concat());
+        assert_eq!(page.title.as_deref(), Some("JSON-LD title"));
+        assert_eq!(page.description.as_deref(), Some("JSON-LD abstract."));
+        assert_eq!(page.metadata.authors, vec!["Mary Somerville"]);
+
+        let page = metadata(&[DUBLIN_CORE, OPEN_GRAPH].concat());
+        assert_eq!(page.title.as_deref(), Some("Dublin Core title"));
+        assert_eq!(page.description.as_deref(), Some("Dublin Core abstract."));
+        assert_eq!(page.metadata.publication_date.as_deref(), Some("2020-01-15"));
+        assert_eq!(page.metadata.publisher.as_deref(), Some("Dublin Core Press"));
+        assert_eq!(page.metadata.resource_type.as_deref(), Some("article"));
+
+        let page = metadata(OPEN_GRAPH);
+        assert_eq!(page.title.as_deref(), Some("OpenGraph title"));
+        assert_eq!(page.description.as_deref(), Some("OpenGraph description."));
+        assert_eq!(page.metadata.publisher.as_deref(), Some("Example University"));
+        assert_eq!(page.metadata.keywords, vec!["heat", "fourier"]);
+    }
+
+    #[test]
+    fn description_tag_comes_before_every_abstract() {
+        let page = metadata(&[
+            r#"<meta name="description" content="Lecture notes on the heat equation.">
+            <meta name="citation_abstract" content="Citation abstract.">"#,
+            JSON_LD, DUBLIN_CORE, OPEN_GRAPH,
+        ].concat());
+
+        assert_eq!(page.description.as_deref(), Some("Lecture notes on the heat equation."));
+        assert_eq!(page.title.as_deref(), Some("JSON-LD title"));
+    }
+}
@@ -0,0 +1,585 @@
+// Module defines the on-disk format of the indices and document store. Each file is a
+// segment - a magic number and format version, a header (what the file holds, the
+// analyser settings its terms were produced with, where its sections lie and their
+// checksums), then the sections themselves. Postings are delta and varint compressed, and the term
+// dictionary is front coded and kept apart from them, so that opening an index reads
+// only the dictionary into memory. The rest of the file is memory-mapped and decoded
+// as queries need it.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::Write;
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::Arc;
+use itertools::Itertools;
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use crate::analysis::AnalysisConfig;
+use crate::index::{FieldLengths, Indexer, InvertedInfo, FIELD_COUNT};
+use crate::store::DocId;
+
+
+const MAGIC: [u8; 8] = *b"EDSRCHSG";
+// Version of the segment layout. Files of other versions are not read - the indices
+// are filled again to rebuild them.
+pub const FORMAT_VERSION: u32 = 1;
+// Magic number, version, header length and header checksum.
+const PREAMBLE_LEN: usize = MAGIC.len() + 4 + 4 + 4;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentKind {
+    TermIndex,
+    InvertedIndex,
+    BTreeIndex,
+    DocumentStore,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SegmentHeader {
+    pub kind: SegmentKind,
+    pub analysis: AnalysisConfig,
+    // Start and length of each section, in bytes from the start of the file.
+    sections: Vec<(u64, u64)>,
+    // CRC-32 of each section.
+    checksums: Vec<u32>,
+}
+
+// Writes the parts to a temporary file, flushes it to disk and renames it over the
+// path, then flushes the directory - a crash leaves the old file or the new one, never
+// part of either. Files mapped by running queries are never changed under them.
+pub fn write_file(path: &str, parts: &[&[u8]]) -> std::io::Result<()> {
+    let temp_path = format!("{}.tmp", path);
+    let mut file = File::create(&temp_path)?;
+    for part in parts {
+        file.write_all(part)?;
+    }
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&temp_path, path)?;
+    if let Some(dir) = Path::new(path).parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+// Writes the sections to a segment at the path, through write_file, recording the
+// analyser settings of the indices it belongs to.
+pub fn write_segment(path: &str, kind: SegmentKind, analysis: &AnalysisConfig, sections: Vec<Vec<u8>>) -> std::io::Result<()> {
+    let checksums = sections.iter().map(|section| crc32fast::hash(section)).collect();
+    let mut header = SegmentHeader { kind, analysis: analysis.clone(), sections: Vec::new(), checksums };
+    // Section offsets depend on the header's length, which does not depend on their values.
+    let header_len = bincode::serialized_size(&SegmentHeader { sections: vec![(0, 0); sections.len()], ..header.clone() })
+        .map_err(std::io::Error::other)? as usize;
+
+    let mut start = (PREAMBLE_LEN + header_len) as u64;
+    for section in &sections {
+        header.sections.push((start, section.len() as u64));
+        start += section.len() as u64;
+    }
+    let header = bincode::serialize(&header).map_err(std::io::Error::other)?;
+
+    let mut preamble = MAGIC.to_vec();
+    preamble.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    preamble.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    preamble.extend_from_slice(&crc32fast::hash(&header).to_le_bytes());
+    let mut parts: Vec<&[u8]> = vec![&preamble, &header];
+    parts.extend(sections.iter().map(Vec::as_slice));
+    write_file(path, &parts)
+}
+
+// A segment file mapped into memory.
+pub struct SegmentFile {
+    pub header: SegmentHeader,
+    map: Mmap,
+}
+
+impl SegmentFile {
+    // Maps the segment at the path and verifies its checksums. None where the file is not
+    // a segment (i.e. an index written before segments), and an error where it was
+    // written by another version or is corrupt.
+    pub fn open(path: &str) -> Result<Option<Self>, String> {
+        let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+        // Files are replaced by renaming, never written in place, so the mapping stays
+        // valid for as long as it is held.
+        let map = unsafe { Mmap::map(&file) }.map_err(|e| format!("{}: {}", path, e))?;
+
+        if map.len() < 16 || map[..MAGIC.len()] != MAGIC {
+            return Ok(None);
+        }
+        let word = |at: usize| map.get(at..at + 4).map_or(0, |bytes| u32::from_le_bytes(bytes.try_into().unwrap_or_default()));
+        let corrupt = || format!("{} has a corrupt header", path);
+
+        let version = word(8);
+        if version != FORMAT_VERSION {
+            return Err(format!("{} was written by index format version {}, this build reads version {} - fill the indices again to rebuild it",
+                path, version, FORMAT_VERSION));
+        }
+        let bytes = map.get(PREAMBLE_LEN..PREAMBLE_LEN + word(12) as usize).ok_or_else(corrupt)?;
+        if crc32fast::hash(bytes) != word(16) {
+            return Err(corrupt());
+        }
+        let header: SegmentHeader = bincode::deserialize(bytes).map_err(|_| corrupt())?;
+        if header.sections.iter().any(|(start, len)| start + len > map.len() as u64) {
+            return Err(format!("{} is truncated", path));
+        }
+
+        let file = SegmentFile { header, map };
+        for (index, checksum) in file.header.checksums.iter().enumerate() {
+            if crc32fast::hash(file.section(index)) != *checksum {
+                return Err(format!("{} is corrupt - section {} does not match its checksum", path, index));
+            }
+        }
+        Ok(Some(file))
+    }
+
+    pub fn section(&self, index: usize) -> &[u8] {
+        match self.header.sections.get(index) {
+            Some((start, len)) => &self.map[*start as usize..(start + len) as usize],
+            None => &[],
+        }
+    }
+}
+
+pub fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+// Reads varints and byte strings from a section, None once it runs out.
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes, position: 0 }
+    }
+
+    pub fn varint(&mut self) -> Option<u64> {
+        let mut value: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.bytes.get(self.position)?;
+            self.position += 1;
+            value |= ((byte & 0x7F) as u64) << shift;
+            if byte < 0x80 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes.get(self.position..self.position.checked_add(len)?)?;
+        self.position += len;
+        Some(bytes)
+    }
+}
+
+// Postings in document order - the gap to each document ID, its frequency in each
+// field, and the gaps between its positions (as many as its frequencies add up to).
+fn encode_postings(postings: &[InvertedInfo], out: &mut Vec<u8>) {
+    let mut sorted: Vec<&InvertedInfo> = postings.iter().collect();
+    sorted.sort_by_key(|info| info.doc_id);
+
+    let mut previous_id: DocId = 0;
+    for info in sorted {
+        write_varint(out, (info.doc_id - previous_id) as u64);
+        previous_id = info.doc_id;
+        for freq in info.field_freqs {
+            write_varint(out, freq as u64);
+        }
+        let mut previous_position = 0;
+        for position in &info.positions {
+            write_varint(out, (position - previous_position) as u64);
+            previous_position = *position;
+        }
+    }
+}
+
+fn decode_postings(bytes: &[u8], count: usize) -> Option<Vec<InvertedInfo>> {
+    let mut reader = Reader::new(bytes);
+    let mut postings = Vec::with_capacity(count);
+    let mut doc_id: DocId = 0;
+    for _ in 0..count {
+        doc_id += reader.varint()? as DocId;
+        let mut field_freqs = [0; FIELD_COUNT];
+        for freq in field_freqs.iter_mut() {
+            *freq = reader.varint()? as u32;
+        }
+        let term_freq = field_freqs.iter().sum::<u32>() as usize;
+        let mut positions = Vec::with_capacity(term_freq);
+        let mut position = 0;
+        for _ in 0..term_freq {
+            position += reader.varint()? as u32;
+            positions.push(position);
+        }
+        postings.push(InvertedInfo { doc_id, term_freq, field_freqs, positions });
+    }
+    Some(postings)
+}
+
+// Entry of the term dictionary - the number of documents holding the term, and where
+// its postings lie in the postings section.
+struct TermEntry {
+    term: String,
+    doc_freq: usize,
+    start: usize,
+    len: usize,
+}
+
+// Sections of an index segment:
+// inverted - (0) term dictionary, front coded, (1) postings, (2) field lengths;
+// B-Tree - (0) term dictionary, front coded, without postings;
+// term index - (0) document table, (1) terms of each document.
+pub fn encode_index(index: &Indexer) -> (SegmentKind, Vec<Vec<u8>>) {
+    match index {
+        Indexer::TermIndex(map) => {
+            let mut table = Vec::new();
+            let mut terms = Vec::new();
+            let documents: BTreeMap<&DocId, &Vec<String>> = map.iter().collect();
+            write_varint(&mut table, documents.len() as u64);
+            let mut previous_id: DocId = 0;
+            for (id, document_terms) in documents {
+                let start = terms.len();
+                write_varint(&mut terms, document_terms.len() as u64);
+                for term in document_terms {
+                    write_varint(&mut terms, term.len() as u64);
+                    terms.extend_from_slice(term.as_bytes());
+                }
+                write_varint(&mut table, (id - previous_id) as u64);
+                write_varint(&mut table, (terms.len() - start) as u64);
+                previous_id = *id;
+            }
+            (SegmentKind::TermIndex, vec![table, terms])
+        }
+        Indexer::InvertedIndex(map) => {
+            let sorted: BTreeMap<&String, &Vec<InvertedInfo>> = map.iter().collect();
+            (SegmentKind::InvertedIndex, encode_terms(sorted, index))
+        }
+        Indexer::BTreeIndex(terms) => {
+            let mut dictionary = Vec::new();
+            write_varint(&mut dictionary, terms.len() as u64);
+            let mut previous: &[u8] = &[];
+            for term in terms {
+                write_term(&mut dictionary, previous, term.as_bytes());
+                previous = term.as_bytes();
+            }
+            (SegmentKind::BTreeIndex, vec![dictionary])
+        }
+    }
+}
+
+// Writes the term as the bytes it shares with the one before, and the rest.
+fn write_term(dictionary: &mut Vec<u8>, previous: &[u8], term: &[u8]) {
+    let shared = previous.iter().zip(term).take_while(|(a, b)| a == b).count();
+    write_varint(dictionary, shared as u64);
+    write_varint(dictionary, (term.len() - shared) as u64);
+    dictionary.extend_from_slice(&term[shared..]);
+}
+
+fn encode_terms(terms: BTreeMap<&String, &Vec<InvertedInfo>>, index: &Indexer) -> Vec<Vec<u8>> {
+    let mut dictionary = Vec::new();
+    let mut postings = Vec::new();
+    write_varint(&mut dictionary, terms.len() as u64);
+    let mut previous: &[u8] = &[];
+    for (term, term_postings) in terms {
+        let start = postings.len();
+        encode_postings(term_postings, &mut postings);
+
+        let term = term.as_bytes();
+        write_term(&mut dictionary, previous, term);
+        write_varint(&mut dictionary, term_postings.len() as u64);
+        write_varint(&mut dictionary, (postings.len() - start) as u64);
+        previous = term;
+    }
+
+    let field_lengths = FieldLengths::new(index);
+    let mut lengths = Vec::new();
+    let documents: BTreeMap<&DocId, &[u32; FIELD_COUNT]> = field_lengths.lengths.iter().collect();
+    write_varint(&mut lengths, documents.len() as u64);
+    let mut previous_id: DocId = 0;
+    for (id, document_lengths) in documents {
+        write_varint(&mut lengths, (id - previous_id) as u64);
+        previous_id = *id;
+        for length in document_lengths {
+            write_varint(&mut lengths, *length as u64);
+        }
+    }
+
+    vec![dictionary, postings, lengths]
+}
+
+// An index opened for search. Its term dictionary (or document table) is read into
+// memory, and postings are decoded from the mapped file as they are looked up.
+pub struct Segment {
+    file: SegmentFile,
+    terms: Vec<TermEntry>,
+    // ID of each document of a term index, and where its terms lie.
+    documents: Vec<(DocId, usize, usize)>,
+}
+
+impl Segment {
+    pub fn new(file: SegmentFile, path: &str) -> Result<Self, String> {
+        let corrupt = || format!("{} has a corrupt {}", path, if file.header.kind == SegmentKind::TermIndex { "document table" } else { "term dictionary" });
+        let mut reader = Reader::new(file.section(0));
+        let count = reader.varint().ok_or_else(corrupt)? as usize;
+
+        let mut terms = Vec::new();
+        let mut documents = Vec::new();
+        let mut start = 0;
+        match file.header.kind {
+            SegmentKind::TermIndex => {
+                let mut id: DocId = 0;
+                for _ in 0..count {
+                    id += reader.varint().ok_or_else(corrupt)? as DocId;
+                    let len = reader.varint().ok_or_else(corrupt)? as usize;
+                    documents.push((id, start, len));
+                    start += len;
+                }
+            }
+            SegmentKind::InvertedIndex | SegmentKind::BTreeIndex => {
+                let mut term: Vec<u8> = Vec::new();
+                for _ in 0..count {
+                    let shared = reader.varint().ok_or_else(corrupt)? as usize;
+                    let suffix_len = reader.varint().ok_or_else(corrupt)? as usize;
+                    term.truncate(shared);
+                    term.extend_from_slice(reader.bytes(suffix_len).ok_or_else(corrupt)?);
+                    // The B-Tree keeps its terms alone, without postings.
+                    let (doc_freq, len) = match file.header.kind {
+                        SegmentKind::BTreeIndex => (0, 0),
+                        _ => (reader.varint().ok_or_else(corrupt)? as usize, reader.varint().ok_or_else(corrupt)? as usize),
+                    };
+                    let text = String::from_utf8(term.clone()).map_err(|_| corrupt())?;
+                    terms.push(TermEntry { term: text, doc_freq, start, len });
+                    start += len;
+                }
+            }
+            SegmentKind::DocumentStore => return Err(format!("{} holds a document store, not an index", path)),
+        }
+
+        Ok(Segment { file, terms, documents })
+    }
+
+    pub fn kind(&self) -> SegmentKind {
+        self.file.header.kind
+    }
+
+    fn entry(&self, term: &str) -> Option<&TermEntry> {
+        let index = self.terms.binary_search_by(|entry| entry.term.as_str().cmp(term)).ok()?;
+        self.terms.get(index)
+    }
+
+    fn decode(&self, entry: &TermEntry) -> Option<Vec<InvertedInfo>> {
+        decode_postings(self.file.section(1).get(entry.start..entry.start + entry.len)?, entry.doc_freq)
+    }
+
+    // Postings of a term, for inverted indices.
+    pub fn postings(&self, term: &str) -> Option<Vec<InvertedInfo>> {
+        self.decode(self.entry(term)?)
+    }
+
+    // Number of documents holding the term, read from the dictionary alone.
+    pub fn doc_freq(&self, term: &str) -> usize {
+        self.entry(term).map_or(0, |entry| entry.doc_freq)
+    }
+
+    // Terms within the bounds in order, for the B-Tree index - other index types are
+    // searched as if they kept no order, so have no vocabulary to walk.
+    pub fn vocabulary<'a>(&'a self, bounds: (Bound<&'a str>, Bound<&'a str>)) -> Box<dyn Iterator<Item = &'a str> + 'a> {
+        if self.kind() != SegmentKind::BTreeIndex {
+            return Box::new(std::iter::empty());
+        }
+
+        let start = match bounds.0 {
+            Bound::Included(from) => self.terms.partition_point(|entry| entry.term.as_str() < from),
+            Bound::Excluded(from) => self.terms.partition_point(|entry| entry.term.as_str() <= from),
+            Bound::Unbounded => 0,
+        };
+        Box::new(self.terms[start..].iter()
+            .map(|entry| entry.term.as_str())
+            .take_while(move |term| match bounds.1 {
+                Bound::Included(to) => *term <= to,
+                Bound::Excluded(to) => *term < to,
+                Bound::Unbounded => true,
+            }))
+    }
+
+    // Field lengths of each document, empty for term indices.
+    fn document_lengths(&self) -> HashMap<DocId, [u32; FIELD_COUNT]> {
+        let mut lengths: HashMap<DocId, [u32; FIELD_COUNT]> = HashMap::new();
+        let mut reader = Reader::new(self.file.section(2));
+        let count = reader.varint().unwrap_or(0);
+        let mut id: DocId = 0;
+        for _ in 0..count {
+            let Some(gap) = reader.varint() else { break };
+            id += gap as DocId;
+            let mut document_lengths = [0; FIELD_COUNT];
+            for length in document_lengths.iter_mut() {
+                *length = reader.varint().unwrap_or(0) as u32;
+            }
+            lengths.insert(id, document_lengths);
+        }
+        lengths
+    }
+
+    // Terms of each document, for term indices.
+    pub fn documents(&self) -> impl Iterator<Item = (DocId, Vec<String>)> + '_ {
+        self.documents.iter().filter_map(|(id, start, len)| {
+            let mut reader = Reader::new(self.file.section(1).get(*start..start + len)?);
+            let count = reader.varint()?;
+            let terms = (0..count)
+                .map(|_| {
+                    let len = reader.varint()? as usize;
+                    String::from_utf8(reader.bytes(len)?.to_vec()).ok()
+                })
+                .collect::<Option<Vec<String>>>()?;
+            Some((*id, terms))
+        })
+    }
+
+    // Decodes the whole index back into memory, i.e. to merge it with others.
+    pub fn to_indexer(&self) -> Indexer {
+        match self.kind() {
+            SegmentKind::TermIndex => Indexer::TermIndex(self.documents().collect()),
+            SegmentKind::BTreeIndex => Indexer::BTreeIndex(self.terms.iter().map(|entry| entry.term.clone()).collect()),
+            _ => Indexer::InvertedIndex(self.terms.iter()
+                .filter_map(|entry| Some((entry.term.clone(), self.decode(entry)?)))
+                .collect()),
+        }
+    }
+}
+
+// One index type over every segment of a commit (see writer.rs), less the documents
+// deleted from each. Results of the segments are combined as if they were one index.
+pub struct SegmentedIndex {
+    kind: SegmentKind,
+    segments: Vec<(Segment, Arc<HashSet<DocId>>)>,
+}
+
+impl SegmentedIndex {
+    pub fn new(kind: SegmentKind) -> Self {
+        SegmentedIndex { kind, segments: Vec::new() }
+    }
+
+    pub fn push(&mut self, segment: Segment, deleted: Arc<HashSet<DocId>>) -> Result<(), String> {
+        if segment.kind() != self.kind {
+            return Err(format!("Found a {:?} segment among those of the {:?}", segment.kind(), self.kind));
+        }
+        self.segments.push((segment, deleted));
+        Ok(())
+    }
+
+    pub fn kind(&self) -> SegmentKind {
+        self.kind
+    }
+
+    // Postings of a term in every segment, less those of deleted documents.
+    pub fn postings(&self, term: &str) -> Option<Vec<InvertedInfo>> {
+        let postings: Vec<InvertedInfo> = self.segments.iter()
+            .filter_map(|(segment, deleted)| Some(segment.postings(term)?.into_iter().filter(|info| !deleted.contains(&info.doc_id))))
+            .flatten()
+            .collect();
+        (!postings.is_empty()).then_some(postings)
+    }
+
+    // Number of live documents holding the term - read from the dictionary of segments
+    // without deletions, and otherwise from the postings less the deleted documents.
+    pub fn doc_freq(&self, term: &str) -> usize {
+        self.segments.iter()
+            .map(|(segment, deleted)| match deleted.is_empty() {
+                true => segment.doc_freq(term),
+                false => segment.postings(term).map_or(0, |postings| postings.iter().filter(|info| !deleted.contains(&info.doc_id)).count()),
+            })
+            .sum()
+    }
+
+    // Terms within the bounds in order, over the vocabularies of every segment.
+    pub fn vocabulary<'a>(&'a self, bounds: (Bound<&'a str>, Bound<&'a str>)) -> impl Iterator<Item = &'a str> + 'a {
+        self.segments.iter().map(move |(segment, _)| segment.vocabulary(bounds)).kmerge().dedup()
+    }
+
+    pub fn field_lengths(&self) -> FieldLengths {
+        let mut lengths = HashMap::new();
+        for (segment, deleted) in &self.segments {
+            lengths.extend(segment.document_lengths().into_iter().filter(|(id, _)| !deleted.contains(id)));
+        }
+        FieldLengths::from_lengths(lengths)
+    }
+
+    pub fn documents(&self) -> impl Iterator<Item = (DocId, Vec<String>)> + '_ {
+        self.segments.iter()
+            .flat_map(|(segment, deleted)| segment.documents().filter(move |(id, _)| !deleted.contains(id)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::index::Field;
+    use super::*;
+
+    #[test]
+    fn varints_round_trip() {
+        let values = [0, 1, 127, 128, 300, u32::MAX as u64, u64::MAX];
+        let mut bytes = Vec::new();
+        for value in values {
+            write_varint(&mut bytes, value);
+        }
+        assert_eq!(bytes[..3], [0, 1, 127]);
+
+        let mut reader = Reader::new(&bytes);
+        for value in values {
+            assert_eq!(reader.varint(), Some(value));
+        }
+        assert_eq!(reader.varint(), None);
+        // A varint cut short reads as None rather than a value.
+        assert_eq!(Reader::new(&[0x80, 0x80]).varint(), None);
+    }
+
+    // Writes the index out as a segment and opens it again.
+    fn round_trip(index: &Indexer, name: &str) -> Segment {
+        let path = std::env::temp_dir().join(format!("segment-test-{}-{}.seg", name, std::process::id())).to_string_lossy().into_owned();
+        let (kind, sections) = encode_index(index);
+        write_segment(&path, kind, &AnalysisConfig::default(), sections).unwrap();
+        let segment = SegmentFile::open(&path).unwrap().map(|file| Segment::new(file, &path).unwrap()).unwrap();
+        let _ = fs::remove_file(&path);
+        segment
+    }
+
+    #[test]
+    fn term_dictionary_round_trips_through_front_coding() {
+        let postings = |doc_id: DocId, positions: &[u32]| InvertedInfo::new(doc_id, positions.iter().map(|position| (Field::Body, *position)).collect());
+        let index = Indexer::InvertedIndex(HashMap::from([
+            (String::from("algebra"), vec![postings(3, &[0, 7])]),
+            (String::from("algorithm"), vec![postings(9, &[2]), postings(1, &[4, 5, 40])]),
+            (String::from("algorithms"), vec![postings(1, &[6])]),
+            (String::from("zebra"), vec![postings(9, &[1])]),
+        ]));
+        let segment = round_trip(&index, "inverted");
+
+        assert_eq!(segment.doc_freq("algorithm"), 2);
+        assert_eq!(segment.doc_freq("algo"), 0);
+        let decoded = segment.postings("algorithm").unwrap();
+        assert_eq!(decoded.iter().map(|info| (info.doc_id, info.positions.clone())).collect::<Vec<_>>(), vec![(1, vec![4, 5, 40]), (9, vec![2])]);
+        assert_eq!(decoded[0].term_freq, 3);
+        // Only the B-Tree index is walked in order.
+        assert_eq!(segment.vocabulary((Bound::Unbounded, Bound::Unbounded)).count(), 0);
+    }
+
+    #[test]
+    fn btree_walks_its_terms_in_order() {
+        let index = Indexer::BTreeIndex(["zebra", "algorithms", "algebra", "algorithm"].map(String::from).into());
+        let segment = round_trip(&index, "btree");
+
+        let vocabulary: Vec<&str> = segment.vocabulary((Bound::Unbounded, Bound::Unbounded)).collect();
+        assert_eq!(vocabulary, vec!["algebra", "algorithm", "algorithms", "zebra"]);
+        let prefixed: Vec<&str> = segment.vocabulary((Bound::Included("algo"), Bound::Excluded("algp"))).collect();
+        assert_eq!(prefixed, vec!["algorithm", "algorithms"]);
+        let ranged: Vec<&str> = segment.vocabulary((Bound::Excluded("algebra"), Bound::Included("algorithms"))).collect();
+        assert_eq!(ranged, vec!["algorithm", "algorithms"]);
+    }
+}
@@ -1,97 +1,346 @@
 // Module includes API-accessible commands - such as filling indices on application startup - and
 // obtaining query results.
     
-    use std::collections::HashMap;
-    use crate::rank::get_ranked_documents;
-    use crate::index::{Indexer, read_index_file};
+    use std::collections::{HashMap, HashSet};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use crate::rank::{get_ranked_documents, ScoringConfig};
     use crate::discover::get_domains_and_webpages;
     use crate::parser::{parse_crawl_results, Document};
-    use crate::crawl::{get_crawled, CrawlResult};
+    use crate::crawl::{get_crawled, read_feeds, revisit, CrawlConfig, CrawlOutput, CrawlResult, Revisit, RevisitTarget};
+    use crate::checkpoint::{checkpoint_dir, read_state};
+    use crate::collection::{Collection, Collections};
+    use crate::links::{normalise, registered_domain};
+    use crate::translate::{translate_documents, TranslationConfig};
+    use crate::anchors::{attach_anchor_text, LinkGraph};
+    use crate::dedup::collapse_duplicates;
+    use crate::store::{DocId, DocumentStore};
+    use crate::shared::SharedIndices;
+    use crate::writer::{rebuild_indices, IndexWriter};
+    use url::Url;
     use serde::{Serialize, Deserialize};
     use crate::meta::SearchResponse;
     use thiserror::Error;
     
     // To Do:
     // Later this will encapsulate all services errors.
-    // Currently single error implemented for case where incorrect
-    // index name contains index type.
     #[derive(Error, Debug)]
+    #[allow(clippy::enum_variant_names)]
     pub enum ServiceError {
-        #[error("Incorrect index type in file")]
-        IndexTypeError(std::io::Error),
         #[error("Could not read domains JSON")]
-        ReadDomainsError(std::io::Error)
+        ReadDomainsError(std::io::Error),
+        #[error("Indices must be filled before they can be recrawled")]
+        MissingIndexError(String),
+        #[error("Could not write updated index")]
+        IndexWriteError(String),
+        #[error("Could not load updated indices")]
+        IndexLoadError(String)
     }
     
-    pub async fn fill_indices (crawl_depth: u8, seed_count: u8) -> Result<(), ServiceError> {
-        let new_forward_index: bool;
-        let new_inverted_index: bool;
+    // Fills the collection's indices, crawling from its seeds, to its depth and with its
+    // crawl and translation settings where it has them, and otherwise from seed_count
+    // university pages to crawl_depth with those of the request.
+    pub async fn fill_indices (collection: &Collection, crawl_depth: u8, seed_count: u8, crawl_config: &CrawlConfig, translation: &TranslationConfig) -> Result<(), ServiceError> {
+        let indices = &collection.indices;
+        // The store is read through the last commit, so this covers the indices too.
+        let mut new_index = DocumentStore::read(&indices.dir).is_err();
+        let crawl_depth = collection.config.crawl_depth.unwrap_or(crawl_depth);
+        let crawl_config = collection.config.crawl.as_ref().unwrap_or(crawl_config);
+        let translation = collection.config.translation.as_ref().unwrap_or(translation);
+        let crawl_config = CrawlConfig { checkpoint_dir: checkpoint_dir(&indices.dir), ..crawl_config.clone() };
 
-        match read_index_file("./indices/dterm.json") {
-            Ok(Indexer::TermIndex(_)) => {
-                new_forward_index = false
-            }
-            Ok(Indexer::InvertedIndex(_)) => {
-                return Err(ServiceError::IndexTypeError(std::io::Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    "Incorrect index type in file",
-                )));
-            }
-            Err(_) => {
-                new_forward_index = true
-            }
-        }
-
-        match read_index_file("./indices/inverted.json") {
-            Ok(Indexer::TermIndex(_)) => {
-                return Err(ServiceError::IndexTypeError(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Incorrect index type in file",
-            )));
-            }
-            Ok(Indexer::InvertedIndex(_)) => {
-                new_inverted_index = false;
-            }
-            Err(_) => {
-                new_inverted_index = true
-            }
+        let seed_urls: Vec<String>;
+        let known_domains: Vec<String>;
 
+        if !collection.config.seeds.is_empty() {
+            // The seeds' own domains stand in for the university domains.
+            seed_urls = collection.config.seeds.clone();
+            known_domains = seed_urls.iter()
+                .filter_map(|seed| registered_domain(&Url::parse(seed).ok()?))
+                .collect();
         }
-        
-        if new_inverted_index || new_forward_index {
-            let seed_urls: Vec<String>;
-
+        else {
             match get_domains_and_webpages() {
-                Ok((urls, _)) => {
+                Ok((urls, domains)) => {
                     seed_urls = urls[0..seed_count as usize].to_vec();
+                    known_domains = domains;
                 }
                 Err(e) => {
                    return Err(ServiceError::ReadDomainsError(e)) 
                 }
             }
-            
-            // Modify to handle error case explicitly.
-            let results: Vec<CrawlResult> = get_crawled(seed_urls, crawl_depth.into()).await;
-            let parsed_results = parse_crawl_results(results);
+        }
+
+        // A checkpointed crawl asked to go deeper or cover more seeds is extended
+        // (without refetching its pages) and the indices rebuilt from the result.
+        if let Some(state) = read_state(&crawl_config.checkpoint_dir) {
+            let deeper = state.max_depth < crawl_depth as u32;
+            let more_seeds = seed_urls.iter().any(|seed| !state.seeds.contains(seed));
+            if deeper || more_seeds {
+                println!("Extending previous crawl");
+                new_index = true;
+            }
+        }
         
-            // Creates raw indices - stores in file (if file isn't already filled) and stores indices raw for later use.
-            if new_forward_index {
-                let _ = Indexer::TermIndex(HashMap::new()).new(parsed_results.clone()); 
+        if new_index {
+            // Modify to handle error case explicitly.
+            let output: CrawlOutput = get_crawled(seed_urls, crawl_depth.into(), &crawl_config, &known_domains).await;
+            println!("Crawled {} pages, skipped {} URLs", output.results.len(), output.skipped.len());
+            for skipped in &output.skipped {
+                println!("Skipped {}: {:?}", skipped.url, skipped.reason);
             }
+            let mut link_graph = LinkGraph::default();
+            for result in &output.results {
+                link_graph.set_links(&result.url, result.edges.clone());
+            }
+            if let Err(e) = link_graph.write(&indices.dir) {
+                eprintln!("Could not write link graph: {}", e);
+            }
+
+            // Anchor text is attached before near-duplicates are collapsed, as it helps pick
+            // the canonical document, and again after so that it covers the alternates.
+            let inbound = link_graph.inbound_anchors();
+            let mut parsed_results = parse_crawl_results(output.results);
+            attach_anchor_text(&mut parsed_results, &inbound);
+            let mut parsed_results = collapse_duplicates(parsed_results);
+            attach_anchor_text(&mut parsed_results, &inbound);
+            translate_documents(&mut parsed_results, translation).await;
 
-            if new_inverted_index {
-                let _ = Indexer::InvertedIndex(HashMap::new()).new(parsed_results.clone());
+            let mut store = DocumentStore::default();
+            for document in parsed_results {
+                store.insert(document);
             }
-            
-            Ok(())
+
+            // The new indices replace any committed ones as a whole, and searches switch
+            // over to them without a restart.
+            rebuild_indices(&store, indices).map_err(ServiceError::IndexWriteError)
         } 
 
         else {
-            println!("Indices already exist!");
+            println!("Indices of collection {} already exist!", collection.name);
+            if indices.snapshot().is_none() {
+                indices.reload().map_err(ServiceError::IndexLoadError)?;
+            }
             Ok(()) 
         }
     }
 
+    // Counts of what a recrawl changed, returned from /search/recrawl.
+    #[derive(Serialize, Deserialize, Debug, Default)]
+    pub struct RecrawlReport {
+        pub unchanged: usize,
+        pub updated: usize,
+        pub added: usize,
+        pub removed: usize,
+        pub failed: usize,
+        // Documents re-indexed only because links to them, or their near-duplicates, changed.
+        pub relinked: usize,
+        // Indexed documents folded into a near-duplicate.
+        pub collapsed: usize,
+    }
+
+    // Revisits every indexed URL with conditional requests and applies only the
+    // differences to both indices:
+    // (1) 304 or same content hash -> left as is.
+    // (2) Changed content -> document replaced, and links to pages not yet indexed are
+    // fetched (one hop) and added.
+    // (3) 404/410, disallowed by robots.txt or no longer parseable -> document removed.
+    // Failed requests leave the document untouched until the next recrawl.
+    pub async fn recrawl_indices (crawl_config: &CrawlConfig, translation: &TranslationConfig, indices: &Arc<SharedIndices>) -> Result<RecrawlReport, ServiceError> {
+        let mut store = DocumentStore::read(&indices.dir)
+            .map_err(|_| ServiceError::MissingIndexError(indices.dir.clone()))?;
+
+        let indexed: HashMap<String, Document> = store.documents()
+            .map(|(_, document)| (document.url.clone(), document))
+            .collect();
+
+        // Feeds linked from indexed pages are a cheap change signal - items not updated
+        // since their page was fetched need no request, and unseen items are new pages.
+        let feeds: Vec<String> = indexed.values()
+            .flat_map(|document| document.feeds.iter().cloned())
+            .collect::<HashSet<String>>()
+            .into_iter()
+            .collect();
+
+        let feed_updates: HashMap<String, Option<i64>> = if crawl_config.follow_feeds && !feeds.is_empty() {
+            read_feeds(feeds, crawl_config).await.into_iter()
+                .filter_map(|item| {
+                    let url = Url::parse(&item.url).ok().and_then(|url| normalise(url, &crawl_config.tracking_params))?;
+                    Some((url.to_string(), item.updated))
+                })
+                .collect()
+        }
+        else {
+            HashMap::new()
+        };
+
+        let mut report = RecrawlReport::default();
+
+        let targets: Vec<RevisitTarget> = indexed.values()
+            .filter(|document| {
+                let unchanged = feed_updates.get(&document.url)
+                    .is_some_and(|updated| updated.is_some_and(|updated| updated <= document.fetched_at));
+                if unchanged {
+                    report.unchanged += 1;
+                }
+                !unchanged
+            })
+            .map(|document| RevisitTarget { url: document.url.clone(), validators: document.validators.clone() })
+            .collect();
+
+        println!("Recrawling {} indexed documents", targets.len());
+        let mut removed: Vec<String> = Vec::new();
+        let mut modified: Vec<CrawlResult> = Vec::new();
+
+        for (url, outcome) in revisit(targets, crawl_config).await {
+            match outcome {
+                Revisit::Unchanged => report.unchanged += 1,
+                Revisit::Modified(result) => modified.push(result),
+                Revisit::Gone => removed.push(url),
+                Revisit::Failed(reason) => {
+                    println!("Could not recrawl {}: {:?}", url, reason);
+                    report.failed += 1;
+                }
+            }
+        }
+
+        // Links from modified pages and feed items to anything not yet indexed are candidates to add.
+        // Alternates of indexed documents are known duplicates, not new pages.
+        let alternates: HashSet<&str> = indexed.values()
+            .flat_map(|document| document.alternates.iter().map(String::as_str))
+            .collect();
+        let new_urls: Vec<String> = modified.iter()
+            .flat_map(|result| result.new_urls.iter())
+            .chain(feed_updates.keys())
+            .filter(|url| !indexed.contains_key(url.as_str()) && !alternates.contains(url.as_str()))
+            .cloned()
+            .collect::<HashSet<String>>()
+            .into_iter()
+            .collect();
+
+        // The link graph predates anchor text indexing where missing, and is rebuilt as pages are revisited.
+        let mut link_graph = LinkGraph::read(&indices.dir).unwrap_or_default();
+        for result in &modified {
+            link_graph.set_links(&result.url, result.edges.clone());
+        }
+
+        let modified_urls: Vec<String> = modified.iter().map(|result| result.url.clone()).collect();
+        let mut reparsed: HashMap<String, Document> = parse_crawl_results(modified).into_iter()
+            .map(|document| (document.url.clone(), document))
+            .collect();
+
+        let mut changed: Vec<Document> = Vec::new();
+        for url in modified_urls {
+            match (reparsed.remove(&url), indexed.get(&url)) {
+                (Some(document), Some(previous)) => {
+                    if document.content_hash == previous.content_hash && document.validators == previous.validators {
+                        report.unchanged += 1;
+                    }
+                    else {
+                        report.updated += 1;
+                        changed.push(document);
+                    }
+                }
+                (Some(document), None) => {
+                    report.updated += 1;
+                    changed.push(document);
+                }
+                (None, _) => removed.push(url)
+            }
+        }
+
+        if !new_urls.is_empty() {
+            // Single hop crawl without touching the fill checkpoint.
+            let discovery_config = CrawlConfig { checkpoint_every: 0, use_sitemaps: false, follow_feeds: false, ..crawl_config.clone() };
+            let output = get_crawled(new_urls, 1, &discovery_config, &[]).await;
+            for result in &output.results {
+                link_graph.set_links(&result.url, result.edges.clone());
+            }
+            let added = parse_crawl_results(output.results);
+            report.added = added.len();
+            changed.extend(added);
+        }
+
+        report.removed = removed.len();
+        for url in &removed {
+            link_graph.remove(url);
+        }
+        if let Err(e) = link_graph.write(&indices.dir) {
+            eprintln!("Could not write link graph: {}", e);
+        }
+
+        // Near-duplicates are collapsed across the whole collection, so a new mirror of an
+        // indexed page folds into it, and anchor text is attached to every document as in
+        // fill_indices. Documents this changes are re-indexed, and those folded into another
+        // removed.
+        let inbound = link_graph.inbound_anchors();
+        let refetched: HashSet<String> = changed.iter().map(|document| document.url.clone()).collect();
+        let mut documents: Vec<Document> = indexed.values()
+            .filter(|document| !refetched.contains(&document.url) && !removed.contains(&document.url))
+            .cloned()
+            .chain(changed)
+            .collect();
+        attach_anchor_text(&mut documents, &inbound);
+        let mut documents = collapse_duplicates(documents);
+        attach_anchor_text(&mut documents, &inbound);
+
+        let kept: HashSet<&str> = documents.iter().map(|document| document.url.as_str()).collect();
+        let collapsed: Vec<String> = indexed.keys()
+            .filter(|url| !kept.contains(url.as_str()) && !removed.contains(url))
+            .cloned()
+            .collect();
+        report.collapsed = collapsed.len();
+        removed.extend(collapsed);
+
+        let mut changed: Vec<Document> = documents.into_iter()
+            .filter(|document| indexed.get(&document.url) != Some(document))
+            .collect();
+        report.relinked = changed.iter().filter(|document| !refetched.contains(&document.url)).count();
+        translate_documents(&mut changed, translation).await;
+
+        if removed.is_empty() && changed.is_empty() {
+            println!("Recrawl found no changes");
+            return Ok(report);
+        }
+
+        let removed_ids: Vec<DocId> = removed.iter().filter_map(|url| store.remove(url)).collect();
+        let updated: Vec<(DocId, Document)> = changed.into_iter()
+            .map(|document| (store.insert(document.clone()), document))
+            .collect();
+
+        // Only the changes are indexed, as a new segment - removed and replaced versions
+        // of documents are marked deleted in the segments holding them.
+        let mut writer = IndexWriter::open(indices).map_err(ServiceError::MissingIndexError)?;
+        for id in removed_ids {
+            writer.delete(id);
+        }
+        for (id, document) in updated {
+            writer.add(id, document).map_err(ServiceError::IndexWriteError)?;
+        }
+        writer.commit(&store).map_err(ServiceError::IndexWriteError)?;
+
+        println!("Recrawl complete: {:?}", report);
+        Ok(report)
+    }
+
+    // Recrawls every collection on a fixed interval for the lifetime of the server, with
+    // its own crawl and translation settings where it has them.
+    pub async fn schedule_recrawls (interval: Duration, crawl_config: CrawlConfig, translation: TranslationConfig, collections: Arc<Collections>) {
+        loop {
+            tokio::time::sleep(interval).await;
+            for collection in collections.all() {
+                if collection.indices.snapshot().is_none() {
+                    continue;
+                }
+                let crawl_config = collection.config.crawl.as_ref().unwrap_or(&crawl_config);
+                let translation = collection.config.translation.as_ref().unwrap_or(&translation);
+                if let Err(e) = recrawl_indices(crawl_config, translation, &collection.indices).await {
+                    eprintln!("Scheduled recrawl of collection {} failed: {}", collection.name, e);
+                }
+            }
+        }
+    }
+
     // get_search_results can receive a selection of possible ranking procedures (supported).
     // These are 1. Word2Vec document clustering 2. Sentence Transformer (BERT) document clustering
     // 3. BM25 (TF-IDF improvement) sorted.
@@ -103,49 +352,84 @@
     // We need information about the procedure type.
     // Simple by checking if script string is not None.
     // Where None this is asking for BM25 ranked.
-    pub fn get_search_results(query: String, script: &str) -> Result<SearchResponse, String> {
+    // Searches each of the collections, ranking with the request's scoring where given
+    // and otherwise the collection's own. The query is analysed in its language where
+    // given, and otherwise in the default language of each collection.
+    pub fn get_search_results(query: String, language: Option<&str>, index_type: u8, script: &str, scoring: Option<&ScoringConfig>, collections: &[Arc<Collection>]) -> Result<SearchResponse, String> {
         if script.is_empty() {
             println!("Using BM25 ranked search");
-            match read_index_file("./indices/inverted.json") {
-                Ok(Indexer::InvertedIndex(map)) => {
-                    let num_indexed = map.len();
-                    let results = get_ranked_documents(query, Indexer::InvertedIndex(map), "")?;
-                    Ok(SearchResponse::Search(DocumentResult {results, indexed: num_indexed}))
-                },
-                Ok(Indexer::TermIndex(_)) => {
-                    Err(String::from("2"))
-                },
-                Err(_) => Err(String::from('2'))
-            }
         }
-
         else {
             println!("Using {} ranked search", script);
-            match read_index_file("./indices/dterm.json") {
-                Ok(Indexer::InvertedIndex(_)) => {
-                    println!("Index is Inverted Index");
-                    Err(String::from("2"))
-                },
-                Ok(Indexer::TermIndex(map)) => {
-                    println!("Index is Term Index");
-                    let num_indexed = map.len();
-                    let results: Vec<Document> = get_ranked_documents(query, Indexer::TermIndex(map), script)?;
-                    Ok(SearchResponse::Search(DocumentResult {results, indexed: num_indexed}))
-                },
-                Err(_) => {
-                    println!("Index not found");
-                    Err(String::from("2"))
-                }       
+        }
+
+        let mut ranked: Vec<(String, Vec<Document>)> = Vec::new();
+        let mut num_indexed = 0;
+        let mut error = String::from("2");
+        for collection in collections {
+            let Some(snapshot) = collection.indices.snapshot() else {
+                println!("Index of collection {} not found", collection.name);
+                continue;
+            };
+            let scoring = scoring.or(collection.config.scoring.as_ref()).cloned().unwrap_or_default();
+            match get_ranked_documents(query.clone(), language, &snapshot, index_type, script, &scoring) {
+                Ok(results) => {
+                    num_indexed += snapshot.store.len();
+                    ranked.push((collection.name.clone(), results));
+                }
+                Err(e) => {
+                    println!("Could not search collection {}: {}", collection.name, e);
+                    error = e;
+                }
             }
-            
         }
+        if ranked.is_empty() {
+            return Err(error);
+        }
+
+        let (collections, results): (Vec<String>, Vec<Document>) = interleave_results(ranked).into_iter().unzip();
+        let links = page_links(&results, &query);
+        Ok(SearchResponse::Search(DocumentResult {results, links, indexed: num_indexed, collections}))
+    }
+
+    // Takes the results of each collection in turn, so that the best of every collection
+    // come first - scores of collections analysed or weighted differently do not
+    // compare. A page found in several collections is listed once, under the first.
+    fn interleave_results(ranked: Vec<(String, Vec<Document>)>) -> Vec<(String, Document)> {
+        let mut remaining: Vec<(String, std::vec::IntoIter<Document>)> = ranked.into_iter()
+            .map(|(name, results)| (name, results.into_iter()))
+            .collect();
+        let mut seen: HashSet<String> = HashSet::new();
+        let mut merged: Vec<(String, Document)> = Vec::new();
+        while !remaining.is_empty() {
+            remaining.retain_mut(|(name, results)| match results.next() {
+                Some(document) => {
+                    if seen.insert(document.url.clone()) {
+                        merged.push((name.clone(), document));
+                    }
+                    true
+                }
+                None => false,
+            });
+        }
+        merged
     }
 
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct DocumentResult {
     pub results: Vec<Document>,
-    pub indexed: usize
+    // Link to open each result at, deep-linking into the matching page of PDFs.
+    pub links: Vec<String>,
+    // Documents across the collections searched.
+    pub indexed: usize,
+    // Collection each result was found in.
+    #[serde(default)]
+    pub collections: Vec<String>,
+}
+
+fn page_links(results: &[Document], query: &str) -> Vec<String> {
+    results.iter().map(|document| document.page_link(query)).collect()
 }
 
 
@@ -0,0 +1,112 @@
+// Module keeps the indices and document store open for the lifetime of the server.
+// They are mapped from disk once at startup, and replaced as a whole after each commit
+// (see writer.rs) - queries hold on to the snapshot they started with, so they never
+// block on a writer nor see half of a commit. Each collection (see collection.rs) has
+// indices of its own, in its own directory and with its own analyser.
+
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use arc_swap::ArcSwapOption;
+use crate::analysis::Analyser;
+use crate::index::FieldLengths;
+use crate::segment::SegmentedIndex;
+use crate::store::DocumentStore;
+use crate::writer::{recover_indices, upgrade_indices, Commit, SEGMENT_FILES};
+
+
+// The indices along with the documents they refer to, the field lengths scoring
+// normalises by, and the analyser queries against them are parsed with.
+pub struct IndexSnapshot {
+    pub forward: SegmentedIndex,
+    pub inverted: SegmentedIndex,
+    pub btree: SegmentedIndex,
+    pub store: DocumentStore,
+    pub lengths: FieldLengths,
+    pub analyser: Arc<Analyser>,
+}
+
+impl IndexSnapshot {
+    pub fn new(forward: SegmentedIndex, inverted: SegmentedIndex, btree: SegmentedIndex, store: DocumentStore, analyser: Arc<Analyser>) -> Self {
+        let lengths = inverted.field_lengths();
+        IndexSnapshot { forward, inverted, btree, store, lengths, analyser }
+    }
+
+    // Opens the segments and store of the commit to the indices, verifying their
+    // checksums, less the documents deleted from each segment.
+    pub fn open(commit: &Commit, indices: &SharedIndices) -> Result<Self, String> {
+        let [mut forward, mut inverted, mut btree] = SEGMENT_FILES.map(|(kind, _)| SegmentedIndex::new(kind));
+        for meta in &commit.segments {
+            let deleted = Arc::new(meta.deleted_ids());
+            let [dterm, postings, ordered] = meta.open(&indices.dir)?;
+            forward.push(dterm, deleted.clone())?;
+            inverted.push(postings, deleted.clone())?;
+            btree.push(ordered, deleted)?;
+        }
+        let store = DocumentStore::open(&commit.store_path(&indices.dir))?;
+
+        Ok(IndexSnapshot::new(forward, inverted, btree, store, indices.analyser.clone()))
+    }
+
+    pub fn read(indices: &SharedIndices) -> Result<Self, String> {
+        Self::open(&Commit::read(&indices.dir)?, indices)
+    }
+}
+
+// Indices kept in a directory. The current snapshot is None until they have been filled.
+pub struct SharedIndices {
+    current: ArcSwapOption<IndexSnapshot>,
+    pub dir: String,
+    // Analyser of the documents indexed and of queries against them.
+    pub analyser: Arc<Analyser>,
+    // Held by whoever is changing the commits - one writer at a time, and merges only
+    // take it to pick their segments and to commit.
+    write_lock: Mutex<()>,
+    // Set while a merge runs, so that only one does.
+    pub merging: AtomicBool,
+}
+
+impl SharedIndices {
+    pub fn new(dir: &str, analyser: Analyser) -> Self {
+        SharedIndices {
+            current: ArcSwapOption::empty(),
+            dir: dir.to_string(),
+            analyser: Arc::new(analyser),
+            write_lock: Mutex::new(()),
+            merging: AtomicBool::new(false),
+        }
+    }
+
+    // Loads the last generation of the indices in the directory that verifies, starting
+    // empty where there is none. Indices from before segments, or analysed with other
+    // settings, are rebuilt first.
+    pub fn load(dir: &str, analyser: Analyser) -> Arc<Self> {
+        let shared = Arc::new(SharedIndices::new(dir, analyser));
+        if let Err(e) = upgrade_indices(&shared).and_then(|_| recover_indices(&shared)) {
+            println!("Indices in {} not loaded, fill them to search: {}", dir, e);
+        }
+        shared
+    }
+
+    pub fn write_lock(&self) -> MutexGuard<'_, ()> {
+        self.write_lock.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Snapshot for a query to read - lock-free, and unaffected by later reloads.
+    pub fn snapshot(&self) -> Option<Arc<IndexSnapshot>> {
+        self.current.load_full()
+    }
+
+    // Reads the indices from disk and swaps them in. Queries running on the previous
+    // snapshot finish on it, and it is freed once the last of them does.
+    pub fn reload(&self) -> Result<(), String> {
+        let snapshot = IndexSnapshot::read(self)?;
+        println!("Loaded indices of {} documents", snapshot.store.len());
+        self.replace(snapshot);
+        Ok(())
+    }
+
+    // Swaps in indices already held in memory, i.e. those a writer has just committed.
+    pub fn replace(&self, snapshot: IndexSnapshot) {
+        self.current.store(Some(Arc::new(snapshot)));
+    }
+}
@@ -0,0 +1,211 @@
+// Module parses sitemaps (urlset, sitemap index and plain text forms, optionally
+// gzipped) so that seeds can be expanded to the pages a site lists for crawlers.
+
+use std::io::Read;
+use chrono::{DateTime, NaiveDate};
+use flate2::read::GzDecoder;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+
+// Paths tried where robots.txt does not list any sitemap.
+const WELL_KNOWN_PATHS: [&str; 2] = ["/sitemap.xml", "/sitemap_index.xml"];
+
+#[derive(Debug, Clone)]
+pub struct SitemapEntry {
+    pub url: String,
+    // Unix timestamp of <lastmod>, where given.
+    pub lastmod: Option<i64>,
+}
+
+#[derive(Debug)]
+pub enum Sitemap {
+    // <sitemapindex> - locations of further sitemaps.
+    Index(Vec<String>),
+    // <urlset> or plain text - the pages themselves.
+    UrlSet(Vec<SitemapEntry>),
+}
+
+pub fn well_known_sitemaps(origin: &str) -> Vec<String> {
+    WELL_KNOWN_PATHS.iter().map(|path| format!("{}{}", origin, path)).collect()
+}
+
+// Decodes a fetched sitemap body, gunzipping where it carries the gzip magic bytes
+// (i.e. sitemap.xml.gz served without Content-Encoding).
+pub fn decode_body(bytes: &[u8]) -> Option<String> {
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        let mut decoded = String::new();
+        GzDecoder::new(bytes).read_to_string(&mut decoded).ok()?;
+        Some(decoded)
+    }
+    else {
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+}
+
+pub fn parse_sitemap(body: &str) -> Sitemap {
+    let body = body.trim_start_matches('\u{feff}').trim();
+
+    // Plain text sitemaps list one URL per line.
+    if !body.starts_with('<') {
+        let entries = body.lines()
+            .map(str::trim)
+            .filter(|line| line.starts_with("http://") || line.starts_with("https://"))
+            .map(|line| SitemapEntry { url: line.to_string(), lastmod: None })
+            .collect();
+        return Sitemap::UrlSet(entries);
+    }
+
+    let mut reader = Reader::from_str(body);
+    reader.config_mut().trim_text(true);
+
+    let mut is_index = false;
+    let mut sitemaps: Vec<String> = Vec::new();
+    let mut entries: Vec<SitemapEntry> = Vec::new();
+
+    // Element whose text is being read, and the entry being built.
+    let mut current = String::new();
+    let mut loc: Option<String> = None;
+    let mut lastmod: Option<i64> = None;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(element)) => {
+                current = String::from_utf8_lossy(element.local_name().as_ref()).to_lowercase();
+                match current.as_str() {
+                    "sitemapindex" => is_index = true,
+                    "url" | "sitemap" => {
+                        loc = None;
+                        lastmod = None;
+                    }
+                    _ => ()
+                }
+            }
+            Ok(Event::Text(text)) => {
+                let Ok(text) = text.unescape() else { continue };
+                match current.as_str() {
+                    "loc" => loc = Some(text.trim().to_string()),
+                    "lastmod" => lastmod = parse_datetime(&text),
+                    _ => ()
+                }
+            }
+            Ok(Event::CData(text)) if current == "loc" => {
+                loc = Some(String::from_utf8_lossy(&text).trim().to_string());
+            }
+            Ok(Event::End(element)) => {
+                let name = String::from_utf8_lossy(element.local_name().as_ref()).to_lowercase();
+                match name.as_str() {
+                    "url" => {
+                        if let Some(url) = loc.take() {
+                            entries.push(SitemapEntry { url, lastmod });
+                        }
+                    }
+                    "sitemap" => {
+                        if let Some(url) = loc.take() {
+                            sitemaps.push(url);
+                        }
+                    }
+                    _ => ()
+                }
+                current.clear();
+            }
+            Ok(Event::Eof) => break,
+            // Keep whatever was parsed before the malformed part.
+            Err(e) => {
+                eprintln!("Malformed sitemap: {}", e);
+                break
+            }
+            _ => ()
+        }
+    }
+
+    if is_index {
+        Sitemap::Index(sitemaps)
+    }
+    else {
+        Sitemap::UrlSet(entries)
+    }
+}
+
+// Parses the date formats used by sitemaps (W3C datetime) and feeds (RFC 3339
+// for Atom, RFC 2822 for RSS) into a Unix timestamp.
+pub fn parse_datetime(text: &str) -> Option<i64> {
+    let text = text.trim();
+
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(text) {
+        return Some(datetime.timestamp());
+    }
+    if let Ok(datetime) = DateTime::parse_from_rfc2822(text) {
+        return Some(datetime.timestamp());
+    }
+    // Date only, or W3C datetime with minutes but no seconds.
+    if let Ok(date) = NaiveDate::parse_from_str(text.get(..10)?, "%Y-%m-%d") {
+        return date.and_hms_opt(0, 0, 0).map(|datetime| datetime.and_utc().timestamp());
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use super::*;
+
+    const URLSET: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <url><loc>https://example.org/courses/heat</loc><lastmod>2024-03-05T10:30:00+00:00</lastmod></url>
+  <url><loc>https://example.org/courses/waves</loc></url>
+</urlset>"#;
+
+    fn entries(sitemap: Sitemap) -> Vec<(String, Option<i64>)> {
+        match sitemap {
+            Sitemap::UrlSet(entries) => entries.into_iter().map(|entry| (entry.url, entry.lastmod)).collect(),
+            Sitemap::Index(sitemaps) => panic!("Expected a url set, got an index of {:?}", sitemaps),
+        }
+    }
+
+    #[test]
+    fn parses_url_sets_with_their_lastmod() {
+        assert_eq!(entries(parse_sitemap(URLSET)), vec![
+            (String::from("https://example.org/courses/heat"), Some(1709634600)),
+            (String::from("https://example.org/courses/waves"), None),
+        ]);
+        // Plain text sitemaps list one URL per line.
+        assert_eq!(entries(parse_sitemap("https://example.org/a\n\nnot a url\nhttps://example.org/b\n")).len(), 2);
+    }
+
+    #[test]
+    fn parses_sitemap_indexes_into_the_sitemaps_to_follow() {
+        let body = r#"<?xml version="1.0" encoding="UTF-8"?>
+<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <sitemap><loc>https://example.org/sitemap-courses.xml</loc><lastmod>2024-03-01</lastmod></sitemap>
+  <sitemap><loc><![CDATA[https://example.org/sitemap-news.xml.gz]]></loc></sitemap>
+</sitemapindex>"#;
+
+        match parse_sitemap(body) {
+            Sitemap::Index(sitemaps) => assert_eq!(sitemaps, vec!["https://example.org/sitemap-courses.xml", "https://example.org/sitemap-news.xml.gz"]),
+            Sitemap::UrlSet(entries) => panic!("Expected an index, got a url set of {:?}", entries),
+        }
+    }
+
+    #[test]
+    fn decodes_gzipped_sitemaps() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(URLSET.as_bytes()).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        let body = decode_body(&gzipped).unwrap();
+        assert_eq!(body, URLSET);
+        assert_eq!(entries(parse_sitemap(&body)).len(), 2);
+        assert_eq!(decode_body(URLSET.as_bytes()).as_deref(), Some(URLSET));
+    }
+
+    #[test]
+    fn parses_sitemap_and_feed_dates() {
+        assert_eq!(parse_datetime("2024-03-05T10:30:00Z"), Some(1709634600));
+        assert_eq!(parse_datetime("Tue, 05 Mar 2024 10:30:00 GMT"), Some(1709634600));
+        assert_eq!(parse_datetime("2024-03-05T10:30+00:00"), Some(1709596800));
+        assert_eq!(parse_datetime("March 2024"), None);
+    }
+}
@@ -0,0 +1,141 @@
+// Module holds every indexed document once, under a compact numeric ID the indices
+// refer to. IDs are stable - a URL keeps its ID for as long as it stays indexed, and
+// IDs of removed documents are not reused. The store is a segment (see segment.rs) of
+// a document table and the encoded documents, which are decoded from the mapped file
+// as they are read rather than held in memory. Each commit (see writer.rs) writes the
+// store out anew, so that older generations keep the store they were committed with.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use serde::{Serialize, Deserialize};
+use crate::analysis::AnalysisConfig;
+use crate::parser::Document;
+use crate::segment::{write_segment, SegmentFile, SegmentKind};
+use crate::writer::Commit;
+
+
+pub type DocId = u32;
+
+// Entry of the document table - the URL and language kept in memory for lookups, and
+// where the document lies in the documents section.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct StoredDocument {
+    url: String,
+    language: Option<String>,
+    start: usize,
+    len: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DocumentTable {
+    next_id: DocId,
+    documents: BTreeMap<DocId, StoredDocument>,
+}
+
+#[derive(Default)]
+pub struct DocumentStore {
+    // Documents of the store's file, and those inserted since it was read - each ID is
+    // in one or the other.
+    file: Option<SegmentFile>,
+    stored: BTreeMap<DocId, StoredDocument>,
+    inserted: BTreeMap<DocId, Document>,
+    ids: HashMap<String, DocId>,
+    next_id: DocId,
+}
+
+impl DocumentStore {
+    // The store of the last commit of the indices in the directory.
+    pub fn read(dir: &str) -> Result<Self, String> {
+        Self::open(&Commit::read(dir)?.store_path(dir))
+    }
+
+    pub fn open(path: &str) -> Result<Self, String> {
+        let file = SegmentFile::open(path)?.ok_or_else(|| format!("{} is not a segment", path))?;
+        if file.header.kind != SegmentKind::DocumentStore {
+            return Err(format!("{} does not hold a document store", path));
+        }
+
+        let table: DocumentTable = bincode::deserialize(file.section(0))
+            .map_err(|e| format!("{} has a corrupt document table: {}", path, e))?;
+        let ids = table.documents.iter().map(|(id, document)| (document.url.clone(), *id)).collect();
+        Ok(DocumentStore { file: Some(file), stored: table.documents, inserted: BTreeMap::new(), ids, next_id: table.next_id })
+    }
+
+    // Writes the store to a new file. Documents read from the store's own file are
+    // copied over as they are, without decoding them.
+    pub fn write(&self, path: &str, analysis: &AnalysisConfig) -> Result<(), Box<dyn std::error::Error>> {
+        let mut table = DocumentTable { next_id: self.next_id, documents: BTreeMap::new() };
+        let mut documents: Vec<u8> = Vec::new();
+        for id in self.ids() {
+            let start = documents.len();
+            let stored = match (self.inserted.get(&id), self.stored.get(&id), &self.file) {
+                (Some(document), _, _) => {
+                    bincode::serialize_into(&mut documents, document)?;
+                    StoredDocument { url: document.url.clone(), language: document.language.clone(), start, len: 0 }
+                }
+                (None, Some(stored), Some(file)) => {
+                    documents.extend_from_slice(file.section(1).get(stored.start..stored.start + stored.len).unwrap_or_default());
+                    StoredDocument { start, ..stored.clone() }
+                }
+                _ => continue,
+            };
+            table.documents.insert(id, StoredDocument { len: documents.len() - start, ..stored });
+        }
+
+        write_segment(path, SegmentKind::DocumentStore, analysis, vec![bincode::serialize(&table)?, documents])?;
+        Ok(())
+    }
+
+    // Stores the document under its URL's ID, assigning the next ID to unseen URLs.
+    pub fn insert(&mut self, document: Document) -> DocId {
+        let id = match self.ids.get(&document.url) {
+            Some(id) => *id,
+            None => {
+                let id = self.next_id;
+                self.next_id += 1;
+                self.ids.insert(document.url.clone(), id);
+                id
+            }
+        };
+        self.stored.remove(&id);
+        self.inserted.insert(id, document);
+        id
+    }
+
+    pub fn remove(&mut self, url: &str) -> Option<DocId> {
+        let id = self.ids.remove(url)?;
+        self.stored.remove(&id);
+        self.inserted.remove(&id);
+        Some(id)
+    }
+
+    pub fn get(&self, id: DocId) -> Option<Document> {
+        if let Some(document) = self.inserted.get(&id) {
+            return Some(document.clone());
+        }
+
+        let stored = self.stored.get(&id)?;
+        let bytes = self.file.as_ref()?.section(1).get(stored.start..stored.start + stored.len)?;
+        bincode::deserialize(bytes).ok()
+    }
+
+    // Language of the document, without decoding it.
+    pub fn language(&self, id: DocId) -> Option<String> {
+        match self.inserted.get(&id) {
+            Some(document) => document.language.clone(),
+            None => self.stored.get(&id)?.language.clone(),
+        }
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = DocId> + '_ {
+        self.stored.keys().chain(self.inserted.keys()).copied().collect::<BTreeSet<DocId>>().into_iter()
+    }
+
+    // Every document in ID order, each decoded as it is reached.
+    pub fn documents(&self) -> impl Iterator<Item = (DocId, Document)> + '_ {
+        self.ids().filter_map(|id| Some((id, self.get(id)?)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.stored.len() + self.inserted.len()
+    }
+}
@@ -0,0 +1,312 @@
+// Module translates the text of non-English documents through a LibreTranslate
+// compatible service, so they can be searched in the target language as well as their
+// own. Translation is optional - where the service is disabled, unreachable or slow,
+// documents are indexed in their original language only.
+
+use std::collections::HashMap;
+use std::time::Duration;
+use futures::stream::{self, StreamExt};
+use reqwest::Client;
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+use crate::parser::Document;
+
+
+// Settings of the translation service, part of SearchParams.
+// Missing fields fall back to the defaults below.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TranslationConfig {
+    pub enabled: bool,
+    // LibreTranslate /translate endpoint, and its API key where the instance needs one.
+    pub endpoint: String,
+    pub api_key: Option<String>,
+    // Language (ISO 639-1) documents are translated into.
+    pub target: String,
+    // Time allowed for each request before its documents are left untranslated.
+    pub timeout_ms: u64,
+    // Number of text segments sent in a single request, and requests running at once.
+    pub batch_size: usize,
+    pub max_concurrent: usize,
+}
+
+impl Default for TranslationConfig {
+    fn default() -> Self {
+        TranslationConfig {
+            enabled: false,
+            endpoint: String::from("http://127.0.0.1:5000/translate"),
+            api_key: None,
+            target: String::from("en"),
+            timeout_ms: 10000,
+            batch_size: 32,
+            max_concurrent: 2,
+        }
+    }
+}
+
+// Translated text of a document, indexed alongside the original.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Translation {
+    // Language the text was translated into.
+    pub language: String,
+    pub title: String,
+    pub description: String,
+    pub content: Vec<String>,
+}
+
+// A segment of document text - the title (0), description (1) or a content entry (2..).
+struct Segment {
+    document: usize,
+    position: usize,
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct TranslateResponse {
+    #[serde(rename = "translatedText")]
+    translated_text: Vec<String>,
+}
+
+// Translates documents not in the target language (nor translated already), returning
+// how many were translated. Segments are batched per source language, and a document only gets a
+// translation where every one of its segments came back.
+pub async fn translate_documents(documents: &mut [Document], config: &TranslationConfig) -> usize {
+    if !config.enabled {
+        return 0;
+    }
+
+    let client = match Client::builder().timeout(Duration::from_millis(config.timeout_ms)).build() {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Could not create translation client: {}", e);
+            return 0;
+        }
+    };
+
+    let mut by_language: HashMap<String, Vec<Segment>> = HashMap::new();
+    for (index, document) in documents.iter().enumerate() {
+        let Some(language) = document.language.as_ref().filter(|language| **language != config.target && document.translation.is_none()) else {
+            continue;
+        };
+
+        let texts = [&document.title, &document.description].into_iter().chain(document.content.iter());
+        for (position, text) in texts.enumerate() {
+            by_language.entry(language.clone()).or_default().push(Segment { document: index, position, text: text.clone() });
+        }
+    }
+
+    if by_language.is_empty() {
+        return 0;
+    }
+
+    let batches: Vec<(String, Vec<Segment>)> = by_language.into_iter()
+        .flat_map(|(language, mut segments)| {
+            let mut batches = Vec::new();
+            while !segments.is_empty() {
+                let rest = segments.split_off(segments.len().min(config.batch_size.max(1)));
+                batches.push((language.clone(), std::mem::replace(&mut segments, rest)));
+            }
+            batches
+        })
+        .collect();
+
+    let translated: Vec<(Vec<Segment>, Option<Vec<String>>)> = stream::iter(batches)
+        .map(|(language, segments)| {
+            let client = &client;
+            async move {
+                let texts: Vec<&str> = segments.iter().map(|segment| segment.text.as_str()).collect();
+                let outcome = match request_translation(client, config, &language, &texts).await {
+                    Ok(translations) if translations.len() == texts.len() => Some(translations),
+                    Ok(_) => {
+                        eprintln!("Translation service returned the wrong number of segments");
+                        None
+                    }
+                    Err(e) => {
+                        eprintln!("Could not translate from {}: {}", language, e);
+                        None
+                    }
+                };
+                (segments, outcome)
+            }
+        })
+        .buffer_unordered(config.max_concurrent.max(1))
+        .collect()
+        .await;
+
+    // Translated segments of each document, None where any batch holding them failed.
+    let mut results: HashMap<usize, Option<Vec<(usize, String)>>> = HashMap::new();
+    for (segments, outcome) in translated {
+        match outcome {
+            Some(translations) => {
+                for (segment, translation) in segments.into_iter().zip(translations) {
+                    if let Some(parts) = results.entry(segment.document).or_insert_with(|| Some(Vec::new())) {
+                        parts.push((segment.position, translation));
+                    }
+                }
+            }
+            None => {
+                for segment in segments {
+                    results.insert(segment.document, None);
+                }
+            }
+        }
+    }
+
+    let mut count = 0;
+    for (index, parts) in results {
+        let document = &mut documents[index];
+        let Some(mut parts) = parts else {
+            eprintln!("Keeping {} untranslated", document.url);
+            continue;
+        };
+
+        parts.sort_by_key(|(position, _)| *position);
+        let mut texts = parts.into_iter().map(|(_, text)| text);
+        document.translation = Some(Translation {
+            language: config.target.clone(),
+            title: texts.next().unwrap_or_default(),
+            description: texts.next().unwrap_or_default(),
+            content: texts.collect(),
+        });
+        count += 1;
+    }
+
+    println!("Translated {} documents", count);
+    count
+}
+
+async fn request_translation(client: &Client, config: &TranslationConfig, source: &str, texts: &[&str]) -> Result<Vec<String>, reqwest::Error> {
+    let mut body = json!({
+        "q": texts,
+        "source": source,
+        "target": config.target,
+        "format": "text",
+    });
+    if let Some(api_key) = &config.api_key {
+        body["api_key"] = json!(api_key);
+    }
+
+    let response = client.post(config.endpoint.as_str())
+        .json(&body)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok(response.json::<TranslateResponse>().await?.translated_text)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use serde_json::Value;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use super::*;
+
+    // LibreTranslate stand-in - upper cases each segment, but drops one from requests
+    // holding "miscount" and stalls past the client timeout on those holding "stall".
+    async fn mock_service() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(respond(stream));
+            }
+        });
+        format!("http://{}/translate", address)
+    }
+
+    async fn respond(mut stream: TcpStream) {
+        let mut request: Vec<u8> = Vec::new();
+        let mut buffer = [0; 4096];
+        let body = loop {
+            let read = stream.read(&mut buffer).await.unwrap();
+            if read == 0 {
+                return;
+            }
+            request.extend_from_slice(&buffer[..read]);
+            let text = String::from_utf8_lossy(&request);
+            let Some((head, body)) = text.split_once("\r\n\r\n") else { continue };
+            let length = head.lines()
+                .find_map(|line| line.to_lowercase().strip_prefix("content-length:").map(|value| value.trim().parse::<usize>().unwrap()))
+                .unwrap_or(0);
+            if body.len() >= length {
+                break body.to_string();
+            }
+        };
+
+        let request: Value = serde_json::from_str(&body).unwrap();
+        let mut texts: Vec<String> = request["q"].as_array().unwrap().iter()
+            .map(|text| text.as_str().unwrap().to_uppercase())
+            .collect();
+        if texts.iter().any(|text| text.contains("STALL")) {
+            tokio::time::sleep(Duration::from_secs(2)).await;
+        }
+        if texts.iter().any(|text| text.contains("MISCOUNT")) {
+            texts.pop();
+        }
+
+        let body = json!({ "translatedText": texts }).to_string();
+        let response = format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+        let _ = stream.write_all(response.as_bytes()).await;
+    }
+
+    fn document(language: &str, title: &str, content: &[&str]) -> Document {
+        let mut document = Document::default();
+        document.url = format!("http://example.org/{}", title);
+        document.title = title.to_string();
+        document.description = String::from("description");
+        document.content = content.iter().map(|text| text.to_string()).collect();
+        document.language = Some(language.to_string());
+        document
+    }
+
+    fn config(endpoint: String, batch_size: usize) -> TranslationConfig {
+        TranslationConfig { enabled: true, endpoint, timeout_ms: 500, batch_size, ..Default::default() }
+    }
+
+    #[tokio::test]
+    async fn translates_documents_not_in_the_target_language() {
+        let config = config(mock_service().await, 32);
+        let mut documents = [document("fr", "bonjour", &["le monde"]), document("en", "hello", &["world"])];
+
+        assert_eq!(translate_documents(&mut documents, &config).await, 1);
+        let translation = documents[0].translation.as_ref().unwrap();
+        assert_eq!(translation.language, "en");
+        assert_eq!(translation.title, "BONJOUR");
+        assert_eq!(translation.description, "DESCRIPTION");
+        assert_eq!(translation.content, vec![String::from("LE MONDE")]);
+        assert!(documents[1].translation.is_none());
+    }
+
+    #[tokio::test]
+    async fn keeps_documents_untranslated_on_a_wrong_segment_count() {
+        let config = config(mock_service().await, 32);
+        let mut documents = [document("fr", "bonjour", &["miscount"])];
+
+        assert_eq!(translate_documents(&mut documents, &config).await, 0);
+        assert!(documents[0].translation.is_none());
+    }
+
+    #[tokio::test]
+    async fn keeps_documents_untranslated_on_a_timeout() {
+        let config = config(mock_service().await, 32);
+        let mut documents = [document("fr", "bonjour", &["stall"])];
+
+        assert_eq!(translate_documents(&mut documents, &config).await, 0);
+        assert!(documents[0].translation.is_none());
+    }
+
+    #[tokio::test]
+    async fn drops_a_document_when_any_of_its_batches_fails() {
+        // Two segments a batch - the German document's second batch fails, its first
+        // and the French document's batches come back.
+        let config = config(mock_service().await, 2);
+        let mut documents = [document("de", "hallo", &["welt", "miscount"]), document("fr", "bonjour", &["le monde", "encore"])];
+
+        assert_eq!(translate_documents(&mut documents, &config).await, 1);
+        assert!(documents[0].translation.is_none());
+        assert_eq!(documents[1].translation.as_ref().unwrap().content, vec![String::from("LE MONDE"), String::from("ENCORE")]);
+    }
+}
@@ -0,0 +1,667 @@
+// Module writes the indices as segments (see segment.rs), which are never changed once
+// written. Each commit adds a segment of the documents added since the last, and marks
+// the documents it deletes or replaces in the tombstones of the older segments holding
+// them. Each commit is a file listing the live segments, their tombstones and the
+// document store written with them, checksummed and written through a temporary file,
+// so a commit is seen whole or not at all. The last few generations are kept, to fall
+// back to should the newest fail verification. Segments of similar size are merged in
+// the background, dropping their deleted documents. Every path is under the directory
+// of the indices written, one per collection.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, MutexGuard};
+use std::thread;
+use serde::{Deserialize, Serialize};
+use crate::analysis::AnalysisConfig;
+use crate::index::{index_documents, migrate_legacy_index, Indexer, DTERM_FILE, INVERTED_FILE};
+use crate::parser::Document;
+use crate::segment::{encode_index, write_file, write_segment, Segment, SegmentFile, SegmentKind, FORMAT_VERSION};
+use crate::shared::{IndexSnapshot, SharedIndices};
+use crate::store::{DocId, DocumentStore};
+
+
+// Directories of the segments and the commits, under that of the indices.
+const SEGMENT_DIR: &str = "segments";
+const COMMIT_DIR: &str = "commits";
+// Generations kept to fall back to, the newest included.
+const KEEP_GENERATIONS: usize = 3;
+// Index types each segment holds, and the name of their file.
+pub const SEGMENT_FILES: [(SegmentKind, &str); 3] = [
+    (SegmentKind::TermIndex, "dterm"),
+    (SegmentKind::InvertedIndex, "inverted"),
+    (SegmentKind::BTreeIndex, "btree"),
+];
+// Segments merged at once, and the ratio between the sizes of successive tiers.
+const MERGE_FACTOR: usize = 4;
+// Documents held in memory before they are written out as a segment, short of a commit.
+const MAX_BUFFERED_DOCS: usize = 1000;
+// Share of a segment's documents deleted before it is rewritten without them.
+const MAX_DELETED_RATIO: f64 = 0.5;
+
+fn segment_path(dir: &str, id: u64, name: &str) -> String {
+    format!("{}/{}/{}-{}.seg", dir, SEGMENT_DIR, id, name)
+}
+
+fn create_segment_dir(dir: &str) -> Result<(), String> {
+    let segment_dir = format!("{}/{}", dir, SEGMENT_DIR);
+    if !Path::new(&segment_dir).exists() {
+        fs::create_dir_all(&segment_dir).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// Documents deleted from a segment, a bit for each of its documents in ID order.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Tombstones {
+    bits: Vec<u64>,
+}
+
+impl Tombstones {
+    fn new(len: usize) -> Self {
+        Tombstones { bits: vec![0; len.div_ceil(64)] }
+    }
+
+    fn insert(&mut self, ordinal: usize) {
+        self.bits[ordinal / 64] |= 1 << (ordinal % 64);
+    }
+
+    pub fn contains(&self, ordinal: usize) -> bool {
+        self.bits.get(ordinal / 64).is_some_and(|word| word & (1 << (ordinal % 64)) != 0)
+    }
+
+    fn len(&self) -> usize {
+        self.bits.iter().map(|word| word.count_ones() as usize).sum()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SegmentMeta {
+    pub id: u64,
+    // IDs of the segment's documents in ascending order, which the tombstones follow.
+    pub doc_ids: Vec<DocId>,
+    pub deleted: Tombstones,
+}
+
+impl SegmentMeta {
+    fn new(id: u64, doc_ids: Vec<DocId>) -> Self {
+        let deleted = Tombstones::new(doc_ids.len());
+        SegmentMeta { id, doc_ids, deleted }
+    }
+
+    pub fn path(&self, dir: &str, name: &str) -> String {
+        segment_path(dir, self.id, name)
+    }
+
+    fn live_docs(&self) -> usize {
+        self.doc_ids.len() - self.deleted.len()
+    }
+
+    // Marks the document deleted where the segment holds it.
+    fn delete(&mut self, id: DocId) {
+        if let Ok(ordinal) = self.doc_ids.binary_search(&id) {
+            self.deleted.insert(ordinal);
+        }
+    }
+
+    pub fn deleted_ids(&self) -> HashSet<DocId> {
+        self.doc_ids.iter().enumerate()
+            .filter(|(ordinal, _)| self.deleted.contains(*ordinal))
+            .map(|(_, id)| *id)
+            .collect()
+    }
+
+    // The segment's index of each type, in the order of SEGMENT_FILES.
+    pub fn open(&self, dir: &str) -> Result<[Segment; 3], String> {
+        let open = |name: &str| {
+            let path = self.path(dir, name);
+            let file = SegmentFile::open(&path)?.ok_or_else(|| format!("{} is not a segment", path))?;
+            Segment::new(file, &path)
+        };
+        Ok([open("dterm")?, open("inverted")?, open("btree")?])
+    }
+
+    fn remove_files(&self, dir: &str) {
+        for (_, name) in SEGMENT_FILES {
+            let _ = fs::remove_file(self.path(dir, name));
+        }
+    }
+}
+
+// The segments making up the indices at a point in time.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Commit {
+    // Counts the commits made, merges included.
+    pub generation: u64,
+    // ID the next segment written is given.
+    next_segment: u64,
+    // Analyser settings the terms of every segment were produced with.
+    pub analysis: AnalysisConfig,
+    pub segments: Vec<SegmentMeta>,
+    // ID of the document store written with the commit, among those of the segments.
+    store: u64,
+}
+
+impl Commit {
+    fn path(dir: &str, generation: u64) -> String {
+        format!("{}/{}/{}.commit", dir, COMMIT_DIR, generation)
+    }
+
+    pub fn store_path(&self, dir: &str) -> String {
+        segment_path(dir, self.store, "docs")
+    }
+
+    // Generations with a commit file, newest first.
+    pub fn generations(dir: &str) -> Vec<u64> {
+        let mut generations: Vec<u64> = fs::read_dir(format!("{}/{}", dir, COMMIT_DIR)).into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|entry| entry.file_name().to_str()?.strip_suffix(".commit")?.parse().ok())
+            .collect();
+        generations.sort_unstable_by(|a, b| b.cmp(a));
+        generations
+    }
+
+    // The newest commit whose file verifies.
+    pub fn read(dir: &str) -> Result<Self, String> {
+        let mut error = format!("{}/{} holds no commits", dir, COMMIT_DIR);
+        for generation in Self::generations(dir) {
+            match Self::read_generation(dir, generation) {
+                Ok(commit) => return Ok(commit),
+                Err(e) => error = e,
+            }
+        }
+        Err(error)
+    }
+
+    // Commit files are the format version, a CRC-32 of the commit, then the commit.
+    pub fn read_generation(dir: &str, generation: u64) -> Result<Self, String> {
+        let path = Self::path(dir, generation);
+        let bytes = fs::read(&path).map_err(|e| format!("{}: {}", path, e))?;
+        let word = |at: usize| bytes.get(at..at + 4).map_or(0, |word| u32::from_le_bytes(word.try_into().unwrap_or_default()));
+
+        let version = word(0);
+        if version != FORMAT_VERSION {
+            return Err(format!("{} was written by index format version {}, this build reads version {} - fill the indices again to rebuild it",
+                path, version, FORMAT_VERSION));
+        }
+        let commit = bytes.get(8..).unwrap_or_default();
+        if crc32fast::hash(commit) != word(4) {
+            return Err(format!("{} is corrupt - it does not match its checksum", path));
+        }
+        bincode::deserialize(commit).map_err(|e| format!("{} is corrupt: {}", path, e))
+    }
+
+    fn write(&self, dir: &str) -> Result<(), String> {
+        let commit_dir = format!("{}/{}", dir, COMMIT_DIR);
+        if !Path::new(&commit_dir).exists() {
+            fs::create_dir_all(&commit_dir).map_err(|e| e.to_string())?;
+        }
+        let commit = bincode::serialize(self).map_err(|e| e.to_string())?;
+        let path = Self::path(dir, self.generation);
+        write_file(&path, &[&FORMAT_VERSION.to_le_bytes(), &crc32fast::hash(&commit).to_le_bytes(), &commit])
+            .map_err(|e| format!("{}: {}", path, e))
+    }
+
+    // Renames the commit file of a generation that failed verification out of the
+    // way, so that writers carry on from the one before. It is kept for inspection.
+    fn set_aside(dir: &str, generation: u64) {
+        let path = Self::path(dir, generation);
+        if let Err(e) = fs::rename(&path, format!("{}.corrupt", path)) {
+            eprintln!("Could not set aside {}: {}", path, e);
+        }
+    }
+}
+
+// Writes the indexed documents out as the segment of the indices with the ID.
+fn write_segment_files(shared: &SharedIndices, id: u64, indices: [Indexer; 3], doc_ids: Vec<DocId>) -> Result<SegmentMeta, String> {
+    create_segment_dir(&shared.dir)?;
+    for (index, (_, name)) in indices.iter().zip(SEGMENT_FILES) {
+        let (kind, sections) = encode_index(index);
+        let path = segment_path(&shared.dir, id, name);
+        write_segment(&path, kind, shared.analyser.config(), sections).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    Ok(SegmentMeta::new(id, doc_ids))
+}
+
+// Opens the commit's segments and store, verifying their checksums, then writes the
+// commit and swaps the snapshot in for queries to read. A commit whose files fail
+// verification is not written, and the last one stays current.
+fn publish(commit: &Commit, indices: &SharedIndices) -> Result<(), String> {
+    let snapshot = IndexSnapshot::open(commit, indices)?;
+    commit.write(&indices.dir)?;
+    println!("Committed generation {} of {} - {} segments of {} documents", commit.generation, indices.dir, commit.segments.len(), snapshot.store.len());
+    indices.replace(snapshot);
+    Ok(())
+}
+
+// Deletes the commits of generations older than those kept, then the segment and
+// store files no kept commit refers to - those replaced by merges and fills, and any
+// left by a writer that failed before committing. Called under the write lock, and
+// not while a merge is writing its segment, as that is not committed yet.
+fn remove_unreferenced(dir: &str) {
+    let generations = Commit::generations(dir);
+    for generation in generations.iter().skip(KEEP_GENERATIONS) {
+        let _ = fs::remove_file(Commit::path(dir, *generation));
+    }
+
+    let mut live: HashSet<u64> = HashSet::new();
+    for generation in generations.iter().take(KEEP_GENERATIONS) {
+        // Files of a commit that cannot be read are not known, so none are deleted.
+        let Ok(commit) = Commit::read_generation(dir, *generation) else { return };
+        live.extend(commit.segments.iter().map(|segment| segment.id));
+        live.insert(commit.store);
+    }
+
+    let Ok(entries) = fs::read_dir(format!("{}/{}", dir, SEGMENT_DIR)) else { return };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let id = name.split('-').next().and_then(|id| id.parse::<u64>().ok());
+        if !id.is_some_and(|id| live.contains(&id)) || name.ends_with(".tmp") {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+// Bytes of the segment, store and commit files of the indices in the directory, those
+// of the older generations kept included.
+pub fn disk_usage(dir: &str) -> u64 {
+    [SEGMENT_DIR, COMMIT_DIR].iter()
+        .flat_map(|name| fs::read_dir(format!("{}/{}", dir, name)).into_iter().flatten().flatten())
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+// Adds and deletes documents, which queries see once the writer commits. Only one
+// writer of the indices is open at a time - opening another waits for it to commit or
+// be dropped.
+pub struct IndexWriter<'a> {
+    _lock: MutexGuard<'a, ()>,
+    indices: &'a Arc<SharedIndices>,
+    commit: Commit,
+    // Documents added since the last segment was written, and the segments written
+    // since the last commit.
+    buffered: BTreeMap<DocId, Document>,
+    written: Vec<SegmentMeta>,
+}
+
+impl<'a> IndexWriter<'a> {
+    // Opens the committed indices to add to.
+    pub fn open(indices: &'a Arc<SharedIndices>) -> Result<Self, String> {
+        let lock = indices.write_lock();
+        let commit = Commit::read(&indices.dir)?;
+        if commit.analysis != *indices.analyser.config() {
+            return Err(format!("Generation {} was analysed with other settings - fill the indices again to rebuild it", commit.generation));
+        }
+        Ok(IndexWriter { _lock: lock, indices, commit, buffered: BTreeMap::new(), written: Vec::new() })
+    }
+
+    // Opens empty indices, which replace the committed ones once the writer commits.
+    pub fn create(indices: &'a Arc<SharedIndices>) -> Self {
+        let lock = indices.write_lock();
+        let previous = Commit::read(&indices.dir).unwrap_or_default();
+        let commit = Commit {
+            generation: previous.generation,
+            next_segment: previous.next_segment,
+            analysis: indices.analyser.config().clone(),
+            segments: Vec::new(),
+            store: previous.store,
+        };
+        IndexWriter { _lock: lock, indices, commit, buffered: BTreeMap::new(), written: Vec::new() }
+    }
+
+    // Adds the document under its ID in the store, replacing any version of it
+    // already indexed.
+    pub fn add(&mut self, id: DocId, document: Document) -> Result<(), String> {
+        self.delete(id);
+        self.buffered.insert(id, document);
+        if self.buffered.len() >= MAX_BUFFERED_DOCS {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn delete(&mut self, id: DocId) {
+        self.buffered.remove(&id);
+        for segment in self.commit.segments.iter_mut().chain(self.written.iter_mut()) {
+            segment.delete(id);
+        }
+    }
+
+    // Writes the buffered documents out as a new segment.
+    fn flush(&mut self) -> Result<(), String> {
+        if self.buffered.is_empty() {
+            return Ok(());
+        }
+        let documents = std::mem::take(&mut self.buffered);
+        let id = self.commit.next_segment;
+        self.commit.next_segment += 1;
+        let indexed = index_documents(documents.iter().map(|(id, document)| (*id, document)), &self.indices.analyser);
+        let segment = write_segment_files(self.indices, id, indexed, documents.into_keys().collect())?;
+        self.written.push(segment);
+        Ok(())
+    }
+
+    // Writes out the buffered documents and the store the writer's documents are kept
+    // in, then commits and swaps the new snapshot in - queries see every change of the
+    // commit, or none of it.
+    pub fn commit(mut self, store: &DocumentStore) -> Result<(), String> {
+        self.flush()?;
+        let indices = self.indices;
+        let mut commit = std::mem::take(&mut self.commit);
+        commit.segments.append(&mut self.written);
+        commit.segments.retain(|segment| segment.live_docs() > 0);
+        commit.store = commit.next_segment;
+        commit.next_segment += 1;
+        create_segment_dir(&indices.dir)?;
+        let store_path = commit.store_path(&indices.dir);
+        store.write(&store_path, indices.analyser.config()).map_err(|e| format!("{}: {}", store_path, e))?;
+        commit.generation += 1;
+
+        publish(&commit, indices)?;
+        if !indices.merging.load(Ordering::SeqCst) {
+            remove_unreferenced(&indices.dir);
+        }
+        drop(self);
+        start_merge(indices);
+        Ok(())
+    }
+}
+
+// Size-tiered merge policy - segments are grouped into tiers by their number of live
+// documents, each tier MERGE_FACTOR times the size of the one below, and MERGE_FACTOR
+// segments of the smallest full tier are merged. Failing that, a segment mostly
+// deleted is rewritten on its own.
+fn merge_candidates(segments: &[SegmentMeta]) -> Option<Vec<SegmentMeta>> {
+    let mut tiers: BTreeMap<u32, Vec<&SegmentMeta>> = BTreeMap::new();
+    for segment in segments {
+        tiers.entry(segment.live_docs().max(1).ilog(MERGE_FACTOR)).or_default().push(segment);
+    }
+    if let Some(tier) = tiers.values().find(|tier| tier.len() >= MERGE_FACTOR) {
+        return Some(tier.iter().take(MERGE_FACTOR).map(|segment| (*segment).clone()).collect());
+    }
+
+    segments.iter()
+        .find(|segment| segment.deleted.len() as f64 > segment.doc_ids.len() as f64 * MAX_DELETED_RATIO)
+        .map(|segment| vec![segment.clone()])
+}
+
+// Merges segments in a background thread until the policy finds none to merge. Queries
+// and writers carry on meanwhile.
+fn start_merge(indices: &Arc<SharedIndices>) {
+    if indices.merging.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst).is_err() {
+        return;
+    }
+    let indices = indices.clone();
+    thread::spawn(move || {
+        loop {
+            match merge_once(&indices) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => {
+                    eprintln!("Merging segments failed: {}", e);
+                    break;
+                }
+            }
+        }
+        let _lock = indices.write_lock();
+        remove_unreferenced(&indices.dir);
+        indices.merging.store(false, Ordering::SeqCst);
+    });
+}
+
+// Merges one set of segments the policy picks, false where there were none.
+fn merge_once(indices: &Arc<SharedIndices>) -> Result<bool, String> {
+    // The segments are picked, and the merged segment's ID taken, under the write lock -
+    // the merge itself runs without it. The ID is reserved in a generation of its own,
+    // so that writers meanwhile carry on from it and committed files are never rewritten.
+    let (sources, id) = {
+        let _lock = indices.write_lock();
+        let mut commit = Commit::read(&indices.dir)?;
+        let Some(sources) = merge_candidates(&commit.segments) else { return Ok(false) };
+        let id = commit.next_segment;
+        commit.next_segment += 1;
+        commit.generation += 1;
+        commit.write(&indices.dir)?;
+        (sources, id)
+    };
+
+    println!("Merging {} segments of {} into segment {}", sources.len(), indices.dir, id);
+    let mut merged = [Indexer::TermIndex(Default::default()), Indexer::InvertedIndex(Default::default()), Indexer::BTreeIndex(Default::default())];
+    let mut doc_ids: BTreeSet<DocId> = BTreeSet::new();
+    for source in &sources {
+        let deleted = source.deleted_ids();
+        for (index, segment) in merged.iter_mut().zip(source.open(&indices.dir)?) {
+            index.absorb(segment.to_indexer(), |id| deleted.contains(&id));
+        }
+        doc_ids.extend(source.doc_ids.iter().filter(|id| !deleted.contains(id)));
+    }
+    // Terms found only in deleted documents leave the B-tree with their postings.
+    if let [_, Indexer::InvertedIndex(postings), Indexer::BTreeIndex(terms)] = &mut merged {
+        terms.retain(|term| postings.contains_key(term));
+    }
+    let mut segment = write_segment_files(indices, id, merged, doc_ids.into_iter().collect())?;
+
+    let _lock = indices.write_lock();
+    let mut commit = Commit::read(&indices.dir)?;
+    let source_ids: HashSet<u64> = sources.iter().map(|source| source.id).collect();
+    // Documents deleted from the segments while they were being merged. Those deleted
+    // before were left out, and may have a live version in another of the segments.
+    for source in &sources {
+        // A fill replaced the segments while they were being merged.
+        let Some(current) = commit.segments.iter().find(|segment| segment.id == source.id) else {
+            segment.remove_files(&indices.dir);
+            return Ok(false);
+        };
+        let deleted = source.deleted_ids();
+        for id in current.deleted_ids().difference(&deleted) {
+            segment.delete(*id);
+        }
+    }
+
+    commit.segments.retain(|segment| !source_ids.contains(&segment.id));
+    if segment.live_docs() > 0 {
+        commit.segments.push(segment);
+    } else {
+        segment.remove_files(&indices.dir);
+    }
+    commit.generation += 1;
+    publish(&commit, indices)?;
+    // The merged segment is committed, so the files it replaces can go once no kept
+    // generation refers to them.
+    remove_unreferenced(&indices.dir);
+    Ok(true)
+}
+
+// Indexes every document of the store anew, in a commit replacing every segment.
+pub fn rebuild_indices(store: &DocumentStore, indices: &Arc<SharedIndices>) -> Result<(), String> {
+    let mut writer = IndexWriter::create(indices);
+    for (id, document) in store.documents() {
+        writer.add(id, document)?;
+    }
+    writer.commit(store)
+}
+
+// Rebuilds the indices as segments from the document store where there is no commit
+// to read them from - indices filled before segments, whose files are deleted once
+// every one has migrated and the segments are committed - or where they were analysed
+// with other settings than the current ones.
+pub fn upgrade_indices(indices: &Arc<SharedIndices>) -> Result<(), String> {
+    let dir = &indices.dir;
+    let legacy_paths = [DTERM_FILE, INVERTED_FILE].map(|file| format!("{}/{}", dir, file));
+    let store = match Commit::read(dir) {
+        Ok(commit) if commit.analysis == *indices.analyser.config() => return Ok(()),
+        Ok(commit) => {
+            println!("Rebuilding the indices in {}, which were analysed with other settings", dir);
+            DocumentStore::open(&commit.store_path(dir))?
+        }
+        Err(_) if legacy_paths.iter().any(|path| Path::new(path).exists()) => {
+            let mut store = DocumentStore::default();
+            for path in &legacy_paths {
+                if Path::new(path).exists() {
+                    migrate_legacy_index(path, &mut store)?;
+                }
+            }
+            println!("Rebuilding the indices in {} as segments", dir);
+            store
+        }
+        // Commits that cannot be read are left to recover_indices.
+        Err(_) => return Ok(()),
+    };
+
+    rebuild_indices(&store, indices)?;
+    for path in &legacy_paths {
+        let _ = fs::remove_file(path);
+    }
+    Ok(())
+}
+
+// Opens the newest generation whose commit, segments and store verify, and swaps it
+// in. Each generation that fails is reported - its indices are rebuilt from its store
+// where that verifies, and otherwise it is set aside for the generation before.
+pub fn recover_indices(indices: &Arc<SharedIndices>) -> Result<(), String> {
+    let dir = &indices.dir;
+    let generations = Commit::generations(dir);
+    if generations.is_empty() {
+        return Err(format!("{}/{} holds no commits", dir, COMMIT_DIR));
+    }
+
+    for generation in generations {
+        let error = match Commit::read_generation(dir, generation) {
+            Ok(commit) => {
+                let error = match IndexSnapshot::open(&commit, indices) {
+                    Ok(snapshot) => {
+                        println!("Loaded generation {} of the indices in {} - {} documents", generation, dir, snapshot.store.len());
+                        indices.replace(snapshot);
+                        return Ok(());
+                    }
+                    Err(e) => e,
+                };
+                if let Ok(store) = DocumentStore::open(&commit.store_path(dir)) {
+                    eprintln!("Generation {} of the indices failed verification, rebuilding them from its document store: {}", generation, error);
+                    return rebuild_indices(&store, indices);
+                }
+                error
+            }
+            Err(e) => e,
+        };
+        eprintln!("Generation {} of the indices failed verification, falling back to the one before: {}", generation, error);
+        Commit::set_aside(dir, generation);
+    }
+    Err(format!("No generation of the indices in {} passed verification - fill them again", dir))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::Analyser;
+    use crate::rank::{get_bm25_rankings, ScoringConfig};
+    use super::*;
+
+    // Empty indices in a directory of their own. Merges are marked running, so that
+    // commits leave them to the test.
+    fn indices(name: &str) -> Arc<SharedIndices> {
+        let dir = std::env::temp_dir().join(format!("writer-test-{}-{}", name, std::process::id())).to_string_lossy().into_owned();
+        let _ = fs::remove_dir_all(&dir);
+        let indices = Arc::new(SharedIndices::new(&dir, Analyser::from_config(&AnalysisConfig::default())));
+        indices.merging.store(true, Ordering::SeqCst);
+        indices
+    }
+
+    fn document(url: &str, text: &str) -> Document {
+        let mut document = Document::default();
+        document.url = url.to_string();
+        document.title = text.to_string();
+        document.content = vec![text.to_string()];
+        document
+    }
+
+    // Adds the documents to the store and indices in one commit, returning their IDs.
+    fn add(indices: &Arc<SharedIndices>, store: &mut DocumentStore, documents: &[(&str, &str)]) -> Vec<DocId> {
+        let mut writer = IndexWriter::open(indices).unwrap_or_else(|_| IndexWriter::create(indices));
+        let ids = documents.iter().map(|(url, text)| {
+            let id = store.insert(document(url, text));
+            writer.add(id, document(url, text)).unwrap();
+            id
+        }).collect();
+        writer.commit(store).unwrap();
+        ids
+    }
+
+    fn delete(indices: &Arc<SharedIndices>, store: &DocumentStore, id: DocId) {
+        let mut writer = IndexWriter::open(indices).unwrap();
+        writer.delete(id);
+        writer.commit(store).unwrap();
+    }
+
+    fn search(indices: &SharedIndices, query: &str) -> Vec<String> {
+        let snapshot = indices.snapshot().unwrap();
+        get_bm25_rankings(query.to_string(), None, None, &snapshot, &ScoringConfig::default()).unwrap()
+            .into_iter()
+            .map(|document| document.url)
+            .collect()
+    }
+
+    #[test]
+    fn hides_deleted_documents_from_search() {
+        let indices = indices("delete");
+        let mut store = DocumentStore::default();
+        let ids = add(&indices, &mut store, &[("https://example.com/a", "fourier series"), ("https://example.com/b", "fourier transform")]);
+        assert_eq!(search(&indices, "fourier").len(), 2);
+
+        // The store still holds the document, so only its tombstone hides it.
+        delete(&indices, &store, ids[0]);
+        assert_eq!(search(&indices, "fourier"), vec!["https://example.com/b"]);
+        assert!(!search(&indices, "series").contains(&String::from("https://example.com/a")));
+        let _ = fs::remove_dir_all(&indices.dir);
+    }
+
+    #[test]
+    fn tiered_merges_drop_deleted_documents() {
+        let indices = indices("merge");
+        let mut store = DocumentStore::default();
+        let mut ids: Vec<DocId> = Vec::new();
+        for segment in 0..MERGE_FACTOR {
+            let urls = [format!("https://example.com/{}/a", segment), format!("https://example.com/{}/b", segment)];
+            ids.extend(add(&indices, &mut store, &[(&urls[0], "fourier series"), (&urls[1], "heat equation")]));
+        }
+        delete(&indices, &store, ids[0]);
+        let before = Commit::read(&indices.dir).unwrap();
+        assert_eq!(before.segments.len(), MERGE_FACTOR);
+
+        assert!(merge_once(&indices).unwrap());
+        let after = Commit::read(&indices.dir).unwrap();
+        assert!(after.generation > before.generation);
+        assert_eq!(after.segments.len(), 1);
+        assert_eq!(after.segments[0].doc_ids, ids[1..].to_vec());
+        let found = search(&indices, "series");
+        assert_eq!(found.len(), 2 * MERGE_FACTOR - 1);
+        assert!(!found.contains(&String::from("https://example.com/0/a")));
+        // The generation merged from is kept as it was, to fall back to.
+        assert_eq!(Commit::read_generation(&indices.dir, before.generation).unwrap().segments.len(), MERGE_FACTOR);
+        assert!(!merge_once(&indices).unwrap());
+        let _ = fs::remove_dir_all(&indices.dir);
+    }
+
+    #[test]
+    fn recovers_the_generation_before_a_torn_commit() {
+        let indices = indices("torn");
+        let mut store = DocumentStore::default();
+        add(&indices, &mut store, &[("https://example.com/a", "fourier series")]);
+        add(&indices, &mut store, &[("https://example.com/b", "fourier transform")]);
+
+        // The newest commit is cut off part way through writing.
+        let generation = Commit::read(&indices.dir).unwrap().generation;
+        let path = Commit::path(&indices.dir, generation);
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+
+        let reopened = Arc::new(SharedIndices::new(&indices.dir, Analyser::from_config(&AnalysisConfig::default())));
+        recover_indices(&reopened).unwrap();
+        assert_eq!(search(&reopened, "fourier"), vec!["https://example.com/a"]);
+        assert_eq!(Commit::generations(&indices.dir)[0], generation - 1);
+        let _ = fs::remove_dir_all(&indices.dir);
+    }
+}